@@ -9,6 +9,7 @@ pub struct Scanner<'s> {
     curr: usize,
     global_curr: usize,
     line: u32,
+    file: FileId,
 }
 
 impl<'s> Scanner<'s> {
@@ -18,9 +19,15 @@ impl<'s> Scanner<'s> {
             curr: 0,
             global_curr: 0,
             line: 1,
+            file: FileId::default(),
         }
     }
 
+    /// Mark the tokens' spans as being in `file`.
+    pub fn with_file(self, file: FileId) -> Self {
+        Self { file, ..self }
+    }
+
     // TODO: make it a lazy iterator use std::iter::from_fn
     pub fn scan_tokens(mut self) -> Result<Vec<Token>, Vec<LexingError>> {
         let mut tokens = Vec::new();
@@ -41,6 +48,7 @@ impl<'s> Scanner<'s> {
             span: Span {
                 line_start: self.line,
                 line_end: self.line,
+                file: self.file,
                 ..Default::default()
             },
         });
@@ -87,10 +95,10 @@ impl<'s> Scanner<'s> {
                 c if c.is_ascii_digit() => Some(self.number()?),
                 c if c.is_alphabetic() || c == '_' => Some(self.identifier()?),
                 _ => {
-                    return Err(LexingError::new(
-                        self.make_span(),
-                        "Unexpected character.".into(),
-                    ));
+                    return Err(
+                        LexingError::new(self.make_span(), "Unexpected character.".into())
+                            .with_code(Code::UnexpectedCharacter),
+                    );
                 }
             };
             let span = self.make_span();
@@ -132,10 +140,10 @@ impl<'s> Scanner<'s> {
         }
 
         // Consume closing quote
-        self.advance_checked().ok_or(LexingError::new(
-            self.make_span(),
-            "Unterminated string.".into(),
-        ))?;
+        self.advance_checked().ok_or(
+            LexingError::new(self.make_span(), "Unterminated string.".into())
+                .with_code(Code::UnterminatedString),
+        )?;
 
         const QUOTE_WIDTH: usize = '"'.len_utf8();
         Ok(TokenType::String(
@@ -215,6 +223,7 @@ impl<'s> Scanner<'s> {
             end: self.global_curr,
             line_start: self.line,
             line_end: self.line,
+            file: self.file,
         }
     }
 }
@@ -222,7 +231,9 @@ impl<'s> Scanner<'s> {
 fn keyword(s: &str) -> Option<TokenType> {
     Some(match s {
         "and" => TokenType::And,
+        "break" => TokenType::Break,
         "class" => TokenType::Class,
+        "continue" => TokenType::Continue,
         "else" => TokenType::Else,
         "false" => TokenType::False,
         "for" => TokenType::For,
@@ -385,7 +396,7 @@ mod tests {
 
     #[test]
     fn test_idents() {
-        let source = "_hello123world _and2 or_ var return";
+        let source = "_hello123world _and2 or_ var return break continue";
         let scanner = Scanner::new(source);
         let tokens = scanner.scan_tokens().unwrap();
 
@@ -397,6 +408,8 @@ mod tests {
                 tok![id: "or_"],
                 tok![var],
                 tok![return],
+                tok![break],
+                tok![continue],
                 tok![EOF],
             ]
         ));
@@ -31,7 +31,9 @@ pub enum TokenType {
     Number(f64),
     // Keywords
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -74,7 +76,9 @@ impl TokenType {
             TokenType::LessEqual => "<=",
             TokenType::Identifier(ident) => ident.as_ref(),
             TokenType::And => "and",
+            TokenType::Break => "break",
             TokenType::Class => "class",
+            TokenType::Continue => "continue",
             TokenType::Else => "else",
             TokenType::False => "false",
             TokenType::Fun => "fun",
@@ -269,12 +273,24 @@ macro_rules! tok {
             span: report::Span { line_start: $line, line_end: $line, ..Default::default()} ,
         }
     };
+    [break, $line:expr] => {
+        $crate::tokens::Token {
+            ty: $crate::tokens::TokenType::Break,
+            span: report::Span { line_start: $line, line_end: $line, ..Default::default()} ,
+        }
+    };
     [class, $line:expr] => {
         $crate::tokens::Token {
             ty: $crate::tokens::TokenType::Class,
             span: report::Span { line_start: $line, line_end: $line, ..Default::default()} ,
         }
     };
+    [continue, $line:expr] => {
+        $crate::tokens::Token {
+            ty: $crate::tokens::TokenType::Continue,
+            span: report::Span { line_start: $line, line_end: $line, ..Default::default()} ,
+        }
+    };
     [else, $line:expr] => {
         $crate::tokens::Token {
             ty: $crate::tokens::TokenType::Else,
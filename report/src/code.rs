@@ -0,0 +1,124 @@
+//! Stable identifiers for errors, for tools that match on them rather than on
+//! their messages. Codes are grouped by [`Kind`] in blocks of a hundred and
+//! never reused: a retired error keeps its number.
+
+use std::fmt;
+
+/// Which stage of running a script an error comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Lexing,
+    Parsing,
+    /// Checks on a parsed program before it runs, e.g. the resolver's.
+    Pass,
+    Runtime,
+    /// Anything else: I/O, corrupted bytecode, compiler limits.
+    Other,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Lexing => "lexing",
+            Kind::Parsing => "parsing",
+            Kind::Pass => "pass",
+            Kind::Runtime => "runtime",
+            Kind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Other,
+
+    Lexing,
+    UnexpectedCharacter,
+    UnterminatedString,
+
+    Syntax,
+    Expected,
+    InvalidAssignmentTarget,
+    LimitExceeded,
+
+    ReturnFromTopLevel,
+    ReturnValueFromInitializer,
+    LoopControlOutsideLoop,
+    ThisOutsideClass,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+    InheritFromSelf,
+
+    Runtime,
+    UndefinedVariable,
+    UndefinedProperty,
+    InvalidOperand,
+    NotCallable,
+    Arity,
+    StackOverflow,
+    NotAnInstance,
+    InvalidSuperclass,
+    OutOfMemory,
+    OutOfBudget,
+    Interrupted,
+}
+
+impl Code {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::Other => "E0000",
+
+            Code::Lexing => "E0100",
+            Code::UnexpectedCharacter => "E0101",
+            Code::UnterminatedString => "E0102",
+
+            Code::Syntax => "E0200",
+            Code::Expected => "E0201",
+            Code::InvalidAssignmentTarget => "E0202",
+            Code::LimitExceeded => "E0203",
+
+            Code::ReturnFromTopLevel => "E0301",
+            Code::ReturnValueFromInitializer => "E0302",
+            Code::LoopControlOutsideLoop => "E0303",
+            Code::ThisOutsideClass => "E0304",
+            Code::SuperOutsideClass => "E0305",
+            Code::SuperWithoutSuperclass => "E0306",
+            Code::InheritFromSelf => "E0307",
+
+            Code::Runtime => "E0400",
+            Code::UndefinedVariable => "E0401",
+            Code::UndefinedProperty => "E0402",
+            Code::InvalidOperand => "E0403",
+            Code::NotCallable => "E0404",
+            Code::Arity => "E0405",
+            Code::StackOverflow => "E0406",
+            Code::NotAnInstance => "E0407",
+            Code::InvalidSuperclass => "E0408",
+            Code::OutOfMemory => "E0409",
+            Code::OutOfBudget => "E0410",
+            Code::Interrupted => "E0411",
+        }
+    }
+
+    pub fn kind(self) -> Kind {
+        match self.as_str().as_bytes()[2] {
+            b'1' => Kind::Lexing,
+            b'2' => Kind::Parsing,
+            b'3' => Kind::Pass,
+            b'4' => Kind::Runtime,
+            _ => Kind::Other,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
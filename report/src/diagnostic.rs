@@ -0,0 +1,52 @@
+//! Context an error can carry beyond its message and span, shown by the rich
+//! [`Format`](crate::Format).
+
+use std::fmt::Display;
+
+use crate::{Span, Spanned};
+
+/// A secondary span with what it has to do with the error, e.g. where a
+/// variable was declared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: Box<str>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations {
+    pub labels: Vec<Label>,
+    pub notes: Vec<Box<str>>,
+    pub help: Option<Box<str>>,
+}
+
+impl Annotations {
+    pub(crate) const EMPTY: &'static Annotations = &Annotations {
+        labels: Vec::new(),
+        notes: Vec::new(),
+        help: None,
+    };
+}
+
+/// Builder methods for errors that carry [`Annotations`].
+pub trait Annotate: Sized {
+    fn annotations_mut(&mut self) -> &mut Annotations;
+
+    fn with_label(mut self, spanned: impl Spanned, message: impl Display) -> Self {
+        self.annotations_mut().labels.push(Label {
+            span: spanned.span(),
+            message: message.to_string().into(),
+        });
+        self
+    }
+
+    fn with_note(mut self, note: impl Display) -> Self {
+        self.annotations_mut().notes.push(note.to_string().into());
+        self
+    }
+
+    fn with_help(mut self, help: impl Display) -> Self {
+        self.annotations_mut().help = Some(help.to_string().into());
+        self
+    }
+}
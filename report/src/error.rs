@@ -2,11 +2,13 @@ use std::process::{ExitCode, Termination};
 
 use thiserror::Error;
 
+pub mod halt;
 pub mod lexing;
 pub mod parsing;
 pub mod pass;
 pub mod runtime;
 
+pub use halt::Error as HaltError;
 pub use lexing::Error as LexingError;
 pub use parsing::Error as ParsingError;
 pub use pass::Error as PassError;
@@ -23,6 +25,8 @@ pub enum Error {
     #[error(transparent)]
     Runtime(#[from] RuntimeError),
     #[error(transparent)]
+    Halt(#[from] HaltError),
+    #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
@@ -30,7 +34,7 @@ impl Termination for Error {
     fn report(self) -> ExitCode {
         match self {
             Error::Parsing { .. } | Error::Lexing(_) | Error::Pass(_) => ExitCode::from(65),
-            Error::Runtime(_) => ExitCode::from(70),
+            Error::Runtime(_) | Error::Halt(_) => ExitCode::from(70),
             Error::Other(_) => ExitCode::FAILURE,
         }
     }
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use thiserror::Error;
+
+/// Execution stopped from outside the program, rather than by an error in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("Execution ran out of its budget of {0} steps.")]
+    OutOfBudget(u64),
+    #[error("Execution interrupted.")]
+    Interrupted,
+}
+
+/// Stops a running interpreter from any thread. Clones share the interpreter
+/// they stop.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Stop the current run at its next step, or the next run if none is
+    /// underway.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether an interrupt is pending, clearing it so it only stops one run.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
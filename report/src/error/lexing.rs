@@ -1,12 +1,13 @@
 use thiserror::Error;
 
-use crate::{Report, Span, Spanned};
+use crate::{Code, Report, Span, Spanned};
 
 #[derive(Debug, Error, Clone)]
 #[error("[line {}:{}] {}", (.span).line_start, .span.start, .message)]
 pub struct Error {
     pub span: Span,
     pub message: Box<str>,
+    pub code: Code,
 }
 
 impl Error {
@@ -14,14 +15,23 @@ impl Error {
         Self {
             span: spanned.span(),
             message,
+            code: Code::Lexing,
         }
     }
+
+    pub fn with_code(self, code: Code) -> Self {
+        Self { code, ..self }
+    }
 }
 
 impl Report for Error {
     fn report(&self, _source: &str, w: &mut dyn std::io::Write) {
         let _ = write!(w, "{}", self.message);
     }
+
+    fn code(&self) -> Code {
+        self.code
+    }
 }
 
 impl Spanned for Error {
@@ -2,7 +2,10 @@ use std::fmt::Display;
 
 use thiserror::Error;
 
-use crate::{Report, Span, Spanned};
+use crate::{
+    Code, Report, Span, Spanned,
+    diagnostic::{Annotate, Annotations},
+};
 
 #[derive(Debug, Error)]
 #[error("[line {}:{}] {message}", .span.line_start, .span.start)]
@@ -10,6 +13,9 @@ pub struct Error {
     pub span: Span,
     pub message: Box<str>,
     pub should_sync: bool,
+    pub code: Code,
+    /// Boxed, and only there once something is added, to keep the error small.
+    pub annotations: Option<Box<Annotations>>,
 }
 
 impl Error {
@@ -18,23 +24,34 @@ impl Error {
             span: spanned.span(),
             message: format!("{message}").into(),
             should_sync: true,
+            code: Code::Syntax,
+            annotations: None,
         }
     }
 
     pub fn custom_no_sync(spanned: impl Spanned, message: impl Display) -> Self {
         Self {
-            span: spanned.span(),
-            message: format!("{message}").into(),
             should_sync: false,
+            ..Self::custom(spanned, message)
         }
     }
 
     pub fn expected(spanned: impl Spanned, expected: impl Display, found: impl Display) -> Self {
-        Self {
-            span: spanned.span(),
-            message: format!("Expected '{}', found '{}'", expected, found).into(),
-            should_sync: true,
-        }
+        Self::custom(
+            spanned,
+            format_args!("Expected '{}', found '{}'", expected, found),
+        )
+        .with_code(Code::Expected)
+    }
+
+    pub fn with_code(self, code: Code) -> Self {
+        Self { code, ..self }
+    }
+}
+
+impl Annotate for Error {
+    fn annotations_mut(&mut self) -> &mut Annotations {
+        self.annotations.get_or_insert_default()
     }
 }
 
@@ -42,6 +59,14 @@ impl Report for Error {
     fn report(&self, _source: &str, w: &mut dyn std::io::Write) {
         let _ = write!(w, "{}", self.message);
     }
+
+    fn code(&self) -> Code {
+        self.code
+    }
+
+    fn annotations(&self) -> &Annotations {
+        self.annotations.as_deref().unwrap_or(Annotations::EMPTY)
+    }
 }
 
 impl Spanned for Error {
@@ -2,63 +2,117 @@ use std::fmt::Display;
 
 use thiserror::Error;
 
-use crate::{Report, Spanned, span::Span};
+use crate::{
+    Code, Report, Spanned,
+    diagnostic::{Annotate, Annotations},
+    span::Span,
+};
 
 #[derive(Debug, Error)]
 #[error("[line {}:{}] {message}", .span.line_start, .span.start)]
 pub struct Error {
     pub span: Span,
     pub message: Box<str>,
+    pub code: Code,
+    /// The calls in progress when the error happened, innermost first.
+    /// Empty until the backend fills it in.
+    pub trace: Vec<TraceFrame>,
+    /// Boxed, and only there once something is added, to keep the error small.
+    pub annotations: Option<Box<Annotations>>,
+}
+
+/// One active call in a runtime error's backtrace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    /// `None` for top-level code.
+    pub function: Option<Box<str>>,
+    pub line: u32,
+}
+
+impl Display for TraceFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "[line {}] in {name}()", self.line),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
 }
 
 impl Error {
-    pub fn custom(spanned: impl Spanned, message: impl Display) -> Self {
+    fn new(span: Span, code: Code, message: impl Into<Box<str>>) -> Self {
         Self {
-            span: spanned.span(),
-            message: format!("{message}").into(),
+            span,
+            message: message.into(),
+            code,
+            trace: Vec::new(),
+            annotations: None,
         }
     }
 
+    pub fn custom(spanned: impl Spanned, message: impl Display) -> Self {
+        Self::new(spanned.span(), Code::Runtime, message.to_string())
+    }
+
     pub fn with_token(spanned: impl Spanned, message: impl Display) -> Self {
-        Self {
-            span: spanned.span(),
-            message: format!("{message}").into(),
-        }
+        Self::new(spanned.span(), Code::Runtime, message.to_string())
     }
 
     pub fn undefined(spanned: impl Spanned) -> Self {
-        Self {
-            span: spanned.span(),
-            message: "Undefined variable.".into(),
-        }
+        Self::new(
+            spanned.span(),
+            Code::UndefinedVariable,
+            "Undefined variable.",
+        )
     }
 
     pub fn not_callable(span: Span) -> Self {
-        Self {
-            span,
-            message: "Object is not a callable.".into(),
-        }
+        Self::new(span, Code::NotCallable, "Object is not a callable.")
     }
 
     pub fn arity(span: Span, expected: u8, found: usize) -> Self {
-        Self {
+        Self::new(
             span,
-            message: format!("Expected {expected} arguments but found {found}").into(),
-        }
+            Code::Arity,
+            format!("Expected {expected} arguments but found {found}"),
+        )
+    }
+
+    pub fn stack_overflow(span: Span) -> Self {
+        Self::new(span, Code::StackOverflow, "Stack overflow.")
     }
 
     pub fn invalid_break_or_continue(spanned: impl Spanned) -> Self {
-        Self {
-            span: spanned.span(),
-            message: "Invalid control flow statement outside for/while loop.".into(),
-        }
+        Self::new(
+            spanned.span(),
+            Code::Runtime,
+            "Invalid control flow statement outside for/while loop.",
+        )
     }
 
     pub fn invalid_return(spanned: impl Spanned) -> Self {
-        Self {
-            span: spanned.span(),
-            message: "Invalid return statement function.".into(),
+        Self::new(
+            spanned.span(),
+            Code::Runtime,
+            "Invalid return statement function.",
+        )
+    }
+
+    pub fn with_code(self, code: Code) -> Self {
+        Self { code, ..self }
+    }
+
+    /// Attach the backtrace, unless a deeper call already did.
+    pub fn with_trace(mut self, trace: impl FnOnce() -> Vec<TraceFrame>) -> Self {
+        if self.trace.is_empty() {
+            self.trace = trace();
         }
+        self
+    }
+}
+
+impl Annotate for Error {
+    fn annotations_mut(&mut self) -> &mut Annotations {
+        self.annotations.get_or_insert_default()
     }
 }
 
@@ -66,6 +120,18 @@ impl Report for Error {
     fn report(&self, _source: &str, w: &mut dyn std::io::Write) {
         let _ = write!(w, "{}", self.message);
     }
+
+    fn code(&self) -> Code {
+        self.code
+    }
+
+    fn trace(&self) -> &[TraceFrame] {
+        &self.trace
+    }
+
+    fn annotations(&self) -> &Annotations {
+        self.annotations.as_deref().unwrap_or(Annotations::EMPTY)
+    }
 }
 
 impl Spanned for Error {
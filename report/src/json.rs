@@ -0,0 +1,159 @@
+//! The JSON format: one object per error and line, for tools to parse.
+//!
+//! ```json
+//! {"code":"E0401","file":"a.lox","help":null,"kind":"runtime","labels":[],
+//!  "message":"Undefined variable.","notes":[],
+//!  "span":{"column":7,"end":7,"end_column":8,"end_line":1,"line":1,"start":6},
+//!  "trace":[{"function":null,"line":1}]}
+//! ```
+//!
+//! `file` is the name the source was registered under, in angle brackets
+//! like `<repl 2>` for code that isn't from a file. `span` is `null` for
+//! errors about no code in particular (`kind` is then often `other`), and
+//! labels say which file their span is in. Offsets are in
+//! bytes; lines and columns are 1-based, columns count characters and
+//! `end_column` is just past the span. Columns are `null` when the source
+//! isn't at hand, e.g. for bytecode whose source is gone.
+
+use std::io::{self, Write};
+
+use serde_json::{Value, json};
+
+use crate::{FileId, SourceFile, SourceMap, Span, render::Diagnostic};
+
+/// Write `diagnostic`, as about `current` if it has no span.
+pub(crate) fn write(
+    w: &mut dyn Write,
+    sources: &SourceMap,
+    current: FileId,
+    diagnostic: &Diagnostic<'_>,
+) -> io::Result<()> {
+    let name = |file| sources.get(file).map(SourceFile::name);
+    let span = |span: Span| {
+        let file = sources.get(span.file).filter(|file| file.contains(span));
+        let column = |offset| file.map(|file| file.column(offset));
+        json!({
+            "start": span.start,
+            "end": span.end,
+            "line": span.line_start,
+            "column": column(span.start),
+            "end_line": span.line_end,
+            "end_column": column(span.end),
+        })
+    };
+    let annotations = diagnostic.annotations;
+    let object = json!({
+        "kind": diagnostic.code.kind().as_str(),
+        "code": diagnostic.code.as_str(),
+        "message": diagnostic.message,
+        "file": name(diagnostic.span.map_or(current, |span| span.file)),
+        "span": diagnostic.span.map(span),
+        "labels": annotations.labels.iter().map(|label| json!({
+            "message": &*label.message,
+            "file": name(label.span.file),
+            "span": span(label.span),
+        })).collect::<Value>(),
+        "notes": annotations.notes.iter().map(|note| &**note).collect::<Vec<_>>(),
+        "help": annotations.help.as_deref(),
+        "trace": diagnostic.trace.iter().map(|frame| json!({
+            "function": frame.function.as_deref(),
+            "line": frame.line,
+        })).collect::<Value>(),
+    });
+    writeln!(w, "{object}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Code,
+        diagnostic::{Annotations, Label},
+    };
+
+    fn written(sources: &SourceMap, diagnostic: Diagnostic<'_>) -> Value {
+        let mut out = Vec::new();
+        write(&mut out, sources, FileId::default(), &diagnostic).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert_eq!(line.matches('\n').count(), 1, "not one line: {line}");
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn locates_spans_in_their_files() {
+        let mut sources = SourceMap::new();
+        let lib = sources.add("lib.lox", "var é = 1;\n");
+        let main = sources.add("main.lox", "print 1;\nprint é + nil;\n");
+        let start = sources[main].text().rfind('+').unwrap();
+        let span = Span {
+            start,
+            end: start + 1,
+            line_start: 2,
+            line_end: 2,
+            file: main,
+        };
+        let annotations = Annotations {
+            labels: vec![Label {
+                span: Span {
+                    start: 4,
+                    end: 6,
+                    file: lib,
+                    ..Span::default()
+                },
+                message: "declared here".into(),
+            }],
+            ..Annotations::default()
+        };
+        let diagnostic = Diagnostic {
+            message: "invalid operand",
+            code: Code::InvalidOperand,
+            span: Some(span),
+            annotations: &annotations,
+            trace: &[],
+        };
+        let object = written(&sources, diagnostic);
+        assert_eq!(object["kind"], "runtime");
+        assert_eq!(object["code"], "E0403");
+        assert_eq!(object["file"], "main.lox");
+        assert_eq!(
+            object["span"],
+            json!({"start": 18, "end": 19, "line": 2, "column": 9, "end_line": 2, "end_column": 10})
+        );
+        let label = &object["labels"][0];
+        assert_eq!(label["file"], "lib.lox");
+        assert_eq!(
+            (&label["span"]["column"], &label["span"]["end_column"]),
+            (&5.into(), &6.into())
+        );
+    }
+
+    #[test]
+    fn leaves_out_what_it_does_not_know() {
+        let mut sources = SourceMap::new();
+        sources.add("stale.lox", "");
+        let diagnostic = Diagnostic {
+            message: "Stack overflow.",
+            code: Code::StackOverflow,
+            span: Some(Span {
+                line_start: 3,
+                line_end: 3,
+                ..Span::default()
+            }),
+            annotations: Annotations::EMPTY,
+            trace: &[],
+        };
+        let object = written(&sources, diagnostic);
+        assert_eq!(object["file"], "stale.lox");
+        assert_eq!(object["span"]["line"], 3);
+        assert_eq!(object["span"]["column"], Value::Null);
+
+        let unspanned = Diagnostic {
+            message: "Compilation failed",
+            code: Code::Other,
+            span: None,
+            annotations: Annotations::EMPTY,
+            trace: &[],
+        };
+        assert_eq!(written(&SourceMap::new(), unspanned)["file"], Value::Null);
+    }
+}
@@ -1,7 +1,14 @@
+pub mod code;
+pub mod diagnostic;
 pub mod error;
+mod json;
+mod render;
 pub mod reporter;
+pub mod source_map;
 pub mod span;
 
+pub use code::{Code, Kind};
 pub use error::Error;
 pub use reporter::*;
+pub use source_map::{SourceFile, SourceMap};
 pub use span::*;
@@ -0,0 +1,296 @@
+//! The rich, rustc-style rendering of an error: the source lines it's about,
+//! with its span underlined in `^` and its labels' spans in `-`.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use crate::{Code, SourceFile, Span, diagnostic::Annotations, error::runtime::TraceFrame};
+
+/// ANSI styles, when there's a terminal to show them.
+#[derive(Clone, Copy)]
+struct Palette {
+    color: bool,
+}
+
+impl Palette {
+    const ERROR: &str = "\x1b[1;31m";
+    const ACCENT: &str = "\x1b[1;34m";
+    const BOLD: &str = "\x1b[1m";
+    const RESET: &str = "\x1b[0m";
+
+    fn paint(self, style: &str, text: impl Display) -> String {
+        if self.color {
+            format!("{style}{text}{}", Self::RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Everything the rich format shows for one error.
+pub(crate) struct Diagnostic<'a> {
+    pub message: &'a str,
+    pub code: Code,
+    pub span: Option<Span>,
+    pub annotations: &'a Annotations,
+    pub trace: &'a [TraceFrame],
+}
+
+/// A span's part on one line, in characters.
+struct Underline<'a> {
+    column: usize,
+    width: usize,
+    primary: bool,
+    /// Only on the span's last line.
+    message: Option<&'a str>,
+}
+
+/// Underline `span` on each line of `file` it covers.
+fn underline<'a>(
+    file: &SourceFile,
+    span: Span,
+    primary: bool,
+    message: Option<&'a str>,
+    rows: &mut BTreeMap<u32, Vec<Underline<'a>>>,
+) {
+    let first = file.line_of(span.start);
+    let last = file.line_of(span.end.saturating_sub(1).max(span.start));
+    for line in first..=last {
+        let (start, text) = (file.line_start(line), file.line(line));
+        let from = span.start.max(start) - start;
+        let to = (span.end.min(start + text.len()) - start).max(from);
+        rows.entry(line).or_default().push(Underline {
+            column: text[..from].chars().count(),
+            width: text[from..to].chars().count().max(1),
+            primary,
+            message: message.filter(|_| line == last),
+        });
+    }
+}
+
+/// Render `diagnostic`, showing the lines of `file` it points into, if it's
+/// at hand.
+pub(crate) fn render(
+    w: &mut dyn Write,
+    file: Option<&SourceFile>,
+    diagnostic: &Diagnostic<'_>,
+    color: bool,
+) -> io::Result<()> {
+    let p = Palette { color };
+    writeln!(
+        w,
+        "{}{}",
+        p.paint(Palette::ERROR, "error"),
+        p.paint(Palette::BOLD, format_args!(": {}", diagnostic.message))
+    )?;
+    let Some(span) = diagnostic.span else {
+        return Ok(());
+    };
+
+    let mut rows = BTreeMap::new();
+    if let Some(file) = file.filter(|file| file.contains(span)) {
+        underline(file, span, true, None, &mut rows);
+        for label in &diagnostic.annotations.labels {
+            if label.span.file == span.file && file.contains(label.span) {
+                underline(file, label.span, false, Some(&label.message), &mut rows);
+            }
+        }
+    }
+    let width = rows
+        .keys()
+        .next_back()
+        .map_or(span.line_start, |&line| line)
+        .to_string()
+        .len();
+    let gutter = |w: &mut dyn Write, number: &str| {
+        write!(
+            w,
+            "{}",
+            p.paint(Palette::ACCENT, format_args!("{number:>width$} |"))
+        )
+    };
+
+    let location = match file {
+        Some(file) => file.location(span),
+        None => format!("line {}", span.line_start),
+    };
+    writeln!(
+        w,
+        "{:width$}{} {location}",
+        "",
+        p.paint(Palette::ACCENT, "-->")
+    )?;
+    if !rows.is_empty() {
+        gutter(w, "")?;
+        writeln!(w)?;
+    }
+    if let Some(file) = file {
+        let mut previous = None;
+        for (&line, underlines) in &mut rows {
+            if previous.is_some_and(|previous| line > previous + 1) {
+                writeln!(w, "{}", p.paint(Palette::ACCENT, "..."))?;
+            }
+            previous = Some(line);
+            gutter(w, &line.to_string())?;
+            writeln!(w, " {}", file.line(line))?;
+            underlines.sort_by_key(|underline| !underline.primary);
+            for underline in underlines.iter() {
+                let (style, marker) = match underline.primary {
+                    true => (Palette::ERROR, "^"),
+                    false => (Palette::ACCENT, "-"),
+                };
+                let mut mark = marker.repeat(underline.width);
+                if let Some(message) = underline.message {
+                    mark = format!("{mark} {message}");
+                }
+                gutter(w, "")?;
+                writeln!(
+                    w,
+                    " {:col$}{}",
+                    "",
+                    p.paint(style, mark),
+                    col = underline.column
+                )?;
+            }
+        }
+    }
+
+    let annotations = diagnostic.annotations;
+    if annotations.notes.is_empty() && annotations.help.is_none() && diagnostic.trace.is_empty() {
+        return Ok(());
+    }
+    if !rows.is_empty() {
+        gutter(w, "")?;
+        writeln!(w)?;
+    }
+    let note = |w: &mut dyn Write, kind: &str, text: &str| {
+        let kind = p.paint(Palette::BOLD, kind);
+        writeln!(
+            w,
+            "{:width$} {} {kind}: {text}",
+            "",
+            p.paint(Palette::ACCENT, "=")
+        )
+    };
+    for text in &annotations.notes {
+        note(w, "note", text)?;
+    }
+    if let Some(help) = &annotations.help {
+        note(w, "help", help)?;
+    }
+    if !diagnostic.trace.is_empty() {
+        note(w, "note", "call stack:")?;
+        for frame in diagnostic.trace {
+            writeln!(w, "{:width$}         {frame}", "")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SourceMap, diagnostic::Label};
+
+    fn span(source: &str, text: &str, line: u32) -> Span {
+        let start = source.find(text).unwrap();
+        let end = start + text.len();
+        Span {
+            start,
+            end,
+            line_start: line,
+            line_end: line + text.matches('\n').count() as u32,
+            ..Span::default()
+        }
+    }
+
+    fn rendered(source: &str, diagnostic: Diagnostic<'_>) -> String {
+        let mut sources = SourceMap::new();
+        let file = sources.add("main.lox", source);
+        let mut out = Vec::new();
+        render(&mut out, sources.get(file), &diagnostic, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn underlines_the_span_and_labels() {
+        let source = "var answer = 42;\n\nprint answer + nil;\n";
+        let annotations = Annotations {
+            labels: vec![Label {
+                span: span(source, "answer", 1),
+                message: "variable declared here".into(),
+            }],
+            notes: vec!["operands must be two numbers or two strings".into()],
+            help: Some("compare with `nil` instead".into()),
+        };
+        let diagnostic = Diagnostic {
+            message: "Invalid operand.",
+            code: Code::InvalidOperand,
+            span: Some(span(source, "+", 3)),
+            annotations: &annotations,
+            trace: &[],
+        };
+        let expected = "\
+error: Invalid operand.
+ --> main.lox:3:14
+  |
+1 | var answer = 42;
+  |     ------ variable declared here
+...
+3 | print answer + nil;
+  |              ^
+  |
+  = note: operands must be two numbers or two strings
+  = help: compare with `nil` instead
+";
+        assert_eq!(rendered(source, diagnostic), expected);
+    }
+
+    #[test]
+    fn spans_several_lines() {
+        let source = "print \"one\ntwo\" + 1;";
+        let diagnostic = Diagnostic {
+            message: "Invalid operand.",
+            code: Code::InvalidOperand,
+            span: Some(span(source, "\"one\ntwo\"", 1)),
+            annotations: Annotations::EMPTY,
+            trace: &[TraceFrame {
+                function: None,
+                line: 2,
+            }],
+        };
+        let expected = "\
+error: Invalid operand.
+ --> main.lox:1:7
+  |
+1 | print \"one
+  |       ^^^^
+2 | two\" + 1;
+  | ^^^^
+  |
+  = note: call stack:
+          [line 2] in script
+";
+        assert_eq!(rendered(source, diagnostic), expected);
+    }
+
+    #[test]
+    fn falls_back_to_the_line_without_source() {
+        let diagnostic = Diagnostic {
+            message: "Stack overflow.",
+            code: Code::StackOverflow,
+            span: Some(Span {
+                line_start: 12,
+                line_end: 12,
+                ..Span::default()
+            }),
+            annotations: Annotations::EMPTY,
+            trace: &[],
+        };
+        assert_eq!(
+            rendered("", diagnostic),
+            "error: Stack overflow.\n  --> main.lox:12\n"
+        );
+    }
+}
@@ -1,32 +1,153 @@
-use std::io::Write;
+use std::borrow::Cow;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::str::FromStr;
 
-use crate::Spanned;
-use crate::error::{LexingError, ParsingError, RuntimeError};
+use crate::diagnostic::Annotations;
+use crate::error::{HaltError, LexingError, ParsingError, RuntimeError, runtime::TraceFrame};
+use crate::render::{self, Diagnostic};
+use crate::{Code, FileId, SourceFile, SourceMap, Span, Spanned, json};
 
 pub trait Report: Spanned {
     fn report(&self, source: &str, w: &mut dyn Write);
+
+    fn code(&self) -> Code;
+
+    /// Calls in progress when the error happened, innermost first.
+    fn trace(&self) -> &[TraceFrame] {
+        &[]
+    }
+
+    fn annotations(&self) -> &Annotations {
+        Annotations::EMPTY
+    }
+}
+
+/// How a [`Reporter`] writes errors out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One line per error, `[line N] Error 'text': message`, as the test
+    /// expectations spell them.
+    Plain,
+    /// The source lines with the error underlined, labels, notes and help.
+    Rich { color: bool },
+    /// One JSON object per error and line, for editors and CI.
+    Json,
+}
+
+impl Format {
+    /// Rich, in color on a terminal unless `NO_COLOR` is set.
+    pub fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Format::Rich {
+            color: std::io::stderr().is_terminal() && !no_color,
+        }
+    }
+}
+
+/// `plain`, `rich` or `json`, as given to `--error-format`.
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "rich" => Ok(Format::detect()),
+            "json" => Ok(Format::Json),
+            _ => Err(()),
+        }
+    }
 }
 
 pub struct Reporter<'s, 'w> {
-    src: &'s str,
+    sources: Cow<'s, SourceMap>,
+    /// The file being run, which errors without a span are about.
+    file: FileId,
     err: &'w mut dyn Write,
+    format: Format,
 }
 
 impl<'s, 'w> Reporter<'s, 'w> {
-    pub fn new(src: &'s str, err: &'w mut dyn Write) -> Self {
-        Self { src, err }
+    /// Report errors in `src` alone, as `<input>` until
+    /// [named](Self::with_path) otherwise, and [plain](Format::Plain) until
+    /// [told](Self::with_format) otherwise.
+    pub fn new(src: &str, err: &'w mut dyn Write) -> Self {
+        let mut sources = SourceMap::new();
+        let file = sources.add("<input>", src);
+        Self {
+            sources: Cow::Owned(sources),
+            file,
+            err,
+            format: Format::Plain,
+        }
+    }
+
+    /// Report errors in any of `sources`, while running `file`, in plain
+    /// format until [told](Self::with_format) otherwise.
+    pub fn with_sources(sources: &'s SourceMap, file: FileId, err: &'w mut dyn Write) -> Self {
+        Self {
+            sources: Cow::Borrowed(sources),
+            file,
+            err,
+            format: Format::Plain,
+        }
+    }
+
+    pub fn with_format(self, format: Format) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Name the file being run after `path`.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        let name = path.display().to_string();
+        self.sources.to_mut().rename(self.file, name);
+        self
+    }
+
+    /// The file being run, for spans in the code compiled from it.
+    pub fn file(&self) -> FileId {
+        self.file
     }
 
     pub fn report(&mut self, error: &impl Report) {
+        if self.format == Format::Plain {
+            return self.report_plain(error);
+        }
+        let mut message = Vec::new();
+        error.report(text(&self.sources, error.span()), &mut message);
+        self.render(&Diagnostic {
+            message: &String::from_utf8_lossy(&message),
+            code: error.code(),
+            span: Some(error.span()),
+            annotations: error.annotations(),
+            trace: error.trace(),
+        });
+    }
+
+    fn render(&mut self, diagnostic: &Diagnostic<'_>) {
+        let _ = match self.format {
+            Format::Plain => unreachable!("plain errors aren't rendered"),
+            Format::Rich { color } => {
+                let file = diagnostic.span.and_then(|span| self.sources.get(span.file));
+                render::render(self.err, file, diagnostic, color)
+            }
+            Format::Json => json::write(self.err, &self.sources, self.file, diagnostic),
+        };
+    }
+
+    fn report_plain(&mut self, error: &impl Report) {
         let span = error.span();
         let _ = write!(
             self.err,
             "[line {:>4}] Error '{}': ",
             span.line_start,
-            span.slice(self.src)
+            span.slice(text(&self.sources, span))
         );
-        error.report(self.src, &mut *self.err);
+        error.report(text(&self.sources, span), &mut *self.err);
         let _ = writeln!(self.err);
+        for frame in error.trace() {
+            let _ = writeln!(self.err, "{frame}");
+        }
     }
 
     pub fn report_unspanned(&mut self, error: &anyhow::Error) {
@@ -37,9 +158,27 @@ impl<'s, 'w> Reporter<'s, 'w> {
                 self.report(e);
             } else if let Some(e) = cause.downcast_ref::<RuntimeError>() {
                 self.report(e);
+            } else if self.format != Format::Plain {
+                let code = match cause.downcast_ref::<HaltError>() {
+                    Some(HaltError::OutOfBudget(_)) => Code::OutOfBudget,
+                    Some(HaltError::Interrupted) => Code::Interrupted,
+                    None => Code::Other,
+                };
+                self.render(&Diagnostic {
+                    message: &cause.to_string(),
+                    code,
+                    span: None,
+                    annotations: Annotations::EMPTY,
+                    trace: &[],
+                });
             } else {
                 let _ = writeln!(self.err, "Error: {cause}");
             }
         }
     }
 }
+
+/// The text `span` is in, or nothing if its file isn't known.
+fn text(sources: &SourceMap, span: Span) -> &str {
+    sources.get(span.file).map_or("", SourceFile::text)
+}
@@ -0,0 +1,157 @@
+//! The sources errors can point into: script files, REPL entries, `-c`
+//! snippets. Spans say which with their [`FileId`].
+
+use std::ops::Index;
+
+use crate::{FileId, Span};
+
+/// One registered source: its name, its text and where its lines start.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    name: Box<str>,
+    text: Box<str>,
+    /// Byte offset of each line, the first at 0.
+    lines: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(name: Box<str>, text: Box<str>) -> Self {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { name, text, lines }
+    }
+
+    /// A path, or a name in angle brackets like `<repl 3>` for code that
+    /// isn't from a file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The 1-based line holding `offset`.
+    pub fn line_of(&self, offset: usize) -> u32 {
+        self.lines.partition_point(|&start| start <= offset) as u32
+    }
+
+    /// The 1-based column, in characters, `offset` is at.
+    pub fn column(&self, offset: usize) -> usize {
+        let line = self.line_of(offset);
+        self.text[self.line_start(line)..offset].chars().count() + 1
+    }
+
+    pub(crate) fn line_start(&self, line: u32) -> usize {
+        self.lines[line as usize - 1]
+    }
+
+    /// The text of `line`, without its line break.
+    pub(crate) fn line(&self, line: u32) -> &str {
+        let end = self
+            .lines
+            .get(line as usize)
+            .map_or(self.text.len(), |next| next - 1);
+        self.text[self.line_start(line)..end].trim_end_matches('\r')
+    }
+
+    /// Whether `span` points into this text, rather than into a different
+    /// version of it or none (spans from bytecode only know their line).
+    pub fn contains(&self, span: Span) -> bool {
+        span.start <= span.end
+            && self.text.is_char_boundary(span.start)
+            && self.text.is_char_boundary(span.end)
+            && self.line_of(span.start) == span.line_start
+    }
+
+    /// Where `span` starts, as `name:line:column`, or `name:line` when this
+    /// isn't the text it points into.
+    pub fn location(&self, span: Span) -> String {
+        match self.contains(span) {
+            true => format!(
+                "{}:{}:{}",
+                self.name,
+                span.line_start,
+                self.column(span.start)
+            ),
+            false => format!("{}:{}", self.name, span.line_start),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a source under `name`, for spans with the returned id.
+    pub fn add(&mut self, name: impl Into<Box<str>>, text: impl Into<Box<str>>) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile::new(name.into(), text.into()));
+        id
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.0 as usize)
+    }
+
+    pub(crate) fn rename(&mut self, file: FileId, name: impl Into<Box<str>>) {
+        if let Some(file) = self.files.get_mut(file.0 as usize) {
+            file.name = name.into();
+        }
+    }
+
+    /// See [`SourceFile::location`]; `None` for spans in no registered file.
+    pub fn location(&self, span: Span) -> Option<String> {
+        Some(self.get(span.file)?.location(span))
+    }
+}
+
+impl Index<FileId> for SourceMap {
+    type Output = SourceFile;
+
+    fn index(&self, file: FileId) -> &SourceFile {
+        self.get(file)
+            .expect("file registered in another source map")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(file: FileId, start: usize, end: usize, line: u32) -> Span {
+        Span {
+            start,
+            end,
+            line_start: line,
+            line_end: line,
+            file,
+        }
+    }
+
+    #[test]
+    fn locates_spans_in_their_file() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.lox", "var a = 1;\nprint a;\n");
+        let repl = map.add("<repl 1>", "print b;");
+
+        assert_eq!(map.location(span(main, 17, 18, 2)).unwrap(), "main.lox:2:7");
+        assert_eq!(map.location(span(repl, 6, 7, 1)).unwrap(), "<repl 1>:1:7");
+        assert_eq!(map.get(repl).unwrap().text(), "print b;");
+    }
+
+    #[test]
+    fn falls_back_to_the_line() {
+        let mut map = SourceMap::new();
+        let file = map.add("stale.lox", "");
+        assert_eq!(map.location(span(file, 40, 41, 3)).unwrap(), "stale.lox:3");
+        assert_eq!(map.location(span(FileId(7), 0, 0, 1)), None);
+    }
+}
@@ -4,12 +4,20 @@ pub trait Spanned {
     fn span(&self) -> Span;
 }
 
+/// A file registered in a [`SourceMap`](crate::SourceMap). Spans of code
+/// that isn't from one, as with a lone source given to a
+/// [`Reporter`](crate::Reporter), are in the default file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(pub(crate) u32);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Span {
+    /// Byte offsets into the file's text.
     pub start: usize,
     pub end: usize,
     pub line_start: u32,
     pub line_end: u32,
+    pub file: FileId,
 }
 
 impl Span {
@@ -19,11 +27,14 @@ impl Span {
             end: self.end.max(other.end),
             line_start: self.line_start.min(other.line_start),
             line_end: self.line_end.max(other.line_end),
+            file: self.file,
         }
     }
 
+    /// The spanned text, or nothing if `s` isn't the source the span is
+    /// from, e.g. for code loaded from bytecode.
     pub fn slice<'s>(&self, s: &'s str) -> &'s str {
-        &s[self.start..self.end]
+        s.get(self.start..self.end).unwrap_or_default()
     }
 }
 
@@ -46,6 +57,7 @@ impl Default for Span {
             end: 0,
             line_start: 1,
             line_end: 1,
+            file: FileId::default(),
         }
     }
 }
@@ -5,7 +5,17 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Usage: rlox [--vm] [script | -c source]")]
+    #[error(
+        "Usage: rlox [--vm [--no-cache]] [script | -c source]\n       \
+         rlox compile script [-o output]\n       \
+         rlox run-bytecode file\n       \
+         rlox --disassemble file\n       \
+         rlox --debug script\n       \
+         rlox --profile script [--folded output]\n       \
+         rlox dap\n\n\
+         Options, anywhere on the command line:\n       \
+         --error-format plain|rich|json  how errors are shown (default: rich)"
+    )]
     Cli,
     #[error(transparent)]
     Interpreter(#[from] InterpreterError),
@@ -1,17 +1,65 @@
 use std::path::Path;
+use std::process::ExitCode;
 
+use report::Format;
 use rlox::error::Error;
 
-fn main() -> rlox::Result<()> {
-    let args: Vec<_> = std::env::args().collect();
-    match args.as_slice() {
-        [_] => tree_walk::run_prompt()?,
-        [_, flag] if flag == "--vm" => vm::run_prompt()?,
-        [_, script] => tree_walk::run_file(Path::new(script))?,
-        [_, flag, source] if flag == "-c" => tree_walk::run_source(source.clone())?,
-        [_, flag, script] if flag == "--vm" => vm::run_file(Path::new(script))?,
+fn main() -> rlox::Result<ExitCode> {
+    let mut args: Vec<_> = std::env::args().collect();
+    let format = match args.iter().position(|arg| arg == "--error-format") {
+        Some(i) if i + 1 < args.len() => {
+            let format = args[i + 1].parse().map_err(|()| Error::Cli)?;
+            args.drain(i..=i + 1);
+            format
+        }
+        Some(_) => return Err(Error::Cli),
+        None => Format::detect(),
+    };
+
+    match run(&args, format) {
+        // The errors are on stderr as JSON already; keep that stream parseable.
+        Err(Error::Interpreter(_)) if format == Format::Json => Ok(ExitCode::FAILURE),
+        result => result.map(|()| ExitCode::SUCCESS),
+    }
+}
+
+fn run(args: &[String], format: Format) -> rlox::Result<()> {
+    match args {
+        [_] => tree_walk::run_prompt(format)?,
+        [_, flag] if flag == "--vm" => vm::run_prompt(format)?,
+        // DAP `output` events carry plain text, so the adapter reports errors
+        // plain whatever `--error-format` asks for.
+        [_, cmd] if cmd == "dap" => vm::serve_dap()?,
+        [_, script] => tree_walk::run_file(Path::new(script), format)?,
+        [_, flag, source] if flag == "-c" => tree_walk::run_source(source.clone(), format)?,
+        [_, flag, script] if flag == "--vm" => vm::run_file_cached(Path::new(script), format)?,
+        [_, vm_flag, cache_flag, script] if vm_flag == "--vm" && cache_flag == "--no-cache" => {
+            vm::run_file(Path::new(script), format)?
+        }
+        [_, cmd, script] if cmd == "compile" => {
+            let script = Path::new(script);
+            vm::compile_file(script, &script.with_extension("loxc"), format)?
+        }
+        [_, cmd, script, flag, output] if cmd == "compile" && flag == "-o" => {
+            vm::compile_file(Path::new(script), Path::new(output), format)?
+        }
+        [_, cmd, bytecode] if cmd == "run-bytecode" => {
+            vm::run_bytecode_file(Path::new(bytecode), format)?
+        }
+        [_, flag, file] if flag == "--disassemble" => {
+            vm::disassemble_file(Path::new(file), format)?
+        }
+        [_, flag, script] if flag == "--debug" => vm::debug_file(Path::new(script), format)?,
+        [_, flag, script] if flag == "--profile" => {
+            vm::profile_file(Path::new(script), None, format)?
+        }
+        [_, flag, script, folded_flag, folded]
+            if flag == "--profile" && folded_flag == "--folded" =>
+        {
+            vm::profile_file(Path::new(script), Some(Path::new(folded)), format)?
+        }
         [_, vm_flag, c_flag, source] if vm_flag == "--vm" && c_flag == "-c" => {
-            vm::run_source(source.clone())?
+            vm::run_source(source.clone(), format)?
         }
         _ => return Err(Error::Cli),
     };
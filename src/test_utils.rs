@@ -1,6 +1,8 @@
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use report::Format;
+
 #[derive(Clone, Copy)]
 pub enum Backend {
     TreeWalk,
@@ -52,8 +54,10 @@ pub fn run_test(bin: &str, backend: Backend, path: &str) {
     let expectation = parse_expectations(&source);
 
     let mut cmd = Command::new(bin);
+    cmd.args(["--error-format", "plain"]);
     if matches!(backend, Backend::Vm) {
-        cmd.arg("--vm");
+        // Keep `__loxcache__` directories out of the test sources.
+        cmd.args(["--vm", "--no-cache"]);
     }
     cmd.arg(path);
 
@@ -134,8 +138,8 @@ pub fn run_examples(backend: Backend, dir: &str) {
 
     for path in &examples {
         let result = match backend {
-            Backend::TreeWalk => tree_walk::run_file(path),
-            Backend::Vm => vm::run_file(path),
+            Backend::TreeWalk => tree_walk::run_file(path, Format::Plain),
+            Backend::Vm => vm::run_file(path, Format::Plain),
         };
         result.unwrap_or_else(|e| panic!("{} failed: {e}", path.display()));
     }
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::{fs, time::SystemTime};
+
+const SCRIPT: &str = r#"
+fun make_counter() {
+  var n = 0;
+  fun inc() { n = n + 1; return n; }
+  return inc;
+}
+var c = make_counter();
+c();
+print c();
+"#;
+
+/// A scratch directory holding a copy of `SCRIPT` as `main.lox`.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rlox-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.lox"), SCRIPT).unwrap();
+    dir
+}
+
+fn rlox(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .output()
+        .expect("failed to run rlox")
+}
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path).unwrap().modified().unwrap()
+}
+
+#[test]
+fn compile_then_run_bytecode() {
+    let dir = scratch("compile");
+    let script = dir.join("main.lox");
+    let bytecode = dir.join("out.loxc");
+
+    let compiled = rlox(&["compile".as_ref(), &script, "-o".as_ref(), &bytecode]);
+    assert!(compiled.status.success(), "{compiled:?}");
+
+    let run = rlox(&["run-bytecode".as_ref(), &bytecode]);
+    assert!(run.status.success(), "{run:?}");
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "2\n");
+}
+
+#[test]
+fn run_bytecode_rejects_foreign_files() {
+    let dir = scratch("foreign");
+    let run = rlox(&["run-bytecode".as_ref(), &dir.join("main.lox")]);
+    assert!(!run.status.success());
+    assert!(String::from_utf8_lossy(&run.stderr).contains("not a .loxc file"));
+}
+
+#[test]
+fn cache_is_reused_until_source_changes() {
+    let dir = scratch("cache");
+    let script = dir.join("main.lox");
+    let cache = vm::cache_path(&script);
+
+    let first = rlox(&["--vm".as_ref(), &script]);
+    assert_eq!(String::from_utf8_lossy(&first.stdout), "2\n");
+    let cached_at = modified(&cache);
+
+    let second = rlox(&["--vm".as_ref(), &script]);
+    assert_eq!(String::from_utf8_lossy(&second.stdout), "2\n");
+    assert_eq!(modified(&cache), cached_at, "fresh cache was rewritten");
+
+    fs::write(&script, "print 3;").unwrap();
+    let edited = rlox(&["--vm".as_ref(), &script]);
+    assert_eq!(String::from_utf8_lossy(&edited.stdout), "3\n");
+    let rebuilt = fs::read(&cache).unwrap();
+    let (header, _) = vm::chunk::LoxcHeader::parse(&rebuilt).unwrap();
+    assert!(header.is_fresh_for("print 3;"));
+}
+
+#[test]
+fn no_cache_leaves_no_trace() {
+    let dir = scratch("no-cache");
+    let script = dir.join("main.lox");
+    let run = rlox(&["--vm".as_ref(), "--no-cache".as_ref(), &script]);
+    assert!(run.status.success(), "{run:?}");
+    assert!(!dir.join(vm::CACHE_DIR).exists());
+}
+
+#[test]
+fn unwritable_cache_does_not_change_the_run() {
+    let dir = scratch("read-only");
+    let script = dir.join("main.lox");
+    let expected = rlox(&["--vm".as_ref(), "--no-cache".as_ref(), &script]);
+    let same_run = |run: Output| {
+        assert_eq!(run.status.code(), expected.status.code());
+        assert_eq!(String::from_utf8_lossy(&run.stdout), "2\n");
+        assert_eq!(run.stderr, expected.stderr);
+    };
+
+    let mut permissions = fs::metadata(&dir).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&dir, permissions.clone()).unwrap();
+    let run = rlox(&["--vm".as_ref(), &script]);
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    fs::set_permissions(&dir, permissions).unwrap();
+    same_run(run);
+
+    // Permissions don't stop root, but a file where the cache directory
+    // should be stops anyone.
+    let _ = fs::remove_dir_all(dir.join(vm::CACHE_DIR));
+    fs::write(dir.join(vm::CACHE_DIR), "").unwrap();
+    same_run(rlox(&["--vm".as_ref(), &script]));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "left files behind");
+
+    // Nor does a directory where the cache file should be.
+    let cache = vm::cache_path(&script);
+    fs::remove_file(dir.join(vm::CACHE_DIR)).unwrap();
+    fs::create_dir_all(&cache).unwrap();
+    same_run(rlox(&["--vm".as_ref(), &script]));
+    let cache_dir = fs::read_dir(cache.parent().unwrap()).unwrap();
+    assert_eq!(cache_dir.count(), 1, "left files behind");
+}
+
+#[test]
+fn disassembles_source_and_bytecode_alike() {
+    let dir = scratch("disassemble");
+    let script = dir.join("main.lox");
+    let bytecode = dir.join("main.loxc");
+    assert!(rlox(&["compile".as_ref(), &script]).status.success());
+
+    let from_source = rlox(&["--disassemble".as_ref(), &script]);
+    let from_bytecode = rlox(&["--disassemble".as_ref(), &bytecode]);
+    assert!(from_source.status.success(), "{from_source:?}");
+    assert_eq!(from_source.stdout, from_bytecode.stdout);
+
+    let listing = String::from_utf8_lossy(&from_source.stdout);
+    assert!(listing.contains("== <fn make_counter/0> =="));
+    assert!(listing.contains("== <fn inc/0> =="));
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::Value;
+
+const BACKENDS: [&[&str]; 2] = [&[], &["--vm", "--no-cache"]];
+
+/// Write `source` to a scratch script, returning its path.
+fn script(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rlox-{}-{name}.lox", std::process::id()));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// The JSON diagnostics rlox writes running `script` with `backend`.
+fn diagnostics(backend: &[&str], script: &Path) -> Vec<Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(["--error-format", "json"])
+        .args(backend)
+        .arg(script)
+        .output()
+        .expect("failed to run rlox");
+    assert!(!output.status.success());
+    String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{e}: {line}")))
+        .collect()
+}
+
+#[test]
+fn runtime_errors_are_json_lines() {
+    let path = script("runtime", "var a = 1;\nprint a + nil;\n");
+    for backend in BACKENDS {
+        let [diagnostic] = &diagnostics(backend, &path)[..] else {
+            panic!("expected one diagnostic with {backend:?}");
+        };
+        assert_eq!(diagnostic["kind"], "runtime");
+        assert_eq!(diagnostic["code"], "E0403");
+        assert_eq!(diagnostic["file"], path.display().to_string());
+        let span = &diagnostic["span"];
+        assert_eq!((&span["start"], &span["end"]), (&19.into(), &20.into()));
+        assert_eq!((&span["line"], &span["column"]), (&2.into(), &9.into()));
+        assert_eq!(diagnostic["trace"][0]["line"], 2);
+    }
+}
+
+#[test]
+fn each_stage_has_its_kind() {
+    let cases = [
+        ("lexing", "print 1 # 2;", "E0101"),
+        ("parsing", "print (1;", "E0201"),
+        ("pass", "class A < A {}", "E0307"),
+    ];
+    for (kind, source, code) in cases {
+        let path = script(kind, source);
+        for backend in BACKENDS {
+            let diagnostics = diagnostics(backend, &path);
+            let [diagnostic] = &diagnostics[..] else {
+                panic!("expected one diagnostic with {backend:?}: {diagnostics:?}");
+            };
+            assert_eq!(diagnostic["kind"], kind, "{backend:?}");
+            assert_eq!(diagnostic["code"], code, "{backend:?}");
+        }
+    }
+}
@@ -16,7 +16,6 @@ mod assignment {
             local,
             prefix_operator,
             syntax,
-            #[ignore = "unimplemented: this"]
             to_this,
             undefined,
         ]
@@ -31,37 +30,35 @@ mod bool_ {
     rlox::lox_tests!("bool", [equality, not,]);
 }
 
-mod call {
+mod break_ {
     rlox::lox_tests!(
-        "call",
+        "break",
         [
-            bool,
-            nil,
-            num,
-            #[ignore = "unimplemented: classes"]
-            object,
-            string,
+            closes_upvalue,
+            r#for,
+            in_function_in_loop,
+            nested_loop,
+            outside_loop,
+            pops_locals,
+            r#while,
         ]
     );
 }
 
+mod call {
+    rlox::lox_tests!("call", [bool, nil, num, object, string,]);
+}
+
 mod class {
     rlox::lox_tests!(
         "class",
         [
-            #[ignore = "unimplemented: classes"]
             empty,
-            #[ignore = "unimplemented: classes"]
             inherit_self,
-            #[ignore = "unimplemented: classes"]
             inherited_method,
-            #[ignore = "unimplemented: classes"]
             local_inherit_other,
-            #[ignore = "unimplemented: classes"]
             local_inherit_self,
-            #[ignore = "unimplemented: classes"]
             local_reference_self,
-            #[ignore = "unimplemented: classes"]
             reference_self,
         ]
     );
@@ -75,7 +72,6 @@ mod closure {
             assign_to_shadowed_later,
             close_over_function_parameter,
             close_over_later_variable,
-            #[ignore = "unimplemented: classes"]
             close_over_method_parameter,
             closed_closure_in_function,
             nested_closure,
@@ -105,32 +101,35 @@ mod constructor {
     rlox::lox_tests!(
         "constructor",
         [
-            #[ignore = "unimplemented: classes"]
             arguments,
-            #[ignore = "unimplemented: classes"]
             call_init_early_return,
-            #[ignore = "unimplemented: classes"]
             call_init_explicitly,
-            #[ignore = "unimplemented: classes"]
             default,
-            #[ignore = "unimplemented: classes"]
             default_arguments,
-            #[ignore = "unimplemented: classes"]
             early_return,
-            #[ignore = "unimplemented: classes"]
             extra_arguments,
-            #[ignore = "unimplemented: classes"]
             init_not_method,
-            #[ignore = "unimplemented: classes"]
             missing_arguments,
-            #[ignore = "unimplemented: classes"]
             return_in_nested_function,
-            #[ignore = "unimplemented: classes"]
             return_value,
         ]
     );
 }
 
+mod continue_ {
+    rlox::lox_tests!(
+        "continue",
+        [
+            closes_upvalue,
+            for_increment,
+            in_function_in_loop,
+            outside_loop,
+            pops_locals,
+            r#while,
+        ]
+    );
+}
+
 mod expressions {
     rlox::lox_tests!(
         "expressions",
@@ -150,47 +149,26 @@ mod field {
     rlox::lox_tests!(
         "field",
         [
-            #[ignore = "unimplemented: classes"]
             call_function_field,
-            #[ignore = "unimplemented: classes"]
             call_nonfunction_field,
-            #[ignore = "unimplemented: classes"]
             get_and_set_method,
-            #[ignore = "unimplemented: classes"]
             get_on_bool,
-            #[ignore = "unimplemented: classes"]
             get_on_class,
-            #[ignore = "unimplemented: classes"]
             get_on_function,
-            #[ignore = "unimplemented: classes"]
             get_on_nil,
-            #[ignore = "unimplemented: classes"]
             get_on_num,
-            #[ignore = "unimplemented: classes"]
             get_on_string,
-            #[ignore = "unimplemented: classes"]
             many,
-            #[ignore = "unimplemented: classes"]
             method,
-            #[ignore = "unimplemented: classes"]
             method_binds_this,
-            #[ignore = "unimplemented: classes"]
             on_instance,
-            #[ignore = "unimplemented: classes"]
             set_evaluation_order,
-            #[ignore = "unimplemented: classes"]
             set_on_bool,
-            #[ignore = "unimplemented: classes"]
             set_on_class,
-            #[ignore = "unimplemented: classes"]
             set_on_function,
-            #[ignore = "unimplemented: classes"]
             set_on_nil,
-            #[ignore = "unimplemented: classes"]
             set_on_num,
-            #[ignore = "unimplemented: classes"]
             set_on_string,
-            #[ignore = "unimplemented: classes"]
             undefined,
         ]
     );
@@ -200,7 +178,6 @@ mod for_loop {
     rlox::lox_tests!(
         "for",
         [
-            #[ignore = "unimplemented: classes"]
             class_in_body,
             closure_in_body,
             fun_in_body,
@@ -243,9 +220,7 @@ mod if_stmt {
     rlox::lox_tests!(
         "if",
         [
-            #[ignore = "unimplemented: classes"]
             class_in_else,
-            #[ignore = "unimplemented: classes"]
             class_in_then,
             dangling_else,
             r#else,
@@ -263,19 +238,12 @@ mod inheritance {
     rlox::lox_tests!(
         "inheritance",
         [
-            #[ignore = "unimplemented: classes"]
             constructor,
-            #[ignore = "unimplemented: classes"]
             inherit_from_function,
-            #[ignore = "unimplemented: classes"]
             inherit_from_nil,
-            #[ignore = "unimplemented: classes"]
             inherit_from_number,
-            #[ignore = "unimplemented: classes"]
             inherit_methods,
-            #[ignore = "unimplemented: classes"]
             parenthesized_superclass,
-            #[ignore = "unimplemented: classes"]
             set_fields_from_base_class,
         ]
     );
@@ -296,6 +264,7 @@ mod limit {
             too_many_locals,
             #[ignore = "VM-specific"]
             too_many_upvalues,
+            wide_constants,
         ]
     );
 }
@@ -308,23 +277,14 @@ mod method {
     rlox::lox_tests!(
         "method",
         [
-            #[ignore = "unimplemented: classes"]
             arity,
-            #[ignore = "unimplemented: classes"]
             empty_block,
-            #[ignore = "unimplemented: classes"]
             extra_arguments,
-            #[ignore = "unimplemented: classes"]
             missing_arguments,
-            #[ignore = "unimplemented: classes"]
             not_found,
-            #[ignore = "unimplemented: classes"]
             print_bound_method,
-            #[ignore = "unimplemented: classes"]
             refer_to_name,
-            #[ignore = "unimplemented: classes"]
             too_many_arguments,
-            #[ignore = "unimplemented: classes"]
             too_many_parameters,
         ]
     );
@@ -363,9 +323,7 @@ mod operator {
             divide_nonnum_num,
             divide_num_nonnum,
             equals,
-            #[ignore = "unimplemented: classes"]
             equals_class,
-            #[ignore = "unimplemented: classes"]
             equals_method,
             #[ignore = "behavior: no operand type-check for comparisons"]
             greater_nonnum_num,
@@ -389,7 +347,6 @@ mod operator {
             negate,
             negate_nonnum,
             not,
-            #[ignore = "unimplemented: classes"]
             not_class,
             not_equals,
             subtract,
@@ -419,7 +376,6 @@ mod print_ {
 
 mod regression {
     #[test]
-    #[ignore = "unimplemented: classes"]
     fn regression_394() {
         rlox::test_utils::run_test(
             env!("CARGO_BIN_EXE_rlox"),
@@ -447,7 +403,6 @@ mod return_stmt {
             after_while,
             at_top_level,
             in_function,
-            #[ignore = "unimplemented: classes"]
             in_method,
             return_nil_if_no_value,
         ]
@@ -465,45 +420,25 @@ mod super_ {
     rlox::lox_tests!(
         "super",
         [
-            #[ignore = "unimplemented: super"]
             bound_method,
-            #[ignore = "unimplemented: super"]
             call_other_method,
-            #[ignore = "unimplemented: super"]
             call_same_method,
-            #[ignore = "unimplemented: super"]
             closure,
-            #[ignore = "unimplemented: super"]
             constructor,
-            #[ignore = "unimplemented: super"]
             extra_arguments,
-            #[ignore = "unimplemented: super"]
             indirectly_inherited,
-            #[ignore = "unimplemented: super"]
             missing_arguments,
-            #[ignore = "unimplemented: super"]
             no_superclass_bind,
-            #[ignore = "unimplemented: super"]
             no_superclass_call,
-            #[ignore = "unimplemented: super"]
             no_superclass_method,
-            #[ignore = "unimplemented: super"]
             parenthesized,
-            #[ignore = "unimplemented: super"]
             reassign_superclass,
-            #[ignore = "unimplemented: super"]
             super_at_top_level,
-            #[ignore = "unimplemented: super"]
             super_in_closure_in_inherited_method,
-            #[ignore = "unimplemented: super"]
             super_in_inherited_method,
-            #[ignore = "unimplemented: super"]
             super_in_top_level_function,
-            #[ignore = "unimplemented: super"]
             super_without_dot,
-            #[ignore = "unimplemented: super"]
             super_without_name,
-            #[ignore = "unimplemented: super"]
             this_in_superclass_method,
         ]
     );
@@ -513,17 +448,11 @@ mod this_ {
     rlox::lox_tests!(
         "this",
         [
-            #[ignore = "unimplemented: this"]
             closure,
-            #[ignore = "unimplemented: this"]
             nested_class,
-            #[ignore = "unimplemented: this"]
             nested_closure,
-            #[ignore = "unimplemented: this"]
             this_at_top_level,
-            #[ignore = "unimplemented: this"]
             this_in_method,
-            #[ignore = "unimplemented: this"]
             this_in_top_level_function,
         ]
     );
@@ -542,7 +471,6 @@ mod variable {
             early_bound,
             in_middle_of_block,
             in_nested_block,
-            #[ignore = "unimplemented: classes"]
             local_from_method,
             redeclare_global,
             redefine_global,
@@ -550,6 +478,8 @@ mod variable {
             shadow_and_local,
             shadow_global,
             shadow_local,
+            // lorax-specific shadowing test.
+            shadow_parameter,
             undefined_global,
             undefined_local,
             uninitialized,
@@ -559,7 +489,6 @@ mod variable {
             #[ignore = "behavior: resolver doesn't detect self-referencing initializer"]
             use_local_in_initializer,
             use_nil_as_var,
-            #[ignore = "unimplemented: this"]
             use_this_as_var,
         ]
     );
@@ -569,7 +498,6 @@ mod while_loop {
     rlox::lox_tests!(
         "while",
         [
-            #[ignore = "unimplemented: classes"]
             class_in_body,
             closure_in_body,
             fun_in_body,
@@ -6,7 +6,7 @@ use std::{
 
 use anyhow::Context;
 use lexer::Scanner;
-use report::{Error, Reporter};
+use report::{Error, FileId, Format, Reporter, SourceMap};
 
 use crate::{
     parsing::{ast::AstArena, *},
@@ -18,21 +18,40 @@ mod parsing;
 mod passes;
 pub mod runtime;
 
-pub fn run_file(path: &Path) -> Result<(), Error> {
+pub fn run_file(path: &Path, format: Format) -> Result<(), Error> {
     let source = fs::read_to_string(path)
         .with_context(|| format!("could not read source file {}", path.display()))?;
-    run_source(source)
+    let mut sources = SourceMap::new();
+    let file = sources.add(path.display().to_string(), source);
+    run(
+        &sources,
+        file,
+        &mut Interpreter::new(),
+        &mut AstArena::default(),
+        format,
+    )
 }
 
-pub fn run_source(source: String) -> Result<(), Error> {
-    run(source, &mut Interpreter::new(), &mut AstArena::default())
+pub fn run_source(source: String, format: Format) -> Result<(), Error> {
+    let mut sources = SourceMap::new();
+    let file = sources.add("<input>", source);
+    run(
+        &sources,
+        file,
+        &mut Interpreter::new(),
+        &mut AstArena::default(),
+        format,
+    )
 }
 
-pub fn run_prompt() -> Result<(), Error> {
+pub fn run_prompt(format: Format) -> Result<(), Error> {
     let mut buf_reader = BufReader::new(io::stdin());
     let mut ast_arena = AstArena::default();
     let mut interpreter = Interpreter::new();
-    loop {
+    // Every entry stays registered: functions declared in one are called,
+    // and can fail, in later ones.
+    let mut sources = SourceMap::new();
+    for entry in 1.. {
         print!("> ");
         io::stdout().flush().context("could not flush stdout")?;
 
@@ -43,19 +62,23 @@ pub fn run_prompt() -> Result<(), Error> {
         if read == 0 {
             break;
         }
-        let _ = run(line, &mut interpreter, &mut ast_arena);
+        let file = sources.add(format!("<repl {entry}>"), line);
+        let _ = run(&sources, file, &mut interpreter, &mut ast_arena, format);
     }
     Ok(())
 }
 
 fn run(
-    source: String,
+    sources: &SourceMap,
+    file: FileId,
     interpreter: &mut Interpreter,
     ast_arena: &mut AstArena,
+    format: Format,
 ) -> Result<(), Error> {
     let mut err = std::io::stderr();
-    let mut reporter = Reporter::new(&source, &mut err);
-    let tokens = Scanner::new(&source)
+    let mut reporter = Reporter::with_sources(sources, file, &mut err).with_format(format);
+    let tokens = Scanner::new(sources[file].text())
+        .with_file(file)
         .scan_tokens()
         .inspect_err(|errs| errs.iter().for_each(|e| reporter.report(e)))?;
 
@@ -63,11 +86,17 @@ fn run(
         .parse()
         .inspect_err(|errs| errs.iter().for_each(|e| reporter.report(e)))?;
 
-    Resolver::new(interpreter, ast_arena).resolve(&program);
+    Resolver::new(interpreter, ast_arena)
+        .resolve_program(&program)
+        .inspect_err(|errs| errs.iter().for_each(|e| reporter.report(e)))?;
 
     interpreter
         .interpret(program, ast_arena)
-        .inspect_err(|e| reporter.report(e))?;
+        .inspect_err(|e| match e {
+            Error::Runtime(e) => reporter.report(e),
+            Error::Halt(e) => reporter.report_unspanned(&(*e).into()),
+            e => unreachable!("interpreting only fails at runtime, not with {e}"),
+        })?;
 
     Ok(())
 }
@@ -20,6 +20,10 @@ pub enum Expr {
     Variable(ExprVariable),
     Assign(ExprAssign),
     Logical(ExprLogical),
+    Get(ExprGet),
+    Set(ExprSet),
+    This(ExprThis),
+    Super(ExprSuper),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -75,6 +79,30 @@ pub struct ExprLogical {
     pub right: ExprId,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprGet {
+    pub object: ExprId,
+    pub name: Token,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprSet {
+    pub object: ExprId,
+    pub name: Token,
+    pub value: ExprId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprThis {
+    pub keyword: Token,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprSuper {
+    pub keyword: Token,
+    pub method: Token,
+}
+
 impl ExprRef<'_> {
     #[cfg(test)]
     pub fn polish_notation(&self) -> String {
@@ -100,6 +128,10 @@ impl Spanned for ExprRef<'_> {
             Expr::Variable(_) => self.cast::<ExprVariable>().span(),
             Expr::Assign(_) => self.cast::<ExprAssign>().span(),
             Expr::Logical(_) => self.cast::<ExprLogical>().span(),
+            Expr::Get(_) => self.cast::<ExprGet>().span(),
+            Expr::Set(_) => self.cast::<ExprSet>().span(),
+            Expr::This(_) => self.cast::<ExprThis>().span(),
+            Expr::Super(_) => self.cast::<ExprSuper>().span(),
         }
     }
 }
@@ -126,6 +158,10 @@ impl_expr_node!(Expr::Unary, ExprUnary);
 impl_expr_node!(Expr::Variable, ExprVariable);
 impl_expr_node!(Expr::Assign, ExprAssign);
 impl_expr_node!(Expr::Logical, ExprLogical);
+impl_expr_node!(Expr::Get, ExprGet);
+impl_expr_node!(Expr::Set, ExprSet);
+impl_expr_node!(Expr::This, ExprThis);
+impl_expr_node!(Expr::Super, ExprSuper);
 
 impl Display for ExprRef<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -205,6 +241,29 @@ impl ExprVisitor for &mut StdPrinter<'_, '_> {
         write!(self.fmt, " {} ", expr.op.ty)?;
         right.accept(self)
     }
+
+    fn visit_get(self, expr: AstRef<ExprGet>) -> Self::T {
+        let arena = expr.arena();
+
+        arena.expr_ref(expr.object).accept(&mut *self)?;
+        write!(self.fmt, ".{}", expr.name.ty)
+    }
+
+    fn visit_set(self, expr: AstRef<ExprSet>) -> Self::T {
+        let arena = expr.arena();
+
+        arena.expr_ref(expr.object).accept(&mut *self)?;
+        write!(self.fmt, ".{} = ", expr.name.ty)?;
+        arena.expr_ref(expr.value).accept(self)
+    }
+
+    fn visit_this(self, _expr: AstRef<ExprThis>) -> Self::T {
+        write!(self.fmt, "this")
+    }
+
+    fn visit_super(self, expr: AstRef<ExprSuper>) -> Self::T {
+        write!(self.fmt, "super.{}", expr.method.ty)
+    }
 }
 
 pub struct AstPrinter<'a, 'f> {
@@ -288,6 +347,32 @@ impl ExprVisitor for &mut AstPrinter<'_, '_> {
         right.accept(&mut *self)?;
         write!(self.fmt, ")")
     }
+
+    fn visit_get(self, expr: AstRef<ExprGet>) -> Self::T {
+        let arena = expr.arena();
+
+        write!(self.fmt, "(get ")?;
+        arena.expr_ref(expr.object).accept(&mut *self)?;
+        write!(self.fmt, " {})", expr.name.ty)
+    }
+
+    fn visit_set(self, expr: AstRef<ExprSet>) -> Self::T {
+        let arena = expr.arena();
+
+        write!(self.fmt, "(set ")?;
+        arena.expr_ref(expr.object).accept(&mut *self)?;
+        write!(self.fmt, " {} ", expr.name.ty)?;
+        arena.expr_ref(expr.value).accept(&mut *self)?;
+        write!(self.fmt, ")")
+    }
+
+    fn visit_this(self, _expr: AstRef<ExprThis>) -> Self::T {
+        write!(self.fmt, "this")
+    }
+
+    fn visit_super(self, expr: AstRef<ExprSuper>) -> Self::T {
+        write!(self.fmt, "(super {})", expr.method.ty)
+    }
 }
 
 impl Spanned for AstRef<'_, ExprBinary> {
@@ -345,6 +430,33 @@ impl Spanned for AstRef<'_, ExprLogical> {
     }
 }
 
+impl Spanned for AstRef<'_, ExprGet> {
+    fn span(&self) -> Span {
+        let object = ExprRef::new(self.arena(), self.object);
+        object.span().join(&self.name.span)
+    }
+}
+
+impl Spanned for AstRef<'_, ExprSet> {
+    fn span(&self) -> Span {
+        let object = ExprRef::new(self.arena(), self.object);
+        let value = ExprRef::new(self.arena(), self.value);
+        object.span().join(&value.span())
+    }
+}
+
+impl Spanned for AstRef<'_, ExprThis> {
+    fn span(&self) -> Span {
+        self.keyword.span
+    }
+}
+
+impl Spanned for AstRef<'_, ExprSuper> {
+    fn span(&self) -> Span {
+        self.keyword.span.join(&self.method.span)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use lexer::tok;
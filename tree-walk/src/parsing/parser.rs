@@ -1,7 +1,7 @@
 use std::{collections::VecDeque, fmt::Display};
 
 use lexer::tokens::*;
-use report::error::ParsingError;
+use report::{Code, error::ParsingError};
 
 use super::{expr::*, stmt::*};
 use crate::{
@@ -11,16 +11,20 @@ use crate::{
 
 // program          => declaration* EOF ;
 //
-// declaration      => funDecl | varDecl | statement ;
+// declaration      => classDecl | funDecl | varDecl | statement ;
 // statement        => exprStmt
 //                  | ifStmt;
 //                  | printStmt
 //                  | returnStmt
 //                  | whileStmt
 //                  | forStmt
+//                  | breakStmt
+//                  | continueStmt
 //                  | block ;
 // block            => "{" declaration* "}" ;
 //
+// classDecl        => "class" IDENTIFIER ( "<" IDENTIFIER )?
+//                  "{" function* "}" ;
 // funDecl          => "fun" function ;
 // function         => IDENTIFIER "(" parameters? ")" block ;
 // parameters       => IDENTIFIER ( "," IDENTIFIER )* ;
@@ -30,6 +34,8 @@ use crate::{
 // printStmt        => "print" expression ";" ;
 // returnStmt       => "return" expression? ";" ;
 // whileStmt        => "while" "(" expression ")" statement ;
+// breakStmt        => "break" ";" ;
+// continueStmt     => "continue" ";" ;
 // forStmt          => "for" "(" ( varDecl | exprStmt | ";" )
 //                  expression? ";"
 //                  expression? ")" statement ;
@@ -37,7 +43,7 @@ use crate::{
 //                  ( "else" statement )? ;
 //
 // expression       => assignment ;
-// assignment       => IDENTIFIER "=" assignment | logicOr ;
+// assignment       => ( call "." )? IDENTIFIER "=" assignment | logicOr ;
 // logicOr          => logicAnd ( "or" logicAnd )*
 // logicAnd         => equality ( "and" equality )*
 // equality         => comparison ( ("!=" | "==") comparison )* ;
@@ -46,13 +52,13 @@ use crate::{
 // factor           => unary ( ("/" | "*") unary )* ;
 // unary            => ("!" | "-") unary
 //                  | call ;
-// call             => primary ( "(" arguments? ")" )* ;
+// call             => primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
 // arguments        => expression ( "," expression )* ;
 //
 // primary          => NUMBER | STRING
 //                  | "true" | "false" | "nil"
 //                  | "(" expression ")"
-//                  | IDENTIFIER ;
+//                  | "this" | IDENTIFIER | "super" "." IDENTIFIER ;
 
 pub struct Parser<'a> {
     arena: &'a mut AstArena,
@@ -129,15 +135,47 @@ impl<'a> Parser<'a> {
 
     fn declaration(&mut self) -> Result<Stmt, ParsingError> {
         match self.peek_type() {
-            Some(TokenType::Fun) => self.function(),
+            Some(TokenType::Class) => self.class_decl(),
+            Some(TokenType::Fun) => self.fun_decl(),
             Some(TokenType::Var) => self.var_decl(),
             _ => self.statement(),
         }
     }
 
-    fn function(&mut self) -> Result<Stmt, ParsingError> {
+    fn class_decl(&mut self) -> Result<Stmt, ParsingError> {
+        self.consume(TokenType::Class)?;
+        let name = self.consume_with(|t| matches!(t, TokenType::Identifier(_)), "class name")?;
+        let superclass = self
+            .matches(TokenType::Less)
+            .map(|_| {
+                self.consume_with(|t| matches!(t, TokenType::Identifier(_)), "superclass name")
+            })
+            .transpose()?
+            .map(|name| self.alloc_expr(ExprVariable { name }.into()));
+
+        self.consume(TokenType::LeftBrace)?;
+        let mut methods = vec![];
+        while self.peek().is_some_and(|t| t.ty != TokenType::RightBrace) {
+            let method = self.function("method name")?;
+            methods.push(self.alloc_stmt(method.into()));
+        }
+        self.consume(TokenType::RightBrace)?;
+
+        Ok(StmtClass {
+            name,
+            superclass,
+            methods,
+        }
+        .into())
+    }
+
+    fn fun_decl(&mut self) -> Result<Stmt, ParsingError> {
         self.consume(TokenType::Fun)?;
-        let name = self.consume_with(|t| matches!(t, TokenType::Identifier(_)), "function name")?;
+        Ok(self.function("function name")?.into())
+    }
+
+    fn function(&mut self, kind: &str) -> Result<StmtFunction, ParsingError> {
+        let name = self.consume_with(|t| matches!(t, TokenType::Identifier(_)), kind)?;
 
         self.consume(TokenType::LeftParen)?;
         let params = self.parameters()?;
@@ -146,7 +184,7 @@ impl<'a> Parser<'a> {
         let body = self.block()?;
         let body = self.alloc_stmt_vec(body);
 
-        Ok(StmtFunction { name, params, body }.into())
+        Ok(StmtFunction { name, params, body })
     }
 
     fn parameters(&mut self) -> Result<Vec<Token>, ParsingError> {
@@ -161,7 +199,8 @@ impl<'a> Parser<'a> {
             return Err(ParsingError::custom(
                 self.peek().unwrap_or(&self.eof),
                 "Can't have more than 255 parameters.",
-            ));
+            )
+            .with_code(Code::LimitExceeded));
         }
 
         Ok(args)
@@ -187,6 +226,16 @@ impl<'a> Parser<'a> {
             Some(TokenType::Return) => self.return_stmt(),
             Some(TokenType::While) => self.while_stmt(),
             Some(TokenType::For) => self.for_stmt(),
+            Some(TokenType::Break) => {
+                let keyword = self.consume(TokenType::Break)?;
+                self.consume(TokenType::Semicolon)?;
+                Ok(StmtBreak { keyword }.into())
+            }
+            Some(TokenType::Continue) => {
+                let keyword = self.consume(TokenType::Continue)?;
+                self.consume(TokenType::Semicolon)?;
+                Ok(StmtContinue { keyword }.into())
+            }
             Some(TokenType::LeftBrace) => {
                 let block = self.block()?;
                 let statements = self.alloc_stmt_vec(block);
@@ -243,7 +292,12 @@ impl<'a> Parser<'a> {
         self.consume(TokenType::RightParen)?;
         let body = self.statement()?;
         let body = self.alloc_stmt(body);
-        Ok(StmtWhile { condition, body }.into())
+        Ok(StmtWhile {
+            condition,
+            body,
+            increment: None,
+        }
+        .into())
     }
 
     fn for_stmt(&mut self) -> Result<Stmt, ParsingError> {
@@ -273,14 +327,8 @@ impl<'a> Parser<'a> {
         self.consume(TokenType::RightParen)?;
 
         let body = self.statement()?;
-        let mut body = self.alloc_stmt(body);
-        if let Some(increment) = increment.map(|inc| self.alloc_expr(inc)) {
-            let block = StmtBlock {
-                statements: vec![body, self.alloc_stmt(increment.into())],
-            }
-            .into();
-            body = self.alloc_stmt(block);
-        };
+        let body = self.alloc_stmt(body);
+        let increment = increment.map(|inc| self.alloc_expr(inc));
         let condition = condition.unwrap_or_else(|| {
             self.alloc_expr(
                 ExprLiteral {
@@ -291,7 +339,12 @@ impl<'a> Parser<'a> {
                 .into(),
             )
         });
-        let while_stmt = StmtWhile { body, condition }.into();
+        let while_stmt = StmtWhile {
+            body,
+            condition,
+            increment,
+        }
+        .into();
         Ok(match initialization {
             Some(initialization) => StmtBlock {
                 statements: vec![initialization, self.alloc_stmt(while_stmt)],
@@ -337,7 +390,14 @@ impl<'a> Parser<'a> {
 
             return match expr {
                 Expr::Variable(ExprVariable { name }) => Ok(ExprAssign { name, value }.into()),
-                _ => Err(ParsingError::custom(equals, "Invalid assignment target.")),
+                Expr::Get(ExprGet { object, name }) => Ok(ExprSet {
+                    object,
+                    name,
+                    value,
+                }
+                .into()),
+                _ => Err(ParsingError::custom(equals, "Invalid assignment target.")
+                    .with_code(Code::InvalidAssignmentTarget)),
             };
         }
         Ok(expr)
@@ -457,18 +517,33 @@ impl<'a> Parser<'a> {
 
     fn call(&mut self) -> Result<Expr, ParsingError> {
         let mut expr = self.primary()?;
-        while self.matches(TokenType::LeftParen).is_some() {
-            let args = self.arguments()?;
-            let args = self.alloc_expr_vec(args);
+        while let Some(tok) =
+            self.matches_with(|t| matches!(t, TokenType::LeftParen | TokenType::Dot))
+        {
+            expr = match tok.ty {
+                TokenType::LeftParen => {
+                    let args = self.arguments()?;
+                    let args = self.alloc_expr_vec(args);
 
-            let r_paren = self.consume(TokenType::RightParen)?;
+                    let r_paren = self.consume(TokenType::RightParen)?;
 
-            expr = ExprCall {
-                callee: self.alloc_expr(expr),
-                r_paren,
-                args,
-            }
-            .into();
+                    ExprCall {
+                        callee: self.alloc_expr(expr),
+                        r_paren,
+                        args,
+                    }
+                    .into()
+                }
+                _ => {
+                    let name = self
+                        .consume_with(|t| matches!(t, TokenType::Identifier(_)), "property name")?;
+                    ExprGet {
+                        object: self.alloc_expr(expr),
+                        name,
+                    }
+                    .into()
+                }
+            };
         }
         Ok(expr)
     }
@@ -483,7 +558,8 @@ impl<'a> Parser<'a> {
             return Err(ParsingError::custom(
                 self.peek().unwrap_or(&self.eof),
                 "Can't have more than 255 arguments.",
-            ));
+            )
+            .with_code(Code::LimitExceeded));
         }
 
         Ok(args)
@@ -511,6 +587,15 @@ impl<'a> Parser<'a> {
                 expr
             }
             Some(tt_pat!(ident @ TokenType::Identifier(_))) => ExprVariable { name: ident }.into(),
+            Some(tt_pat!(keyword @ TokenType::This)) => ExprThis { keyword }.into(),
+            Some(tt_pat!(keyword @ TokenType::Super)) => {
+                self.consume(TokenType::Dot)?;
+                let method = self.consume_with(
+                    |t| matches!(t, TokenType::Identifier(_)),
+                    "superclass method name",
+                )?;
+                ExprSuper { keyword, method }.into()
+            }
             Some(tok) => return Err(ParsingError::expected(&tok, "expression", &tok)),
             None => return Err(ParsingError::expected(&self.eof, "expression", &self.eof)),
         };
@@ -702,6 +787,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_property_access() {
+        let src = "a.b(1).c = this.d";
+        let tokens = Scanner::new(src).scan_tokens().unwrap();
+
+        let mut arena = AstArena::default();
+        let expr = Parser::new(&mut arena, tokens).expression().unwrap();
+        let expr = arena.alloc_expr(expr);
+
+        assert_eq!(
+            expr.polish_notation(),
+            "(set (call (get a b) 1) c (get this d))"
+        );
+    }
+
     #[test]
     fn test_precedence() {
         let src = "42 + -69 * 420 == (\"wtv\" > !false != nil)";
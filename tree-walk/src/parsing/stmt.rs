@@ -12,7 +12,10 @@ pub enum Stmt {
     If(StmtIf),
     Return(StmtReturn),
     While(StmtWhile),
+    Break(StmtBreak),
+    Continue(StmtContinue),
     Function(StmtFunction),
+    Class(StmtClass),
 }
 
 #[derive(Debug, Clone)]
@@ -54,6 +57,18 @@ pub struct StmtReturn {
 pub struct StmtWhile {
     pub condition: ExprId,
     pub body: StmtId,
+    /// Desugared `for` increment, run after the body and on `continue`.
+    pub increment: Option<ExprId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StmtBreak {
+    pub keyword: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct StmtContinue {
+    pub keyword: Token,
 }
 
 #[derive(Debug, Clone)]
@@ -63,6 +78,13 @@ pub struct StmtFunction {
     pub body: Vec<StmtId>,
 }
 
+#[derive(Debug, Clone)]
+pub struct StmtClass {
+    pub name: Token,
+    pub superclass: Option<ExprId>,
+    pub methods: Vec<StmtId>,
+}
+
 macro_rules! impl_stmt_node {
     ($variant:path, $type:ident) => {
         $crate::impl_ast_node!(Stmt, $variant, $type);
@@ -84,7 +106,10 @@ impl_stmt_node!(Stmt::Block, StmtBlock);
 impl_stmt_node!(Stmt::If, StmtIf);
 impl_stmt_node!(Stmt::Return, StmtReturn);
 impl_stmt_node!(Stmt::While, StmtWhile);
+impl_stmt_node!(Stmt::Break, StmtBreak);
+impl_stmt_node!(Stmt::Continue, StmtContinue);
 impl_stmt_node!(Stmt::Function, StmtFunction);
+impl_stmt_node!(Stmt::Class, StmtClass);
 
 impl From<ExprId> for StmtExpression {
     fn from(expr: ExprId) -> Self {
@@ -13,6 +13,10 @@ pub trait ExprVisitor {
     fn visit_variable(self, expr: AstRef<ExprVariable>) -> Self::T;
     fn visit_assign(self, expr: AstRef<ExprAssign>) -> Self::T;
     fn visit_logical(self, expr: AstRef<ExprLogical>) -> Self::T;
+    fn visit_get(self, expr: AstRef<ExprGet>) -> Self::T;
+    fn visit_set(self, expr: AstRef<ExprSet>) -> Self::T;
+    fn visit_this(self, expr: AstRef<ExprThis>) -> Self::T;
+    fn visit_super(self, expr: AstRef<ExprSuper>) -> Self::T;
 }
 
 pub trait StmtVisitor {
@@ -25,7 +29,10 @@ pub trait StmtVisitor {
     fn visit_if(self, stmt: AstRef<StmtIf>) -> Self::T;
     fn visit_return(self, stmt: AstRef<StmtReturn>) -> Self::T;
     fn visit_while(self, stmt: AstRef<StmtWhile>) -> Self::T;
+    fn visit_break(self, stmt: AstRef<StmtBreak>) -> Self::T;
+    fn visit_continue(self, stmt: AstRef<StmtContinue>) -> Self::T;
     fn visit_function(self, stmt: AstRef<StmtFunction>) -> Self::T;
+    fn visit_class(self, stmt: AstRef<StmtClass>) -> Self::T;
 }
 
 impl<'a> AstRef<'a, Expr> {
@@ -39,6 +46,10 @@ impl<'a> AstRef<'a, Expr> {
             Expr::Variable(_) => visitor.visit_variable(self.cast()),
             Expr::Assign(_) => visitor.visit_assign(self.cast()),
             Expr::Logical(_) => visitor.visit_logical(self.cast()),
+            Expr::Get(_) => visitor.visit_get(self.cast()),
+            Expr::Set(_) => visitor.visit_set(self.cast()),
+            Expr::This(_) => visitor.visit_this(self.cast()),
+            Expr::Super(_) => visitor.visit_super(self.cast()),
         }
     }
 }
@@ -53,7 +64,10 @@ impl<'a> AstRef<'a, Stmt> {
             Stmt::If(_) => visitor.visit_if(self.cast()),
             Stmt::Return(_) => visitor.visit_return(self.cast()),
             Stmt::While(_) => visitor.visit_while(self.cast()),
+            Stmt::Break(_) => visitor.visit_break(self.cast()),
+            Stmt::Continue(_) => visitor.visit_continue(self.cast()),
             Stmt::Function(_) => visitor.visit_function(self.cast()),
+            Stmt::Class(_) => visitor.visit_class(self.cast()),
         }
     }
 }
@@ -1,5 +1,7 @@
 use std::collections::HashSet;
 
+use report::{Code, diagnostic::Annotate, error::ParsingError};
+
 use crate::{
     parsing::{
         ast::{AstArena, AstRef, ExprId, ExprRef, StmtId, StmtRef},
@@ -10,10 +12,29 @@ use crate::{
     runtime::Interpreter,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
 pub struct Resolver<'i, 'a> {
     interpreter: &'i mut Interpreter,
     ast_arena: &'a AstArena,
     scope_stack: Vec<HashSet<Box<str>>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    loop_depth: usize,
+    errors: Vec<ParsingError>,
 }
 
 impl<'i, 'a> Resolver<'i, 'a> {
@@ -22,6 +43,18 @@ impl<'i, 'a> Resolver<'i, 'a> {
             interpreter,
             ast_arena,
             scope_stack: vec![HashSet::new()],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            loop_depth: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve_program(mut self, program: &[StmtId]) -> Result<(), Vec<ParsingError>> {
+        self.resolve(program);
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(self.errors),
         }
     }
 
@@ -33,7 +66,7 @@ impl<'i, 'a> Resolver<'i, 'a> {
         stmt.accept(self)
     }
 
-    pub fn resolve(&mut self, stmts: &[StmtId]) {
+    fn resolve(&mut self, stmts: &[StmtId]) {
         for stmt in stmts.iter().map(|&id| self.ast_arena.stmt_ref(id)) {
             stmt.accept(&mut *self);
         }
@@ -54,13 +87,18 @@ impl<'i, 'a> Resolver<'i, 'a> {
         }
     }
 
-    fn resolve_fn(&mut self, stmt_fn: AstRef<StmtFunction>) {
+    fn resolve_fn(&mut self, stmt_fn: AstRef<StmtFunction>, kind: FunctionType) {
+        let enclosing_function = std::mem::replace(&mut self.current_function, kind);
+        // loops don't extend into function bodies
+        let enclosing_loop_depth = std::mem::take(&mut self.loop_depth);
         self.begin_scope();
         for stmt in &stmt_fn.params {
             self.define(stmt.as_str().into());
         }
         self.resolve(&stmt_fn.body);
         self.end_scope();
+        self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
     }
 
     fn define(&mut self, name: Box<str>) {
@@ -114,18 +152,92 @@ impl StmtVisitor for &mut Resolver<'_, '_> {
 
     fn visit_return(self, stmt: AstRef<StmtReturn>) -> Self::T {
         if let Some(expr) = &stmt.expr {
+            if self.current_function == FunctionType::Initializer {
+                self.errors.push(
+                    ParsingError::custom(
+                        &stmt.return_token,
+                        "Can't return a value from an initializer.",
+                    )
+                    .with_code(Code::ReturnValueFromInitializer)
+                    .with_help("initializers always return `this`; use a bare `return;`"),
+                );
+            }
             self.resolve_expr(self.ast_arena.expr_ref(*expr));
         }
     }
 
     fn visit_while(self, stmt: AstRef<StmtWhile>) -> Self::T {
         self.resolve_expr(self.ast_arena.expr_ref(stmt.condition));
-        self.resolve_stmt(self.ast_arena.stmt_ref(stmt.body))
+        self.loop_depth += 1;
+        self.resolve_stmt(self.ast_arena.stmt_ref(stmt.body));
+        self.loop_depth -= 1;
+        if let Some(increment) = stmt.increment {
+            self.resolve_expr(self.ast_arena.expr_ref(increment));
+        }
+    }
+
+    fn visit_break(self, stmt: AstRef<StmtBreak>) -> Self::T {
+        if self.loop_depth == 0 {
+            self.errors.push(
+                ParsingError::custom(&stmt.keyword, "Can't use 'break' outside of a loop.")
+                    .with_code(Code::LoopControlOutsideLoop),
+            );
+        }
+    }
+
+    fn visit_continue(self, stmt: AstRef<StmtContinue>) -> Self::T {
+        if self.loop_depth == 0 {
+            self.errors.push(
+                ParsingError::custom(&stmt.keyword, "Can't use 'continue' outside of a loop.")
+                    .with_code(Code::LoopControlOutsideLoop),
+            );
+        }
     }
 
     fn visit_function(self, stmt: AstRef<StmtFunction>) -> Self::T {
         self.define(stmt.name.as_str().into());
-        self.resolve_fn(stmt);
+        self.resolve_fn(stmt, FunctionType::Function);
+    }
+
+    fn visit_class(self, stmt: AstRef<StmtClass>) -> Self::T {
+        let enclosing_class = std::mem::replace(&mut self.current_class, ClassType::Class);
+        self.define(stmt.name.as_str().into());
+
+        if let Some(superclass) = stmt.superclass {
+            let superclass = self.ast_arena.expr_ref(superclass);
+            if superclass.cast::<ExprVariable>().name.as_str() == stmt.name.as_str() {
+                self.errors.push(
+                    ParsingError::custom(
+                        &superclass.cast::<ExprVariable>().name,
+                        "A class can't inherit from itself.",
+                    )
+                    .with_code(Code::InheritFromSelf)
+                    .with_label(&stmt.name, "class declared here"),
+                );
+            }
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(superclass);
+
+            self.begin_scope();
+            self.define("super".into());
+        }
+
+        self.begin_scope();
+        self.define("this".into());
+        for method in stmt.methods.iter().map(|&id| self.ast_arena.stmt_ref(id)) {
+            let method = method.cast::<StmtFunction>();
+            let kind = match method.name.as_str() == "init" {
+                true => FunctionType::Initializer,
+                false => FunctionType::Method,
+            };
+            self.resolve_fn(method, kind);
+        }
+        self.end_scope();
+
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+        self.current_class = enclosing_class;
     }
 }
 
@@ -168,4 +280,41 @@ impl ExprVisitor for &mut Resolver<'_, '_> {
         self.resolve_expr(self.ast_arena.expr_ref(expr.left));
         self.resolve_expr(self.ast_arena.expr_ref(expr.right))
     }
+
+    fn visit_get(self, expr: AstRef<ExprGet>) -> Self::T {
+        self.resolve_expr(self.ast_arena.expr_ref(expr.object))
+    }
+
+    fn visit_set(self, expr: AstRef<ExprSet>) -> Self::T {
+        self.resolve_expr(self.ast_arena.expr_ref(expr.value));
+        self.resolve_expr(self.ast_arena.expr_ref(expr.object))
+    }
+
+    fn visit_this(self, expr: AstRef<ExprThis>) -> Self::T {
+        if self.current_class == ClassType::None {
+            self.errors.push(
+                ParsingError::custom(&expr.keyword, "Can't use 'this' outside of a class.")
+                    .with_code(Code::ThisOutsideClass),
+            );
+            return;
+        }
+        self.resolve_local(expr.id(), "this")
+    }
+
+    fn visit_super(self, expr: AstRef<ExprSuper>) -> Self::T {
+        match self.current_class {
+            ClassType::None => self.errors.push(
+                ParsingError::custom(&expr.keyword, "Can't use 'super' outside of a class.")
+                    .with_code(Code::SuperOutsideClass),
+            ),
+            ClassType::Class => self.errors.push(
+                ParsingError::custom(
+                    &expr.keyword,
+                    "Can't use 'super' in a class with no superclass.",
+                )
+                .with_code(Code::SuperWithoutSuperclass),
+            ),
+            ClassType::Subclass => self.resolve_local(expr.id(), "super"),
+        }
+    }
 }
@@ -7,7 +7,12 @@ use crate::{
         ast::{AstArena, AstRef, StmtId},
         stmt::StmtFunction,
     },
-    runtime::{Interpreter, control_flow::ControlFlow, environment::Environment, object::Object},
+    runtime::{
+        Interpreter,
+        control_flow::ControlFlow,
+        environment::Environment,
+        object::{Class, Instance, Object},
+    },
 };
 
 pub trait ObjCallable {
@@ -65,10 +70,11 @@ pub struct Function {
     name: Box<str>,
     arity: u8,
     enclosing_env: Environment,
+    is_initializer: bool,
 }
 
 impl Function {
-    pub fn new(decl: AstRef<StmtFunction>, env: Environment) -> Self {
+    pub fn new(decl: AstRef<StmtFunction>, env: Environment, is_initializer: bool) -> Self {
         let name = decl.name.as_str().into();
         let arity = decl.params.len().try_into().expect("arity always < 256");
         let decl = decl.id();
@@ -77,12 +83,30 @@ impl Function {
             name,
             arity,
             enclosing_env: env,
+            is_initializer,
         }
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Returns a copy of this method whose closure defines `this` as `instance`.
+    pub fn bind(&self, instance: Object) -> Function {
+        let mut enclosing_env = self.enclosing_env.clone();
+        enclosing_env.push_scope();
+        enclosing_env.define("this".into(), instance);
+        Function {
+            enclosing_env,
+            ..self.clone()
+        }
+    }
+
+    fn this(&self) -> Object {
+        self.enclosing_env
+            .get_at(0, "this")
+            .expect("initializers are always bound to an instance")
+    }
 }
 
 impl ObjCallable for Function {
@@ -96,6 +120,7 @@ impl ObjCallable for Function {
         arena: &AstArena,
         args: Vec<Object>,
     ) -> Result<Object, RuntimeError> {
+        interpreter.enter_call(&self.name)?;
         let callsite_env = interpreter.env.clone();
         interpreter.env = self.enclosing_env.clone();
 
@@ -107,6 +132,7 @@ impl ObjCallable for Function {
                 interpreter.env.define(param.as_str().into(), arg);
             }
             match interpreter.execute_block(decl.body.iter().map(|&s| arena.stmt_ref(s))) {
+                Ok(_) | Err(ControlFlow::Return(_)) if self.is_initializer => Ok(self.this()),
                 Ok(_) => Ok(Object::nil()),
                 Err(ControlFlow::Return(object)) => Ok(object),
                 Err(ControlFlow::Error(err)) => Err(err),
@@ -117,10 +143,36 @@ impl ObjCallable for Function {
         };
 
         interpreter.env = callsite_env;
+        let call_result = call_result.map_err(|err| {
+            let span = err.span;
+            err.with_trace(|| interpreter.backtrace(span))
+        });
+        interpreter.leave_call();
         call_result
     }
 }
 
+impl ObjCallable for Class {
+    fn arity(&self) -> u8 {
+        self.find_method("init").map_or(0, Function::arity)
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arena: &AstArena,
+        args: Vec<Object>,
+    ) -> Result<Object, RuntimeError> {
+        let instance = Object::new(Instance::new(self.clone()));
+        if let Some(initializer) = self.find_method("init") {
+            initializer
+                .bind(instance.clone())
+                .call(interpreter, arena, args)?;
+        }
+        Ok(instance)
+    }
+}
+
 impl Debug for NativeFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("NativeFunction")
@@ -165,7 +217,7 @@ impl Display for Function {
 
 impl PartialEq for Function {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
+        std::ptr::eq(self, other)
     }
 }
 
@@ -4,7 +4,11 @@ use std::{
 };
 
 use lexer::tokens::TokenType;
-use report::{Span, Spanned, error::RuntimeError};
+use report::{
+    Code, Error, Span, Spanned,
+    diagnostic::Annotate,
+    error::{HaltError, RuntimeError, halt::InterruptHandle, runtime::TraceFrame},
+};
 
 use super::{environment::*, object::*};
 use crate::{
@@ -24,6 +28,25 @@ pub struct Interpreter {
     pub(super) env: Environment,
     pub(super) span_stack: VecDeque<Span>,
     locals: HashMap<ExprId, usize>,
+    /// Statements and expressions each run may execute, if limited.
+    budget: Option<u64>,
+    /// What's left of the budget in the current run.
+    steps_left: u64,
+    interrupt: InterruptHandle,
+    /// Why the current run was halted. Halting unwinds as a runtime error,
+    /// which this tells apart from a real one.
+    halted: Option<HaltError>,
+    /// Lox function calls in progress, outermost first.
+    calls: Vec<Call>,
+    /// Calls that may be in progress at once, counting the top-level code.
+    max_call_depth: usize,
+}
+
+/// A Lox function call in progress.
+struct Call {
+    function: Box<str>,
+    /// Where the function was called from.
+    site: Span,
 }
 
 impl Default for Interpreter {
@@ -33,46 +56,139 @@ impl Default for Interpreter {
 }
 
 impl Interpreter {
+    /// Default call depth limit, the same as clox's.
+    pub const MAX_CALL_DEPTH: usize = 64;
+
     pub fn new() -> Self {
         let mut this = Self {
             env: Environment::new(),
             span_stack: vec![Span::default()].into(),
             locals: HashMap::new(),
+            budget: None,
+            steps_left: 0,
+            interrupt: InterruptHandle::default(),
+            halted: None,
+            calls: Vec::new(),
+            max_call_depth: Self::MAX_CALL_DEPTH,
         };
         this.define_builtins();
 
         this
     }
 
-    pub fn interpret(
-        &mut self,
-        program: Vec<StmtId>,
-        ast_arena: &AstArena,
-    ) -> Result<(), RuntimeError> {
+    /// Stop each run after it executes `steps` statements and expressions.
+    pub fn with_budget(self, steps: u64) -> Self {
+        Self {
+            budget: Some(steps),
+            ..self
+        }
+    }
+
+    /// Fail calls with "Stack overflow." once `depth` calls are in progress,
+    /// counting the top-level code.
+    pub fn with_max_call_depth(self, depth: usize) -> Self {
+        Self {
+            max_call_depth: depth,
+            ..self
+        }
+    }
+
+    /// A handle to stop this interpreter's runs from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    pub fn interpret(&mut self, program: Vec<StmtId>, ast_arena: &AstArena) -> Result<(), Error> {
+        self.steps_left = self.budget.unwrap_or_default();
         for statement in program.iter().map(|&s| ast_arena.stmt_ref(s)) {
             match self.execute(statement) {
                 Ok(()) => {}
                 Err(ControlFlow::Break | ControlFlow::Continue) => {
-                    return Err(RuntimeError::invalid_break_or_continue(self.current_span()));
+                    return Err(RuntimeError::invalid_break_or_continue(self.current_span()).into());
                 }
                 Err(ControlFlow::Return(_)) => {
-                    return Err(RuntimeError::invalid_return(self.current_span()));
+                    return Err(RuntimeError::invalid_return(self.current_span()).into());
+                }
+                Err(ControlFlow::Error(runtime)) => {
+                    return Err(match self.halted.take() {
+                        Some(halt) => halt.into(),
+                        None => {
+                            let span = runtime.span;
+                            runtime.with_trace(|| self.backtrace(span)).into()
+                        }
+                    });
                 }
-                Err(ControlFlow::Error(runtime)) => return Err(runtime),
             }
         }
         Ok(())
     }
 
     fn evaluate(&mut self, expr: ExprRef) -> Result<Object, RuntimeError> {
+        self.step()?;
         let mut this = self.new_span(expr.span());
         expr.accept(&mut *this)
     }
 
     fn execute(&mut self, stmt: StmtRef) -> Result<(), ControlFlow> {
+        self.step()?;
         stmt.accept(self)
     }
 
+    /// Account for the next statement or expression, unless the budget is
+    /// spent or the host asked to stop.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        let halt = if self.interrupt.take() {
+            HaltError::Interrupted
+        } else {
+            match self.budget {
+                Some(budget) if self.steps_left == 0 => HaltError::OutOfBudget(budget),
+                Some(_) => {
+                    self.steps_left -= 1;
+                    return Ok(());
+                }
+                None => return Ok(()),
+            }
+        };
+        self.halted = Some(halt);
+        Err(RuntimeError::custom(*self.current_span(), halt))
+    }
+
+    /// Enter a call to `function` from the current span, unless that would
+    /// take too many calls.
+    pub(super) fn enter_call(&mut self, function: &str) -> Result<(), RuntimeError> {
+        let site = *self.current_span();
+        if self.calls.len() + 1 >= self.max_call_depth {
+            return Err(RuntimeError::stack_overflow(site).with_note(format_args!(
+                "calls nest at most {} deep",
+                self.max_call_depth
+            )));
+        }
+        self.calls.push(Call {
+            function: function.into(),
+            site,
+        });
+        Ok(())
+    }
+
+    pub(super) fn leave_call(&mut self) {
+        self.calls.pop();
+    }
+
+    /// The call stack, innermost first, for an error at `span`.
+    pub(super) fn backtrace(&self, span: Span) -> Vec<TraceFrame> {
+        let functions = self
+            .calls
+            .iter()
+            .rev()
+            .map(|call| Some(call.function.clone()));
+        let lines = self.calls.iter().rev().map(|call| call.site.line_start);
+        functions
+            .chain([None])
+            .zip(std::iter::once(span.line_start).chain(lines))
+            .map(|(function, line)| TraceFrame { function, line })
+            .collect()
+    }
+
     pub fn resolve_var(&mut self, expr_id: ExprId, depth: usize) {
         self.locals.insert(expr_id, depth);
     }
@@ -135,7 +251,7 @@ impl ExprVisitor for &mut Interpreter {
         let arena = expr.arena();
         let left = self.evaluate(arena.expr_ref(expr.left))?;
         let right = self.evaluate(arena.expr_ref(expr.right))?;
-        let err_handler = |e| RuntimeError::with_token(&expr.op, e);
+        let err_handler = |e| RuntimeError::with_token(&expr.op, e).with_code(Code::InvalidOperand);
 
         let value = match expr.op.ty {
             TokenType::Plus => (left + right).map_err(err_handler)?,
@@ -179,8 +295,7 @@ impl ExprVisitor for &mut Interpreter {
             ));
         }
 
-        let result = callable.call(&mut this, arena, args)?;
-        Ok(result)
+        callable.call(&mut this, arena, args)
     }
 
     fn visit_grouping(self, expr: AstRef<ExprGrouping>) -> Self::T {
@@ -202,6 +317,7 @@ impl ExprVisitor for &mut Interpreter {
                     expr.op.span().join(&right.span()),
                     format!("Invalid operand: {e}"),
                 )
+                .with_code(Code::InvalidOperand)
             })?),
             TokenType::Bang => Object::new(!right.is_truthy()),
             _ => panic!("Unexpected unary operator: {:?}", expr.op),
@@ -225,7 +341,7 @@ impl ExprVisitor for &mut Interpreter {
             Some(depth) => this.env.assign_at(depth, &name, value),
             None => this.env.assign(&name, value),
         }
-        .map_err(|e| RuntimeError::with_token(&expr.name, e))
+        .map_err(|e| RuntimeError::with_token(&expr.name, e).with_code(Code::UndefinedVariable))
     }
 
     fn visit_logical(self, expr: AstRef<ExprLogical>) -> Self::T {
@@ -242,6 +358,69 @@ impl ExprVisitor for &mut Interpreter {
             ),
         }
     }
+
+    fn visit_get(self, expr: AstRef<ExprGet>) -> Self::T {
+        let arena = expr.arena();
+        let object = self.evaluate(arena.expr_ref(expr.object))?;
+        let instance = object.try_downcast::<Instance>().map_err(|_| {
+            RuntimeError::with_token(&expr.name, "Only instances have properties.")
+                .with_code(Code::NotAnInstance)
+        })?;
+        let name = expr.name.as_str();
+
+        instance
+            .field(&name)
+            .or_else(|| {
+                let method = instance.class().find_method(&name)?;
+                Some(Object::new(method.bind(object.clone())))
+            })
+            .ok_or_else(|| {
+                RuntimeError::with_token(&expr.name, format!("Undefined property '{name}'."))
+                    .with_code(Code::UndefinedProperty)
+            })
+    }
+
+    fn visit_set(self, expr: AstRef<ExprSet>) -> Self::T {
+        let arena = expr.arena();
+        let object = self.evaluate(arena.expr_ref(expr.object))?;
+        let instance = object.try_downcast::<Instance>().map_err(|_| {
+            RuntimeError::with_token(&expr.name, "Only instances have fields.")
+                .with_code(Code::NotAnInstance)
+        })?;
+        let value = self.evaluate(arena.expr_ref(expr.value))?;
+
+        instance.set_field(expr.name.as_str().into(), value.clone());
+        Ok(value)
+    }
+
+    fn visit_this(self, expr: AstRef<ExprThis>) -> Self::T {
+        self.lookup_var("this", expr.id())
+            .ok_or_else(|| RuntimeError::undefined(&expr.keyword))
+    }
+
+    fn visit_super(self, expr: AstRef<ExprSuper>) -> Self::T {
+        let depth = *self
+            .locals
+            .get(&expr.id())
+            .expect("resolver binds every valid 'super' expression");
+        let superclass = self
+            .env
+            .get_at(depth, "super")
+            .expect("'super' is defined in the scope enclosing methods");
+        let superclass = superclass.downcast::<Class>();
+        // 'this' is always bound in the scope right inside the one defining 'super'
+        let object = self
+            .env
+            .get_at(depth - 1, "this")
+            .expect("'this' is defined in the scope enclosing method bodies");
+
+        let name = expr.method.as_str();
+        let method = superclass.find_method(&name).ok_or_else(|| {
+            RuntimeError::with_token(&expr.method, format!("Undefined property '{name}'."))
+                .with_code(Code::UndefinedProperty)
+        })?;
+        Ok(Object::new(method.bind(object)))
+    }
 }
 
 impl StmtVisitor for &mut Interpreter {
@@ -305,18 +484,71 @@ impl StmtVisitor for &mut Interpreter {
         let arena = stmt.arena();
 
         while self.evaluate(arena.expr_ref(stmt.condition))?.is_truthy() {
-            self.execute(arena.stmt_ref(stmt.body))?;
+            match self.execute(arena.stmt_ref(stmt.body)) {
+                Ok(()) | Err(ControlFlow::Continue) => {}
+                Err(ControlFlow::Break) => break,
+                Err(err) => return Err(err),
+            }
+            if let Some(increment) = stmt.increment {
+                self.evaluate(arena.expr_ref(increment))?;
+            }
         }
         Ok(())
     }
 
+    fn visit_break(self, _stmt: AstRef<StmtBreak>) -> Self::T {
+        Err(ControlFlow::Break)
+    }
+
+    fn visit_continue(self, _stmt: AstRef<StmtContinue>) -> Self::T {
+        Err(ControlFlow::Continue)
+    }
+
     fn visit_function(self, stmt: AstRef<StmtFunction>) -> Self::T {
         self.env.define(
             stmt.name.as_str().into(),
-            Object::new(Function::new(stmt, self.env.clone())),
+            Object::new(Function::new(stmt, self.env.clone(), false)),
         );
         Ok(())
     }
+
+    fn visit_class(self, stmt: AstRef<StmtClass>) -> Self::T {
+        let arena = stmt.arena();
+        let superclass = stmt
+            .superclass
+            .map(|superclass| {
+                let superclass = arena.expr_ref(superclass);
+                self.evaluate(superclass)?
+                    .try_downcast::<Class>()
+                    .cloned()
+                    .map_err(|_| {
+                        RuntimeError::with_token(superclass, "Superclass must be a class.")
+                            .with_code(Code::InvalidSuperclass)
+                    })
+            })
+            .transpose()?;
+
+        let mut method_env = self.env.clone();
+        if let Some(superclass) = &superclass {
+            method_env.push_scope();
+            method_env.define("super".into(), Object::new(superclass.clone()));
+        }
+        let methods = stmt
+            .methods
+            .iter()
+            .map(|&id| arena.stmt_ref(id).cast::<StmtFunction>())
+            .map(|method| {
+                let is_initializer = method.name.as_str() == "init";
+                let function = Function::new(method, method_env.clone(), is_initializer);
+                (method.name.as_str().into(), function)
+            })
+            .collect();
+
+        let class = Class::new(stmt.name.as_str().into(), superclass, methods);
+        self.env
+            .define(stmt.name.as_str().into(), Object::new(class));
+        Ok(())
+    }
 }
 
 pub struct InterpreterScope<'i, F>
@@ -371,6 +603,7 @@ where
 #[cfg(test)]
 mod tests {
     use crate::parsing::Parser;
+    use crate::passes::resolver::Resolver;
     use lexer::Scanner;
 
     use super::*;
@@ -386,6 +619,87 @@ mod tests {
             .expect("syntax error")
     }
 
+    fn run(source: &str, interpreter: &mut Interpreter) -> Result<(), Error> {
+        let mut ast_arena = AstArena::default();
+        let tokens = Scanner::new(source).scan_tokens().expect("token error");
+        let program = Parser::new(&mut ast_arena, tokens)
+            .parse()
+            .expect("syntax error");
+        Resolver::new(interpreter, &ast_arena)
+            .resolve_program(&program)
+            .expect("resolution error");
+        interpreter.interpret(program, &ast_arena)
+    }
+
+    #[test]
+    fn budget_stops_runaway_loops() {
+        let mut interpreter = Interpreter::new().with_budget(1_000);
+        let result = run("while (true) {}", &mut interpreter);
+        assert!(matches!(
+            result,
+            Err(Error::Halt(HaltError::OutOfBudget(1_000)))
+        ));
+
+        // Each run gets the whole budget again, and a real error is still one.
+        run("var a = 1; a = a + 1;", &mut interpreter).unwrap();
+        let result = run("a = a + nil;", &mut interpreter);
+        assert!(matches!(result, Err(Error::Runtime(_))));
+    }
+
+    #[test]
+    fn interrupts_from_another_thread() {
+        let mut interpreter = Interpreter::new();
+        let interrupt = interpreter.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            interrupt.interrupt();
+        });
+        let result = run("var i = 0; while (true) { i = i + 1; }", &mut interpreter);
+        interrupter.join().unwrap();
+        assert!(matches!(result, Err(Error::Halt(HaltError::Interrupted))));
+        run("i = i + 1;", &mut interpreter).unwrap();
+    }
+
+    #[test]
+    fn deep_recursion_overflows_the_stack() {
+        let mut interpreter = Interpreter::new().with_max_call_depth(8);
+        // Functions point into their run's AST, so each run declares its own.
+        let recurse = |n| format!("fun f(n) {{ if (n > 0) f(n - 1); }} f({n});");
+        // Seven nested calls fill the eight frames with the top-level code.
+        run(&recurse(6), &mut interpreter).unwrap();
+        let result = run(&recurse(7), &mut interpreter);
+        assert!(matches!(result, Err(Error::Runtime(err)) if &*err.message == "Stack overflow."));
+        // Unwinding gave the frames back.
+        run(&recurse(6), &mut interpreter).unwrap();
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_backtrace() {
+        let source = "\
+fun inner() { return nil + 1; }
+fun outer() {
+  inner();
+}
+outer();";
+        let Err(Error::Runtime(err)) = run(source, &mut Interpreter::new()) else {
+            panic!("expected a runtime error");
+        };
+        let trace: Vec<_> = err.trace.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            trace,
+            [
+                "[line 1] in inner()",
+                "[line 3] in outer()",
+                "[line 5] in script"
+            ]
+        );
+
+        let Err(Error::Runtime(err)) = run("nil + 1;", &mut Interpreter::new()) else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(err.trace[0].to_string(), "[line 1] in script");
+    }
+
     #[test]
     fn interpret_unary_bang() -> anyhow::Result<()> {
         let mut ast_arena = AstArena::default();
@@ -1,6 +1,8 @@
 use std::{
     any::Any,
+    cell::RefCell,
     cmp::Ordering,
+    collections::HashMap,
     fmt::{self, Debug, Display, Formatter},
     ops::{Add, Div, Mul, Neg, Not, Sub},
     rc::Rc,
@@ -117,6 +119,8 @@ impl Object {
             Some(callable as &dyn ObjCallable)
         } else if let Ok(callable) = self.try_downcast::<NativeFunction>() {
             Some(callable as &dyn ObjCallable)
+        } else if let Ok(callable) = self.try_downcast::<Class>() {
+            Some(callable as &dyn ObjCallable)
         } else {
             None
         }
@@ -236,6 +240,122 @@ impl Nil for Object {
     }
 }
 
+/// A class value. Cheap to clone, every clone refers to the same class.
+#[derive(Clone)]
+pub struct Class(Rc<ClassData>);
+
+struct ClassData {
+    name: Box<str>,
+    superclass: Option<Class>,
+    methods: HashMap<Box<str>, Function>,
+}
+
+impl Class {
+    pub fn new(
+        name: Box<str>,
+        superclass: Option<Class>,
+        methods: HashMap<Box<str>, Function>,
+    ) -> Self {
+        Self(Rc::new(ClassData {
+            name,
+            superclass,
+            methods,
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Looks up a method on this class, then along the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<&Function> {
+        self.0.methods.get(name).or_else(|| {
+            self.0
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+impl Debug for Class {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Class")
+            .field("name", &self.name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for Class {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
+pub struct Instance {
+    class: Class,
+    fields: RefCell<HashMap<Box<str>, Object>>,
+}
+
+impl Instance {
+    pub fn new(class: Class) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn class(&self) -> &Class {
+        &self.class
+    }
+
+    pub fn field(&self, name: &str) -> Option<Object> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    pub fn set_field(&self, name: Box<str>, value: Object) {
+        self.fields.borrow_mut().insert(name, value);
+    }
+}
+
+impl Debug for Instance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("class", &self.class.name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name())
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Instance {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum OpError {
     #[error("Invalid operand: {}", .0)]
@@ -1,20 +1,48 @@
-use std::fmt::{self, Debug};
+use std::fmt::{self, Debug, Display};
+use std::mem;
+use std::ops::Range;
+
+use report::{FileId, Span};
+use thiserror::Error;
 
 use crate::{
-    debug::{Disassembler, LineInfo},
-    enconding::{Addr, OpCode, OpEncoder},
+    debug::{Disassembler, LineInfo, LocalInfo, SpanInfo},
+    enconding::{LongAddr, MAX_LONG_ADDR, OpCode, OpEncoder},
+    storage::WithStorage,
     value::Value,
 };
 
+mod assemble;
+mod loxc;
+mod relax;
 mod serde;
+mod verify;
+pub use self::assemble::{AssembleError, AssembleErrorKind};
+pub use self::loxc::{
+    CODEGEN_VERSION, FORMAT_VERSION, LoxcHeader, MAGIC, OPCODE_VERSION, SourceInfo,
+};
 pub use self::serde::ChunkIoError;
+pub use self::verify::{MAX_FUNCTION_NESTING, VerifyError};
+
+#[derive(Debug, Error)]
+#[error("too many constants in one chunk (max {MAX_LONG_ADDR})")]
+pub struct TooManyConstants;
 
 #[derive(Default)]
 pub struct Chunk {
     pub(crate) code: Vec<u8>,
     pub(crate) constants: Vec<Value>,
     pub(crate) lines: Vec<LineInfo>,
+    /// Source ranges of the tokens instructions were compiled from, one
+    /// entry per run of instructions sharing a token.
+    pub(crate) spans: Vec<SpanInfo>,
+    /// The file `spans` are in. Like `locals`, not part of the wire format:
+    /// chunks loaded from bytecode are in the default file.
+    pub(crate) file: FileId,
     pub(crate) label: Option<Box<str>>,
+    /// Debug info only: not part of the wire format, so chunks loaded from
+    /// bytecode don't name their locals.
+    pub(crate) locals: Vec<LocalInfo>,
 }
 
 impl Chunk {
@@ -29,6 +57,20 @@ impl Chunk {
         self.code.len() as u64
     }
 
+    /// Heap bytes the chunk's code and tables take, for the collector to
+    /// account to the function owning it.
+    pub(crate) fn owned_bytes(&self) -> usize {
+        fn vec<T>(v: &Vec<T>) -> usize {
+            v.capacity() * mem::size_of::<T>()
+        }
+        vec(&self.code)
+            + vec(&self.constants)
+            + vec(&self.lines)
+            + vec(&self.spans)
+            + vec(&self.locals)
+            + self.label.as_deref().map_or(0, str::len)
+    }
+
     pub fn write(&mut self, instruction: OpCode) {
         self.code
             .encode_op(&instruction)
@@ -56,30 +98,77 @@ impl Chunk {
         };
     }
 
-    pub fn add_constant(&mut self, value: Value) -> Addr {
-        assert!(
-            self.constants.len() < u8::MAX as usize,
-            "can't have more than 255 constants per chunk"
-        );
+    /// Like [`write_with_line`](Self::write_with_line), also recording the
+    /// source `span` the instruction was compiled from.
+    pub fn write_with_span(&mut self, span: Span, instruction: OpCode) {
+        let start_offset = self.current();
+        self.write_with_line(span.line_start, instruction);
+
+        let last_byte_offset = self.current();
+        self.file = span.file;
+        let source = span.start..span.end;
+        match self.spans.last_mut() {
+            Some(info) if info.source == source && info.byte_range.end == start_offset => {
+                info.byte_range.end = last_byte_offset
+            }
+            _ => self.spans.push(SpanInfo {
+                source,
+                byte_range: start_offset..last_byte_offset,
+            }),
+        }
+    }
+
+    /// Addresses past `u8::MAX` need the `*Long` opcode variants.
+    pub fn add_constant(&mut self, value: Value) -> Result<LongAddr, TooManyConstants> {
+        if self.constants.len() >= MAX_LONG_ADDR {
+            return Err(TooManyConstants);
+        }
         self.constants.push(value);
-        (self.constants.len() - 1) as u8
+        Ok((self.constants.len() - 1) as LongAddr)
     }
 
-    pub fn constant(&self, addr: Addr) -> &Value {
+    pub fn constant(&self, addr: LongAddr) -> &Value {
         &self.constants[addr as usize]
     }
 
+    /// The named locals in scope at `byte_offset`.
+    pub fn locals_at(&self, byte_offset: u64) -> impl Iterator<Item = &LocalInfo> {
+        self.locals
+            .iter()
+            .filter(move |info| info.live.contains(&byte_offset))
+    }
+
     pub fn get_line(&self, byte_offset: u64) -> Option<&LineInfo> {
-        let i = self
-            .lines
-            .binary_search_by(|probe| probe.byte_range.start.cmp(&byte_offset))
-            .unwrap_or_else(|i| i.saturating_sub(1));
-        self.lines
-            .get(i)
-            .filter(|info| info.byte_range.contains(&byte_offset))
+        find_run(&self.lines, |info| &info.byte_range, byte_offset)
+    }
+
+    /// The source span of the instruction at `byte_offset`. Chunks that
+    /// weren't compiled from source only know the line.
+    pub fn get_span(&self, byte_offset: u64) -> Option<Span> {
+        let line = self.get_line(byte_offset)?;
+        let span = match find_run(&self.spans, |info| &info.byte_range, byte_offset) {
+            Some(info) => Span {
+                start: info.source.start,
+                end: info.source.end,
+                ..line.to_span()
+            },
+            None => line.to_span(),
+        };
+        Some(Span {
+            file: self.file,
+            ..span
+        })
     }
 }
 
+/// The entry of `runs`, sorted by code range, whose range holds `byte_offset`.
+fn find_run<T>(runs: &[T], range: impl Fn(&T) -> &Range<u64>, byte_offset: u64) -> Option<&T> {
+    let i = runs
+        .binary_search_by(|probe| range(probe).start.cmp(&byte_offset))
+        .unwrap_or_else(|i| i.saturating_sub(1));
+    runs.get(i).filter(|run| range(run).contains(&byte_offset))
+}
+
 impl AsRef<[u8]> for Chunk {
     fn as_ref(&self) -> &[u8] {
         &self.code
@@ -104,3 +193,14 @@ impl Debug for Chunk {
         disassembler.disassemble_chunk()
     }
 }
+
+/// The full listing, nested functions included, in the form
+/// [`Chunk::assemble`] reads back.
+impl Display for WithStorage<'_, Chunk> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.0.label.as_deref().unwrap_or(assemble::SCRIPT_NAME);
+        Disassembler::new(f, self.0, name)
+            .with_storage(self.1)
+            .disassemble_all()
+    }
+}
@@ -0,0 +1,565 @@
+//! Reads the textual listing written by [`Disassembler`] back into a [`Chunk`],
+//! so bytecode can be written by hand and compiler output diffed as text.
+//!
+//! A listing is a `== name ==` header followed by one instruction per line:
+//!
+//! ```text
+//!       == <script> ==
+//! 0000    1 OP_CONSTANT      "hi"[000]
+//! 0002    | OP_PRINT
+//! ```
+//!
+//! The columns are the byte offset, which must match where the instruction
+//! actually lands, the source line (`|` repeats the previous one, `?` leaves
+//! the instruction without one), the opcode and its operands. Every function
+//! constant is followed by its own listing once the chunk referring to it
+//! ends, depth first and in constant order. Constants no instruction refers to
+//! aren't listed and come back as `nil`.
+//!
+//! [`Disassembler`]: crate::debug::Disassembler
+
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Lines;
+
+use lasso::Spur;
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::enconding::{LocalSlot, LongAddr, MAX_LONG_OFFSET, OpCode, UpvalueSlot};
+use crate::object::function::{Capture, LoxFunction};
+use crate::storage::Storage;
+use crate::value::Value;
+
+/// Name the disassembler gives a chunk without a label.
+pub(crate) const SCRIPT_NAME: &str = "<script>";
+
+#[derive(Debug, Error)]
+#[error("line {line}: {kind}")]
+pub struct AssembleError {
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+#[derive(Debug, Error)]
+pub enum AssembleErrorKind {
+    #[error("expected a `== name ==` header")]
+    MissingHeader,
+    #[error("expected the listing of `{0}`")]
+    WrongListing(String),
+    #[error("expected {0}")]
+    Expected(&'static str),
+    #[error("instruction is at offset {actual}, not {written}")]
+    Offset { written: u64, actual: u64 },
+    #[error("unknown instruction `{0}`")]
+    UnknownOp(String),
+    #[error("operand {0} is out of range")]
+    OutOfRange(u64),
+    #[error("malformed constant `{0}`")]
+    Constant(String),
+    #[error("constant [{0}] is given two different values")]
+    ConflictingConstant(LongAddr),
+    #[error("unexpected `{0}`")]
+    Trailing(String),
+}
+
+impl Chunk {
+    /// Assemble a listing written by the disassembler with a `Storage` at hand.
+    /// String constants are interned and functions allocated into `storage`.
+    ///
+    /// The result is not [`verify`](Self::verify)-ed, so tests can build
+    /// chunks the verifier should reject.
+    pub fn assemble(storage: &mut Storage, text: &str) -> Result<Self, AssembleError> {
+        let mut asm = Assembler {
+            storage,
+            lines: text.lines().enumerate().peekable(),
+        };
+        let chunk = asm.listing(None)?;
+        match asm.next_line() {
+            Some((line, text)) => Err(AssembleError {
+                line,
+                kind: AssembleErrorKind::Trailing(text.trim().to_owned()),
+            }),
+            None => Ok(chunk),
+        }
+    }
+}
+
+/// A constant as spelled in the listing, kept to catch one address being
+/// given two different values.
+struct Constant<'t> {
+    text: &'t str,
+    kind: ConstantKind,
+}
+
+enum ConstantKind {
+    Value(Value),
+    /// Built once its listing has been read.
+    Function {
+        name: Spur,
+        arity: u8,
+        captures: Option<Box<[Capture]>>,
+    },
+}
+
+struct Assembler<'t, 's> {
+    storage: &'s mut Storage,
+    lines: Peekable<std::iter::Enumerate<Lines<'t>>>,
+}
+
+impl<'t> Assembler<'t, '_> {
+    /// The next non-blank line and its 1-based number.
+    fn next_line(&mut self) -> Option<(usize, &'t str)> {
+        self.peek_line()?;
+        self.lines.next().map(|(i, text)| (i + 1, text))
+    }
+
+    fn peek_line(&mut self) -> Option<&'t str> {
+        while self
+            .lines
+            .next_if(|(_, text)| text.trim().is_empty())
+            .is_some()
+        {}
+        self.lines.peek().map(|(_, text)| *text)
+    }
+
+    fn listing(&mut self, expected: Option<&str>) -> Result<Chunk, AssembleError> {
+        let (line, header) = self.next_line().ok_or(AssembleError {
+            line: 0,
+            kind: AssembleErrorKind::MissingHeader,
+        })?;
+        let error = |kind| AssembleError { line, kind };
+        let name = parse_header(header).ok_or_else(|| error(AssembleErrorKind::MissingHeader))?;
+        let mut chunk = match expected {
+            Some(expected) if name != expected => {
+                return Err(error(AssembleErrorKind::WrongListing(expected.to_owned())));
+            }
+            None if name != SCRIPT_NAME => Chunk::with_label(name.into()),
+            _ => Chunk::default(),
+        };
+
+        let mut constants = BTreeMap::new();
+        let mut prev_line = None;
+        while self
+            .peek_line()
+            .is_some_and(|text| parse_header(text).is_none())
+        {
+            let (line, text) = self.next_line().expect("peeked above");
+            self.instruction(&mut chunk, &mut constants, &mut prev_line, text)
+                .map_err(|kind| AssembleError { line, kind })?;
+        }
+
+        let len = constants
+            .last_key_value()
+            .map_or(0, |(addr, _)| *addr as usize + 1);
+        chunk.constants = vec![Value::Nil; len];
+        for (addr, constant) in constants {
+            chunk.constants[addr as usize] = match constant.kind {
+                ConstantKind::Value(value) => value,
+                ConstantKind::Function {
+                    name,
+                    arity,
+                    captures,
+                } => {
+                    let nested = self.listing(Some(constant.text))?;
+                    let captures = captures.unwrap_or_default();
+                    let func = LoxFunction::boxed(name, arity, nested, captures);
+                    Value::object(self.storage.add_obj(func))
+                }
+            };
+        }
+        Ok(chunk)
+    }
+
+    fn instruction(
+        &mut self,
+        chunk: &mut Chunk,
+        constants: &mut BTreeMap<LongAddr, Constant<'t>>,
+        prev_line: &mut Option<u32>,
+        text: &'t str,
+    ) -> Result<(), AssembleErrorKind> {
+        let mut rest = text;
+        let written = number(word(&mut rest))?;
+        if written != chunk.current() {
+            return Err(AssembleErrorKind::Offset {
+                written,
+                actual: chunk.current(),
+            });
+        }
+        let line = match word(&mut rest) {
+            "?" => None,
+            "|" => Some(prev_line.ok_or(AssembleErrorKind::Expected("a line number"))?),
+            line => Some(short(number(line)?)?),
+        };
+        let op = self.op(word(&mut rest), &mut rest, constants)?;
+        if !rest.trim().is_empty() {
+            return Err(AssembleErrorKind::Trailing(rest.trim().to_owned()));
+        }
+
+        match line {
+            Some(line) => {
+                *prev_line = Some(line);
+                chunk.write_with_line(line, op);
+            }
+            None => chunk.write(op),
+        }
+        Ok(())
+    }
+
+    fn op(
+        &mut self,
+        verb: &str,
+        rest: &mut &'t str,
+        constants: &mut BTreeMap<LongAddr, Constant<'t>>,
+    ) -> Result<OpCode, AssembleErrorKind> {
+        let mut addr = || self.constant(rest, constants, false);
+        Ok(match verb {
+            "NOOP" => OpCode::NoOp,
+            "OP_RETURN" => OpCode::Ret,
+            "OP_CONSTANT" => OpCode::Constant(short(addr()?.into())?),
+            "OP_NEG" => OpCode::Neg,
+            "OP_ADD" => OpCode::Add,
+            "OP_SUB" => OpCode::Sub,
+            "OP_MUL" => OpCode::Mul,
+            "OP_DIV" => OpCode::Div,
+            "OP_TRUE" => OpCode::True,
+            "OP_FALSE" => OpCode::False,
+            "OP_NIL" => OpCode::Nil,
+            "OP_NOT" => OpCode::Not,
+            "OP_EQUAL" => OpCode::Equal,
+            "OP_GREATER" => OpCode::Greater,
+            "OP_LESS" => OpCode::Less,
+            "OP_PRINT" => OpCode::Print,
+            "OP_POP" => OpCode::Pop,
+            "OP_DEFINE_GLOBAL" => OpCode::DefGlobal(short(addr()?.into())?),
+            "OP_GET_GLOBAL" => OpCode::GetGlobal(short(addr()?.into())?),
+            "OP_SET_GLOBAL" => OpCode::SetGlobal(short(addr()?.into())?),
+            "OP_GET_LOCAL" => OpCode::GetLocal(LocalSlot(short(operand(rest)?)?)),
+            "OP_SET_LOCAL" => OpCode::SetLocal(LocalSlot(short(operand(rest)?)?)),
+            "OP_POPN" => OpCode::PopN(short(operand(rest)?)?),
+            "OP_JMP_IF_FALSE" => OpCode::JmpIfFalse(short(operand(rest)?)?),
+            "OP_JMP" => OpCode::Jmp(short(operand(rest)?)?),
+            "OP_LOOP" => OpCode::Loop(short(operand(rest)?)?),
+            "OP_CALL" => OpCode::Call(short(operand(rest)?)?),
+            "OP_CLOSURE" => OpCode::Closure(short(self.constant(rest, constants, true)?.into())?),
+            "OP_GET_UPVALUE" => OpCode::GetUpvalue(UpvalueSlot(short(operand(rest)?)?)),
+            "OP_SET_UPVALUE" => OpCode::SetUpvalue(UpvalueSlot(short(operand(rest)?)?)),
+            "OP_CLOSE_UPVALUE" => OpCode::CloseUpvalue,
+            "OP_CLASS" => OpCode::Class(short(addr()?.into())?),
+            "OP_GET_PROPERTY" => OpCode::GetProperty(short(addr()?.into())?),
+            "OP_SET_PROPERTY" => OpCode::SetProperty(short(addr()?.into())?),
+            "OP_METHOD" => OpCode::Method(short(addr()?.into())?),
+            "OP_INHERIT" => OpCode::Inherit,
+            "OP_GET_SUPER" => OpCode::GetSuper(short(addr()?.into())?),
+            "OP_CONSTANT_LONG" => OpCode::ConstantLong(addr()?),
+            "OP_DEFINE_GLOBAL_LONG" => OpCode::DefGlobalLong(addr()?),
+            "OP_GET_GLOBAL_LONG" => OpCode::GetGlobalLong(addr()?),
+            "OP_SET_GLOBAL_LONG" => OpCode::SetGlobalLong(addr()?),
+            "OP_CLOSURE_LONG" => OpCode::ClosureLong(self.constant(rest, constants, true)?),
+            "OP_CLASS_LONG" => OpCode::ClassLong(addr()?),
+            "OP_GET_PROPERTY_LONG" => OpCode::GetPropertyLong(addr()?),
+            "OP_SET_PROPERTY_LONG" => OpCode::SetPropertyLong(addr()?),
+            "OP_METHOD_LONG" => OpCode::MethodLong(addr()?),
+            "OP_GET_SUPER_LONG" => OpCode::GetSuperLong(addr()?),
+            "OP_JMP_IF_FALSE_LONG" => OpCode::JmpIfFalseLong(long_offset(rest)?),
+            "OP_JMP_LONG" => OpCode::JmpLong(long_offset(rest)?),
+            "OP_LOOP_LONG" => OpCode::LoopLong(long_offset(rest)?),
+            _ => return Err(AssembleErrorKind::UnknownOp(verb.to_owned())),
+        })
+    }
+
+    /// Parse `<constant>[addr]`, followed by the captures when `closure` is
+    /// set, and record the constant at `addr`.
+    fn constant(
+        &mut self,
+        rest: &mut &'t str,
+        constants: &mut BTreeMap<LongAddr, Constant<'t>>,
+        closure: bool,
+    ) -> Result<LongAddr, AssembleErrorKind> {
+        let (text, mut kind) = self.constant_value(rest)?;
+        let addr = operand(rest)?;
+        if addr > MAX_LONG_OFFSET {
+            return Err(AssembleErrorKind::OutOfRange(addr));
+        }
+        let addr = addr as LongAddr;
+
+        if closure {
+            let ConstantKind::Function { captures, .. } = &mut kind else {
+                return Err(AssembleErrorKind::Expected("a function constant"));
+            };
+            *captures = Some(parse_captures(rest)?);
+        }
+
+        match constants.get_mut(&addr) {
+            None => {
+                constants.insert(addr, Constant { text, kind });
+            }
+            Some(prev) if prev.text != text => {
+                return Err(AssembleErrorKind::ConflictingConstant(addr));
+            }
+            Some(prev) => {
+                if let (
+                    ConstantKind::Function { captures: prev, .. },
+                    ConstantKind::Function {
+                        captures: Some(captures),
+                        ..
+                    },
+                ) = (&mut prev.kind, kind)
+                {
+                    match prev {
+                        Some(prev) if *prev != captures => {
+                            return Err(AssembleErrorKind::ConflictingConstant(addr));
+                        }
+                        _ => *prev = Some(captures),
+                    }
+                }
+            }
+        }
+        Ok(addr)
+    }
+
+    fn constant_value(
+        &mut self,
+        rest: &mut &'t str,
+    ) -> Result<(&'t str, ConstantKind), AssembleErrorKind> {
+        *rest = rest.trim_start();
+        let malformed = |text: &str| AssembleErrorKind::Constant(text.to_owned());
+
+        if rest.starts_with('"') {
+            let (value, len) = unquote(rest).ok_or_else(|| malformed(rest))?;
+            let (text, tail) = rest.split_at(len);
+            *rest = tail;
+            let key = self.storage.intern(&value);
+            return Ok((text, ConstantKind::Value(Value::symbol(key))));
+        }
+
+        if let Some(func) = rest.strip_prefix("<fn ") {
+            let end = func.find('>').ok_or_else(|| malformed(rest))?;
+            let (text, tail) = rest.split_at("<fn ".len() + end + 1);
+            *rest = tail;
+            let (name, arity) = func[..end]
+                .rsplit_once('/')
+                .ok_or_else(|| malformed(text))?;
+            let arity = arity.parse().map_err(|_| malformed(text))?;
+            let kind = ConstantKind::Function {
+                name: self.storage.intern(name),
+                arity,
+                captures: None,
+            };
+            return Ok((text, kind));
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '[')
+            .unwrap_or(rest.len());
+        let (text, tail) = rest.split_at(end);
+        *rest = tail;
+        let value = match text {
+            "nil" => Value::Nil,
+            "true" => Value::boolean(true),
+            "false" => Value::boolean(false),
+            _ => Value::number(text.parse().map_err(|_| malformed(text))?),
+        };
+        Ok((text, ConstantKind::Value(value)))
+    }
+}
+
+fn parse_header(text: &str) -> Option<&str> {
+    text.trim().strip_prefix("== ")?.strip_suffix(" ==")
+}
+
+fn word<'t>(rest: &mut &'t str) -> &'t str {
+    let text = rest.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    let (word, tail) = text.split_at(end);
+    *rest = tail;
+    word
+}
+
+fn number(text: &str) -> Result<u64, AssembleErrorKind> {
+    text.parse()
+        .map_err(|_| AssembleErrorKind::Expected("a number"))
+}
+
+fn short<T: TryFrom<u64>>(n: u64) -> Result<T, AssembleErrorKind> {
+    T::try_from(n).map_err(|_| AssembleErrorKind::OutOfRange(n))
+}
+
+/// Parse a bracketed operand, `[042]`.
+fn operand(rest: &mut &str) -> Result<u64, AssembleErrorKind> {
+    let inner = rest
+        .trim_start()
+        .strip_prefix('[')
+        .and_then(|text| text.split_once(']'));
+    let Some((n, tail)) = inner else {
+        return Err(AssembleErrorKind::Expected("a `[n]` operand"));
+    };
+    *rest = tail;
+    number(n)
+}
+
+fn long_offset(rest: &mut &str) -> Result<u32, AssembleErrorKind> {
+    match operand(rest)? {
+        n if n > MAX_LONG_OFFSET => Err(AssembleErrorKind::OutOfRange(n)),
+        n => Ok(n as u32),
+    }
+}
+
+fn parse_captures(rest: &mut &str) -> Result<Box<[Capture]>, AssembleErrorKind> {
+    let mut captures = Vec::new();
+    while rest.trim_start().starts_with('[') {
+        let text = rest.trim_start();
+        let (inner, tail) = text[1..]
+            .split_once(']')
+            .ok_or(AssembleErrorKind::Expected(
+                "a `[local n]` or `[upvalue n]` capture",
+            ))?;
+        *rest = tail;
+        let capture = match inner.split_once(' ') {
+            Some(("local", slot)) => Capture::Local(LocalSlot(short(number(slot)?)?)),
+            Some(("upvalue", slot)) => Capture::Upvalue(UpvalueSlot(short(number(slot)?)?)),
+            _ => {
+                return Err(AssembleErrorKind::Expected(
+                    "a `[local n]` or `[upvalue n]` capture",
+                ));
+            }
+        };
+        captures.push(capture);
+    }
+    Ok(captures.into())
+}
+
+/// Undo the escaping `{:?}` applies to a string. Returns the string and the
+/// length of its quoted form.
+fn unquote(text: &str) -> Option<(String, usize)> {
+    let mut chars = text.char_indices().skip(1);
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, i + 1)),
+            '\\' => value.push(match chars.next()?.1 {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                c @ ('\\' | '"' | '\'') => c,
+                'u' => {
+                    let (_, '{') = chars.next()? else {
+                        return None;
+                    };
+                    let hex: String = chars
+                        .by_ref()
+                        .map(|(_, c)| c)
+                        .take_while(|&c| c != '}')
+                        .collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                _ => return None,
+            }),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::WithStorage;
+
+    fn compile(storage: &mut Storage, src: &str) -> Chunk {
+        let mut err = std::io::stderr();
+        crate::compiler::Compiler::new(
+            lexer::Scanner::new(src),
+            &mut report::Reporter::new(src, &mut err),
+            storage,
+            &(),
+        )
+        .compile()
+        .unwrap()
+    }
+
+    fn listing(storage: &Storage, chunk: &Chunk) -> String {
+        WithStorage(chunk, storage).to_string()
+    }
+
+    #[test]
+    fn round_trips_compiled_programs() {
+        let src = r#"
+            var greeting = "C:\[dir]
+            ";
+            fun outer(x) {
+                var y = x;
+                fun inner() { return x + y; }
+                return inner;
+            }
+            class A { init(n) { this.n = n; } get() { return this.n; } }
+            class B < A { get() { return super.get() + 1; } }
+            for (var i = 0; i < 3; i = i + 1) {
+                if (i == 1) continue;
+                print outer(i)() and B(i).get() or -0.5;
+            }
+        "#;
+        let mut storage = Storage::new();
+        let chunk = compile(&mut storage, src);
+        let text = listing(&storage, &chunk);
+
+        let assembled = Chunk::assemble(&mut storage, &text).unwrap();
+        assert_eq!(assembled.code, chunk.code);
+        assert_eq!(assembled.lines, chunk.lines);
+        assert_eq!(listing(&storage, &assembled), text);
+        assembled.verify().unwrap();
+    }
+
+    #[test]
+    fn assembles_hand_written_bytecode() {
+        let text = r#"
+            == <script> ==
+            0000    1 OP_CONSTANT      "a b[c]" [000]
+            0002    | OP_CONSTANT      2 [001]
+            0004    2 OP_POP
+            0005    | OP_PRINT
+            0006    ? OP_NIL
+            0007    ? OP_RETURN
+        "#;
+        let mut storage = Storage::new();
+        let chunk = Chunk::assemble(&mut storage, text).unwrap();
+        assert_eq!(chunk.constant(0).as_str(&storage), "a b[c]");
+        assert_eq!(chunk.constant(1), &Value::number(2.0));
+        assert_eq!(chunk.get_line(2).map(|info| info.line), Some(1));
+        assert_eq!(chunk.get_line(4).map(|info| info.line), Some(2));
+        assert!(chunk.get_line(6).is_none());
+    }
+
+    #[test]
+    fn reports_where_listings_go_wrong() {
+        let mut storage = Storage::new();
+        let mut assemble = |text| Chunk::assemble(&mut storage, text).unwrap_err();
+
+        let err = assemble("== <script> ==\n0000 1 OP_NIL\n0002 | OP_RETURN");
+        assert_eq!(err.line, 3);
+        assert!(matches!(
+            err.kind,
+            AssembleErrorKind::Offset {
+                written: 2,
+                actual: 1
+            }
+        ));
+
+        let err = assemble("== <script> ==\n0000 1 OP_FROB");
+        assert!(matches!(err.kind, AssembleErrorKind::UnknownOp(op) if op == "OP_FROB"));
+
+        let err =
+            assemble("== <script> ==\n0000 1 OP_CONSTANT 1 [000]\n0002 | OP_CONSTANT 2 [000]");
+        assert!(matches!(
+            err.kind,
+            AssembleErrorKind::ConflictingConstant(0)
+        ));
+
+        let err = assemble("== <script> ==\n0000 1 OP_CLOSURE <fn f/0>[000]\n== <fn g/0> ==");
+        assert!(matches!(err.kind, AssembleErrorKind::WrongListing(name) if name == "<fn f/0>"));
+
+        let err = assemble("== <script> ==\n0000 1 OP_POPN [00256]");
+        assert!(matches!(err.kind, AssembleErrorKind::OutOfRange(256)));
+    }
+}
@@ -0,0 +1,206 @@
+//! The `.loxc` container: a versioned header in front of a serialized chunk.
+//!
+//! Layout: [`MAGIC`], [`FORMAT_VERSION`] as a little-endian `u16`, the
+//! postcard-encoded [`LoxcHeader`], then the chunk payload. The magic and
+//! format version have a fixed layout so that files written by any future
+//! header shape can still be told apart and rejected cleanly.
+
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::ChunkIoError;
+
+pub const MAGIC: [u8; 4] = *b"LOXC";
+/// Version of the container layout and the chunk wire format.
+pub const FORMAT_VERSION: u16 = 3;
+/// Version of the instruction set. Bump whenever an `OpCode` is added or its
+/// encoding changes.
+pub const OPCODE_VERSION: u16 = 1;
+/// Version of the code the compiler generates. Bump whenever the same source
+/// would compile to different bytecode, including with every bump of
+/// [`OPCODE_VERSION`], so that cached images from older builds go stale.
+pub const CODEGEN_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoxcHeader {
+    pub opcode_version: u16,
+    pub codegen_version: u16,
+    /// The source file the chunk was compiled from, if any.
+    pub source: Option<SourceInfo>,
+    /// Hash of the payload following the header.
+    pub checksum: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceInfo {
+    pub path: String,
+    /// Hash of the source text.
+    pub hash: u64,
+}
+
+impl SourceInfo {
+    pub fn new(path: impl Into<String>, text: &str) -> Self {
+        Self {
+            path: path.into(),
+            hash: fnv1a(text.as_bytes()),
+        }
+    }
+
+    /// Whether `text` is still the source this was compiled from.
+    pub fn matches(&self, text: &str) -> bool {
+        self.hash == fnv1a(text.as_bytes())
+    }
+}
+
+impl LoxcHeader {
+    fn new(source: Option<SourceInfo>, payload: &[u8]) -> Self {
+        Self {
+            opcode_version: OPCODE_VERSION,
+            codegen_version: CODEGEN_VERSION,
+            source,
+            checksum: fnv1a(payload),
+        }
+    }
+
+    /// Validate the container around a `.loxc` image and split off its
+    /// payload. Checks everything but the payload's contents.
+    pub fn parse(bytes: &[u8]) -> Result<(Self, &[u8]), ChunkIoError> {
+        let rest = bytes.strip_prefix(&MAGIC).ok_or(ChunkIoError::BadMagic)?;
+        let (version, rest) = rest.split_first_chunk().ok_or(ChunkIoError::Truncated)?;
+        let version = u16::from_le_bytes(*version);
+        if version != FORMAT_VERSION {
+            return Err(ChunkIoError::FormatVersion {
+                found: version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        let (header, payload) = postcard::take_from_bytes::<LoxcHeader>(rest)?;
+        if header.opcode_version != OPCODE_VERSION {
+            return Err(ChunkIoError::OpcodeVersion {
+                found: header.opcode_version,
+                expected: OPCODE_VERSION,
+            });
+        }
+        if header.checksum != fnv1a(payload) {
+            return Err(ChunkIoError::Checksum);
+        }
+        Ok((header, payload))
+    }
+
+    /// Whether a cached image with this header can stand in for compiling
+    /// `text` with this build.
+    pub fn is_fresh_for(&self, text: &str) -> bool {
+        self.codegen_version == CODEGEN_VERSION
+            && self.source.as_ref().is_some_and(|src| src.matches(text))
+    }
+}
+
+/// Wrap `payload` in a container and write it out.
+pub(super) fn write<W: Write>(
+    writer: &mut W,
+    source: Option<SourceInfo>,
+    payload: &[u8],
+) -> Result<(), ChunkIoError> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    postcard::to_io(&LoxcHeader::new(source, payload), &mut *writer)?;
+    writer.write_all(payload)?;
+    Ok(())
+}
+
+/// 64-bit FNV-1a. Written out rather than borrowed from a hashing crate since
+/// the result is persisted and must never change between builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(source: Option<SourceInfo>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, source, b"payload").unwrap();
+        bytes
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let source = SourceInfo::new("main.lox", "print 1;");
+        let bytes = image(Some(source.clone()));
+        let (header, payload) = LoxcHeader::parse(&bytes).unwrap();
+        assert_eq!(payload, b"payload");
+        assert_eq!(header.source, Some(source));
+        assert_eq!(header.codegen_version, CODEGEN_VERSION);
+    }
+
+    #[test]
+    fn rejects_foreign_and_stale_files() {
+        assert!(matches!(
+            LoxcHeader::parse(b"#!/usr/bin/env lox"),
+            Err(ChunkIoError::BadMagic)
+        ));
+        assert!(matches!(
+            LoxcHeader::parse(b"LOXC"),
+            Err(ChunkIoError::Truncated)
+        ));
+
+        let mut bytes = image(None);
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            LoxcHeader::parse(&bytes),
+            Err(ChunkIoError::FormatVersion { found, .. }) if found == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_other_instruction_sets() {
+        let header = LoxcHeader {
+            opcode_version: OPCODE_VERSION + 1,
+            ..LoxcHeader::new(None, b"payload")
+        };
+        let mut bytes = [&MAGIC[..], &FORMAT_VERSION.to_le_bytes()].concat();
+        bytes.extend(postcard::to_stdvec(&header).unwrap());
+        bytes.extend(b"payload");
+        assert!(matches!(
+            LoxcHeader::parse(&bytes),
+            Err(ChunkIoError::OpcodeVersion { found, .. }) if found == OPCODE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupted_payloads() {
+        let mut bytes = image(None);
+        *bytes.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(
+            LoxcHeader::parse(&bytes),
+            Err(ChunkIoError::Checksum)
+        ));
+    }
+
+    #[test]
+    fn freshness_tracks_source_text() {
+        let bytes = image(Some(SourceInfo::new("main.lox", "print 1;")));
+        let (header, _) = LoxcHeader::parse(&bytes).unwrap();
+        assert!(header.is_fresh_for("print 1;"));
+        assert!(!header.is_fresh_for("print 2;"));
+
+        let (anonymous, _) = LoxcHeader::parse(&image(None)).unwrap();
+        assert!(!anonymous.is_fresh_for("print 1;"));
+    }
+
+    #[test]
+    fn freshness_tracks_codegen() {
+        let older = LoxcHeader {
+            codegen_version: CODEGEN_VERSION - 1,
+            ..LoxcHeader::new(Some(SourceInfo::new("main.lox", "print 1;")), b"payload")
+        };
+        assert!(!older.is_fresh_for("print 1;"));
+    }
+}
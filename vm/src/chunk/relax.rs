@@ -0,0 +1,215 @@
+//! Jump relaxation: re-encoding a finished chunk's jumps in their short form
+//! wherever the distance fits.
+//!
+//! The compiler emits every forward jump long, since the code it jumps over
+//! isn't compiled yet. Shortening one as it's patched would move code that
+//! pending jumps and the line tables already point into, so instead the
+//! whole chunk is re-encoded once it's done, and every offset into it remapped.
+
+use std::io::Cursor;
+
+use crate::chunk::Chunk;
+use crate::enconding::{LongOffset, Offset, OpCode, OpDecoder, OpEncoder};
+
+/// Bytes of a jump with a 16-bit distance, and of one with a 24-bit distance.
+const SHORT: u64 = 3;
+const LONG: u64 = 4;
+
+/// A jump's direction and the index of the instruction it lands on.
+#[derive(Clone, Copy)]
+struct Jump {
+    back: bool,
+    conditional: bool,
+    target: usize,
+}
+
+impl Chunk {
+    /// Re-encode every jump and loop in the shortest form that reaches its
+    /// target, moving the code, line, span and local tables along.
+    pub(crate) fn shorten_jumps(&mut self) {
+        let mut decoder = Cursor::new(self.code.as_slice());
+        let mut ops = Vec::new();
+        loop {
+            let offset = decoder.position();
+            match decoder.decode_op::<OpCode>() {
+                Ok(Some(op)) => ops.push((offset, op)),
+                Ok(None) => break,
+                Err(e) => unreachable!("compiled code failed to decode: {e}"),
+            }
+        }
+        // Offset of each instruction, then of the end of the code.
+        let old: Vec<u64> = ops
+            .iter()
+            .map(|&(offset, _)| offset)
+            .chain([self.current()])
+            .collect();
+        let index = |offset: u64| {
+            old.binary_search(&offset)
+                .expect("compiled jumps land on instructions")
+        };
+        let jumps: Vec<Option<Jump>> = ops
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, op))| {
+                let (back, conditional, distance) = match op {
+                    OpCode::Jmp(d) => (false, false, d as u64),
+                    OpCode::JmpLong(d) => (false, false, d as u64),
+                    OpCode::JmpIfFalse(d) => (false, true, d as u64),
+                    OpCode::JmpIfFalseLong(d) => (false, true, d as u64),
+                    OpCode::Loop(d) => (true, false, d as u64),
+                    OpCode::LoopLong(d) => (true, false, d as u64),
+                    _ => return None,
+                };
+                let target = match back {
+                    true => old[i + 1] - distance,
+                    false => old[i + 1] + distance,
+                };
+                Some(Jump {
+                    back,
+                    conditional,
+                    target: index(target),
+                })
+            })
+            .collect();
+
+        // Start with every jump short and lengthen those that don't reach
+        // until none change. Lengthening only ever moves targets further
+        // away, so this settles.
+        let mut long = vec![false; ops.len()];
+        let new = loop {
+            let mut new = Vec::with_capacity(old.len());
+            let mut offset = 0;
+            for (i, (start, end)) in old.iter().zip(&old[1..]).enumerate() {
+                new.push(offset);
+                offset += match (jumps[i], long[i]) {
+                    (Some(_), false) => SHORT,
+                    (Some(_), true) => LONG,
+                    (None, _) => end - start,
+                };
+            }
+            new.push(offset);
+
+            let mut settled = true;
+            for (i, jump) in jumps.iter().enumerate() {
+                if let Some(jump) = jump
+                    && !long[i]
+                    && new[i + 1].abs_diff(new[jump.target]) > Offset::MAX as u64
+                {
+                    long[i] = true;
+                    settled = false;
+                }
+            }
+            if settled {
+                break new;
+            }
+        };
+
+        let mut code = Vec::with_capacity(new[ops.len()] as usize);
+        for (i, &(_, op)) in ops.iter().enumerate() {
+            let op = match jumps[i] {
+                Some(jump) => {
+                    let distance = new[i + 1].abs_diff(new[jump.target]);
+                    match (jump.back, jump.conditional, long[i]) {
+                        (true, _, false) => OpCode::Loop(distance as Offset),
+                        (true, _, true) => OpCode::LoopLong(distance as LongOffset),
+                        (false, false, false) => OpCode::Jmp(distance as Offset),
+                        (false, false, true) => OpCode::JmpLong(distance as LongOffset),
+                        (false, true, false) => OpCode::JmpIfFalse(distance as Offset),
+                        (false, true, true) => OpCode::JmpIfFalseLong(distance as LongOffset),
+                    }
+                }
+                None => op,
+            };
+            code.encode_op(&op).expect("writing to a Vec can't fail");
+        }
+        self.code = code;
+
+        // Locals in scope to the end of the function end at `u64::MAX`.
+        let remap = |offset: u64| match offset {
+            u64::MAX => u64::MAX,
+            offset => new[index(offset)],
+        };
+        for info in &mut self.lines {
+            info.byte_range = remap(info.byte_range.start)..remap(info.byte_range.end);
+        }
+        for info in &mut self.spans {
+            info.byte_range = remap(info.byte_range.start)..remap(info.byte_range.end);
+        }
+        for info in &mut self.locals {
+            info.live = remap(info.live.start)..remap(info.live.end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn compile(src: &str) -> Chunk {
+        let mut err = Vec::new();
+        crate::compiler::Compiler::new(
+            lexer::Scanner::new(src),
+            &mut report::Reporter::new(src, &mut err),
+            &mut Storage::new(),
+            &(),
+        )
+        .compile()
+        .unwrap()
+    }
+
+    fn ops(chunk: &Chunk) -> Vec<OpCode> {
+        let mut decoder = Cursor::new(chunk.code.as_slice());
+        std::iter::from_fn(|| decoder.decode_op::<OpCode>().unwrap()).collect()
+    }
+
+    fn is_long_jump(op: &OpCode) -> bool {
+        matches!(
+            op,
+            OpCode::JmpLong(_) | OpCode::JmpIfFalseLong(_) | OpCode::LoopLong(_)
+        )
+    }
+
+    #[test]
+    fn shortens_jumps_that_fit() {
+        let src = "var i = 0;
+            while (i < 3) {
+                if (i == 1 and true or false) print i; else print -i;
+                i = i + 1;
+            }
+            print \"done\";";
+        let chunk = compile(src);
+        assert!(!ops(&chunk).iter().any(is_long_jump));
+        chunk.verify().unwrap();
+        // Instructions keep their lines as they move.
+        let mut decoder = Cursor::new(chunk.code.as_slice());
+        let mut print_lines = Vec::new();
+        while let (offset, Some(op)) = (decoder.position(), decoder.decode_op().unwrap()) {
+            if matches!(op, OpCode::Print) {
+                print_lines.push(chunk.get_line(offset).unwrap().line);
+            }
+        }
+        assert_eq!(print_lines, [3, 3, 6]);
+
+        let mut out = Vec::new();
+        crate::run_with(
+            src.to_owned(),
+            &mut crate::vm::VirtualMachine::default(),
+            &mut out,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "-0\n1\n-2\ndone\n");
+    }
+
+    #[test]
+    fn keeps_jumps_that_do_not_fit_long() {
+        let body = "print 1;".repeat(Offset::MAX as usize / 3 + 1);
+        let chunk = compile(&format!("if (true) {{ {body} }} print 2;"));
+        let ops = ops(&chunk);
+        assert!(matches!(ops[1], OpCode::JmpIfFalseLong(_)));
+        // The jump over the `else` only skips a `Pop`.
+        assert!(ops.iter().any(|op| matches!(op, OpCode::Jmp(1))));
+        chunk.verify().unwrap();
+    }
+}
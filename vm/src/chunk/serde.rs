@@ -4,16 +4,23 @@
 //! is an interner key, `Value::Object` a heap pointer), so the wire form can't
 //! be `#[derive]`d. Serialization borrows `&Storage` to inline the text;
 //! deserialization threads `&mut Storage` via [`DeserializeSeed`] to re-intern it.
+//! Function constants carry a whole nested [`Chunk`] and are rebuilt the same way.
+//!
+//! Span runs are written relative to the run before them, which postcard's
+//! varints then store in a few bytes each.
 
 use std::fmt;
 use std::io::{Read, Write};
 
+use report::FileId;
 use serde::de::{DeserializeSeed, EnumAccess, Error as _, SeqAccess, VariantAccess, Visitor};
 use serde::ser::{Error as _, SerializeSeq, SerializeStruct};
-use serde::{Deserializer, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
-use crate::chunk::Chunk;
+use crate::chunk::{Chunk, LoxcHeader, MAX_FUNCTION_NESTING, SourceInfo, VerifyError, loxc};
+use crate::debug::SpanInfo;
+use crate::object::function::{Capture, LoxFunction};
 use crate::object::{ObjKind, string::LoxString};
 use crate::storage::{Storage, WithStorage};
 use crate::value::Value;
@@ -24,10 +31,22 @@ pub enum ChunkIoError {
     Io(#[from] std::io::Error),
     #[error("(de)serialization error: {0}")]
     Postcard(#[from] postcard::Error),
+    #[error("invalid bytecode: {0}")]
+    Verify(#[from] VerifyError),
+    #[error("not a .loxc file")]
+    BadMagic,
+    #[error("truncated .loxc header")]
+    Truncated,
+    #[error("unsupported .loxc format version {found} (expected {expected})")]
+    FormatVersion { found: u16, expected: u16 },
+    #[error("bytecode targets instruction set version {found} (expected {expected})")]
+    OpcodeVersion { found: u16, expected: u16 },
+    #[error("corrupted .loxc: checksum mismatch")]
+    Checksum,
 }
 
 impl Chunk {
-    /// Serialize this chunk to `writer` in postcard format. `storage` resolves
+    /// Serialize this chunk to `writer` as a `.loxc` image. `storage` resolves
     /// the chunk's interned/heap string constants, so pass the `Storage` it was
     /// compiled against.
     pub fn serialize<W: Write>(
@@ -35,8 +54,19 @@ impl Chunk {
         storage: &Storage,
         writer: &mut W,
     ) -> Result<(), ChunkIoError> {
-        postcard::to_io(&WithStorage(self, storage), writer)?;
-        Ok(())
+        self.serialize_with_source(storage, None, writer)
+    }
+
+    /// Like [`serialize`](Self::serialize), recording the source the chunk was
+    /// compiled from so caches can tell when the image goes stale.
+    pub fn serialize_with_source<W: Write>(
+        &self,
+        storage: &Storage,
+        source: Option<SourceInfo>,
+        writer: &mut W,
+    ) -> Result<(), ChunkIoError> {
+        let payload = postcard::to_stdvec(&WithStorage(self, storage))?;
+        loxc::write(writer, source, &payload)
     }
 
     /// Load a chunk written by [`serialize`](Self::serialize). Its string
@@ -51,9 +81,15 @@ impl Chunk {
     /// Load a chunk from an in-memory image. Prefer over [`load`](Self::load) when
     /// the bytes are already resident (e.g. an `mmap`ed file): strings are borrowed
     /// from `bytes`, so only the interned/heap copies allocate.
+    ///
+    /// The image is untrusted, so the chunk is [`verify`](Self::verify)-ed
+    /// before it's handed out.
     pub fn from_bytes(storage: &mut Storage, bytes: &[u8]) -> Result<Self, ChunkIoError> {
-        let mut de = postcard::Deserializer::from_bytes(bytes);
-        Ok(ChunkSeed(storage).deserialize(&mut de)?)
+        let (_, payload) = LoxcHeader::parse(bytes)?;
+        let mut de = postcard::Deserializer::from_bytes(payload);
+        let chunk = ChunkSeed(storage, 0).deserialize(&mut de)?;
+        chunk.verify()?;
+        Ok(chunk)
     }
 }
 
@@ -69,18 +105,70 @@ impl Serialize for Bytes<'_> {
 impl Serialize for WithStorage<'_, Chunk> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let (chunk, storage) = (self.0, self.1);
-        let mut s = serializer.serialize_struct("Chunk", 4)?;
+        let mut s = serializer.serialize_struct("Chunk", 5)?;
         s.serialize_field("code", &Bytes(&chunk.code))?;
         s.serialize_field(
             "constants",
             &WithStorage(chunk.constants.as_slice(), storage),
         )?;
         s.serialize_field("lines", &chunk.lines)?;
+        s.serialize_field("spans", &SpanDelta::encode(&chunk.spans))?;
         s.serialize_field("label", &chunk.label)?;
         s.end()
     }
 }
 
+/// A [`SpanInfo`] relative to the run before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct SpanDelta {
+    /// Code bytes between the end of the previous run and this one.
+    gap: u64,
+    len: u64,
+    /// Source offset from the start of the previous run's source.
+    start: i64,
+    source_len: u64,
+}
+
+impl SpanDelta {
+    fn encode(spans: &[SpanInfo]) -> Vec<SpanDelta> {
+        let mut prev = SpanInfo {
+            source: 0..0,
+            byte_range: 0..0,
+        };
+        spans
+            .iter()
+            .map(|info| {
+                let delta = SpanDelta {
+                    gap: info.byte_range.start - prev.byte_range.end,
+                    len: info.byte_range.end - info.byte_range.start,
+                    start: info.source.start as i64 - prev.source.start as i64,
+                    source_len: (info.source.end - info.source.start) as u64,
+                };
+                prev = info.clone();
+                delta
+            })
+            .collect()
+    }
+
+    /// `None` if the deltas overflow, i.e. the table is corrupted.
+    fn decode(deltas: &[SpanDelta]) -> Option<Vec<SpanInfo>> {
+        let (mut code, mut source) = (0u64, 0usize);
+        deltas
+            .iter()
+            .map(|delta| {
+                let start = code.checked_add(delta.gap)?;
+                code = start.checked_add(delta.len)?;
+                source = source.checked_add_signed(delta.start.try_into().ok()?)?;
+                let end = source.checked_add(delta.source_len.try_into().ok()?)?;
+                Some(SpanInfo {
+                    source: source..end,
+                    byte_range: start..code,
+                })
+            })
+            .collect()
+    }
+}
+
 impl Serialize for WithStorage<'_, [Value]> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         let (values, storage) = (self.0, self.1);
@@ -107,6 +195,16 @@ impl Serialize for WithStorage<'_, Value> {
             Value::Object(obj) if obj.kind() == ObjKind::String => {
                 serializer.serialize_newtype_variant(NAME, 4, "Str", obj.as_str())
             }
+            Value::Object(obj) if obj.kind() == ObjKind::Function => {
+                // SAFETY: matched kind witnesses the dynamic type.
+                let func = unsafe { obj.downcast_ref::<LoxFunction>() };
+                serializer.serialize_newtype_variant(
+                    NAME,
+                    5,
+                    "Function",
+                    &WithStorage(func, storage),
+                )
+            }
             Value::Object(_) => Err(S::Error::custom(
                 "constant pool holds a non-string object that cannot be serialized",
             )),
@@ -114,13 +212,27 @@ impl Serialize for WithStorage<'_, Value> {
     }
 }
 
-struct ChunkSeed<'a>(&'a mut Storage);
+impl Serialize for WithStorage<'_, LoxFunction> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (func, storage) = (self.0, self.1);
+        let mut s = serializer.serialize_struct("Function", 4)?;
+        s.serialize_field("name", storage.resolve(func.name))?;
+        s.serialize_field("arity", &func.arity)?;
+        s.serialize_field("captures", &func.captures)?;
+        s.serialize_field("chunk", &WithStorage(&func.chunk, storage))?;
+        s.end()
+    }
+}
+
+/// Seeds for the parts of a chunk also carry how many functions enclose it,
+/// so untrusted nesting can't recurse without bound.
+struct ChunkSeed<'a>(&'a mut Storage, usize);
 
 impl<'de> DeserializeSeed<'de> for ChunkSeed<'_> {
     type Value = Chunk;
 
     fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Chunk, D::Error> {
-        struct ChunkVisitor<'a>(&'a mut Storage);
+        struct ChunkVisitor<'a>(&'a mut Storage, usize);
 
         impl<'de> Visitor<'de> for ChunkVisitor<'_> {
             type Value = Chunk;
@@ -133,31 +245,37 @@ impl<'de> DeserializeSeed<'de> for ChunkSeed<'_> {
                 let missing = |field| A::Error::custom(format!("Chunk: missing `{field}`"));
                 let code: &[u8] = seq.next_element()?.ok_or_else(|| missing("code"))?;
                 let constants = seq
-                    .next_element_seed(ConstantsSeed(self.0))?
+                    .next_element_seed(ConstantsSeed(self.0, self.1))?
                     .ok_or_else(|| missing("constants"))?;
                 let lines = seq.next_element()?.ok_or_else(|| missing("lines"))?;
+                let spans: Vec<SpanDelta> = seq.next_element()?.ok_or_else(|| missing("spans"))?;
+                let spans = SpanDelta::decode(&spans)
+                    .ok_or_else(|| A::Error::custom("Chunk: corrupted span table"))?;
                 let label = seq.next_element()?.ok_or_else(|| missing("label"))?;
                 Ok(Chunk {
                     code: code.to_vec(),
                     constants,
                     lines,
+                    spans,
+                    file: FileId::default(),
                     label,
+                    locals: Vec::new(),
                 })
             }
         }
 
-        const FIELDS: &[&str] = &["code", "constants", "lines", "label"];
-        deserializer.deserialize_struct("Chunk", FIELDS, ChunkVisitor(self.0))
+        const FIELDS: &[&str] = &["code", "constants", "lines", "spans", "label"];
+        deserializer.deserialize_struct("Chunk", FIELDS, ChunkVisitor(self.0, self.1))
     }
 }
 
-struct ConstantsSeed<'a>(&'a mut Storage);
+struct ConstantsSeed<'a>(&'a mut Storage, usize);
 
 impl<'de> DeserializeSeed<'de> for ConstantsSeed<'_> {
     type Value = Vec<Value>;
 
     fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Value>, D::Error> {
-        struct ConstantsVisitor<'a>(&'a mut Storage);
+        struct ConstantsVisitor<'a>(&'a mut Storage, usize);
 
         impl<'de> Visitor<'de> for ConstantsVisitor<'_> {
             type Value = Vec<Value>;
@@ -169,24 +287,24 @@ impl<'de> DeserializeSeed<'de> for ConstantsSeed<'_> {
             fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Value>, A::Error> {
                 let mut constants = Vec::with_capacity(seq.size_hint().unwrap_or(0));
                 // Reborrow `self.0` each round so the seed can be handed out repeatedly.
-                while let Some(value) = seq.next_element_seed(ValueSeed(&mut *self.0))? {
+                while let Some(value) = seq.next_element_seed(ValueSeed(&mut *self.0, self.1))? {
                     constants.push(value);
                 }
                 Ok(constants)
             }
         }
 
-        deserializer.deserialize_seq(ConstantsVisitor(self.0))
+        deserializer.deserialize_seq(ConstantsVisitor(self.0, self.1))
     }
 }
 
-struct ValueSeed<'a>(&'a mut Storage);
+struct ValueSeed<'a>(&'a mut Storage, usize);
 
 impl<'de> DeserializeSeed<'de> for ValueSeed<'_> {
     type Value = Value;
 
     fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
-        struct ValueVisitor<'a>(&'a mut Storage);
+        struct ValueVisitor<'a>(&'a mut Storage, usize);
 
         impl<'de> Visitor<'de> for ValueVisitor<'_> {
             type Value = Value;
@@ -212,6 +330,14 @@ impl<'de> DeserializeSeed<'de> for ValueSeed<'_> {
                         let text: &str = payload.newtype_variant()?;
                         Value::Object(self.0.add_obj(LoxString::boxed(text)))
                     }
+                    5 => {
+                        if self.1 == MAX_FUNCTION_NESTING {
+                            return Err(A::Error::custom(VerifyError::TooDeep));
+                        }
+                        let func =
+                            payload.newtype_variant_seed(FunctionSeed(self.0, self.1 + 1))?;
+                        Value::Object(self.0.add_obj(func))
+                    }
                     other => {
                         return Err(A::Error::custom(format!("unknown Value variant {other}")));
                     }
@@ -219,8 +345,45 @@ impl<'de> DeserializeSeed<'de> for ValueSeed<'_> {
             }
         }
 
-        const VARIANTS: &[&str] = &["Nil", "Boolean", "Number", "Symbol", "Str"];
-        deserializer.deserialize_enum("Value", VARIANTS, ValueVisitor(self.0))
+        const VARIANTS: &[&str] = &["Nil", "Boolean", "Number", "Symbol", "Str", "Function"];
+        deserializer.deserialize_enum("Value", VARIANTS, ValueVisitor(self.0, self.1))
+    }
+}
+
+struct FunctionSeed<'a>(&'a mut Storage, usize);
+
+impl<'de> DeserializeSeed<'de> for FunctionSeed<'_> {
+    type Value = Box<LoxFunction>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Box<LoxFunction>, D::Error> {
+        struct FunctionVisitor<'a>(&'a mut Storage, usize);
+
+        impl<'de> Visitor<'de> for FunctionVisitor<'_> {
+            type Value = Box<LoxFunction>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a serialized Function")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let missing = |field| A::Error::custom(format!("Function: missing `{field}`"));
+                let name: &str = seq.next_element()?.ok_or_else(|| missing("name"))?;
+                let name = self.0.intern(name);
+                let arity = seq.next_element()?.ok_or_else(|| missing("arity"))?;
+                let captures: Box<[Capture]> =
+                    seq.next_element()?.ok_or_else(|| missing("captures"))?;
+                let chunk = seq
+                    .next_element_seed(ChunkSeed(self.0, self.1))?
+                    .ok_or_else(|| missing("chunk"))?;
+                Ok(LoxFunction::boxed(name, arity, chunk, captures))
+            }
+        }
+
+        const FIELDS: &[&str] = &["name", "arity", "captures", "chunk"];
+        deserializer.deserialize_struct("Function", FIELDS, FunctionVisitor(self.0, self.1))
     }
 }
 
@@ -233,10 +396,12 @@ mod tests {
     /// Build a small valid program: `Constant 1.5; DefGlobal foo; Ret`.
     fn sample(storage: &mut Storage) -> Chunk {
         let mut chunk = Chunk::with_label("sample".into());
-        let num = chunk.add_constant(Value::number(1.5));
-        let foo = chunk.add_constant(Value::symbol(storage.intern("foo")));
-        chunk.write_with_line(1, OpCode::Constant(num));
-        chunk.write_with_line(1, OpCode::DefGlobal(foo));
+        let num = chunk.add_constant(Value::number(1.5)).unwrap();
+        let foo = chunk
+            .add_constant(Value::symbol(storage.intern("foo")))
+            .unwrap();
+        chunk.write_with_line(1, OpCode::Constant(num.try_into().unwrap()));
+        chunk.write_with_line(1, OpCode::DefGlobal(foo.try_into().unwrap()));
         chunk.write_with_line(2, OpCode::Ret);
         chunk
     }
@@ -268,11 +433,15 @@ mod tests {
     fn round_trips_all_constant_kinds() {
         let mut src = Storage::new();
         let mut chunk = Chunk::default();
-        chunk.add_constant(Value::Nil);
-        chunk.add_constant(Value::boolean(true));
-        chunk.add_constant(Value::number(2.5));
-        chunk.add_constant(Value::symbol(src.intern("name")));
-        chunk.add_constant(Value::Object(src.add_obj(LoxString::boxed("hello"))));
+        chunk.add_constant(Value::Nil).unwrap();
+        chunk.add_constant(Value::boolean(true)).unwrap();
+        chunk.add_constant(Value::number(2.5)).unwrap();
+        chunk
+            .add_constant(Value::symbol(src.intern("name")))
+            .unwrap();
+        chunk
+            .add_constant(Value::Object(src.add_obj(LoxString::boxed("hello"))))
+            .unwrap();
 
         let mut bytes = Vec::new();
         chunk.serialize(&src, &mut bytes).unwrap();
@@ -305,4 +474,165 @@ mod tests {
         let loaded = Chunk::load(vm.storage(), &mut bytes.as_slice()).unwrap();
         vm.run(loaded).unwrap();
     }
+
+    #[test]
+    fn round_trips_programs_with_functions() {
+        let src = r#"
+            fun counter() {
+                var n = 0;
+                fun inc() { n = n + 1; return n; }
+                return inc;
+            }
+            class Greeter {
+                init(name) { this.name = name; }
+                greet() { return "hi " + this.name; }
+            }
+            var c = counter();
+            c();
+            print c();
+            print Greeter("lox").greet();
+        "#;
+        let mut src_storage = Storage::new();
+        let mut err = Vec::new();
+        let chunk = crate::compiler::Compiler::new(
+            lexer::Scanner::new(src),
+            &mut report::Reporter::new(src, &mut err),
+            &mut src_storage,
+            &(),
+        )
+        .compile()
+        .unwrap();
+        let mut bytes = Vec::new();
+        chunk.serialize(&src_storage, &mut bytes).unwrap();
+
+        let mut vm = VirtualMachine::default();
+        let loaded = Chunk::from_bytes(vm.storage(), &bytes).unwrap();
+        assert!(!chunk.spans.is_empty());
+        assert_eq!(loaded.spans, chunk.spans);
+        let mut out = Vec::new();
+        vm.run_with(loaded, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2\nhi lox\n");
+    }
+
+    #[test]
+    fn span_deltas_round_trip() {
+        let spans = [
+            SpanInfo {
+                source: 40..43,
+                byte_range: 0..2,
+            },
+            // Runs can go back in the source, e.g. an implicit return.
+            SpanInfo {
+                source: 12..13,
+                byte_range: 5..6,
+            },
+        ];
+        let deltas = SpanDelta::encode(&spans);
+        assert_eq!(deltas[1].gap, 3);
+        assert_eq!(deltas[1].start, -28);
+        assert_eq!(SpanDelta::decode(&deltas).unwrap(), spans);
+
+        let corrupted = SpanDelta {
+            start: -1,
+            ..deltas[0]
+        };
+        assert_eq!(SpanDelta::decode(&[corrupted]), None);
+    }
+
+    #[test]
+    fn rejects_chunks_that_fail_verification() {
+        let mut src = Storage::new();
+        let mut chunk = sample(&mut src);
+        // Point `DefGlobal` past the end of the constant pool.
+        chunk.code[3] = 7;
+        let mut bytes = Vec::new();
+        chunk.serialize(&src, &mut bytes).unwrap();
+
+        let mut dst = Storage::new();
+        let err = Chunk::from_bytes(&mut dst, &bytes).unwrap_err();
+        assert!(matches!(
+            err,
+            ChunkIoError::Verify(VerifyError::ConstantOutOfRange { addr: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_functions_nested_too_deep() {
+        let nested = |depth| {
+            let mut storage = Storage::new();
+            let name = storage.intern("f");
+            let mut chunk = Chunk::default();
+            for _ in 0..depth {
+                let func = LoxFunction::boxed(name, 0, chunk, Box::new([]));
+                chunk = Chunk::default();
+                chunk
+                    .add_constant(Value::Object(storage.add_obj(func)))
+                    .unwrap();
+            }
+            let mut bytes = Vec::new();
+            chunk.serialize(&storage, &mut bytes).unwrap();
+            Chunk::from_bytes(&mut Storage::new(), &bytes)
+        };
+        assert!(nested(MAX_FUNCTION_NESTING).is_ok());
+        assert!(matches!(
+            nested(MAX_FUNCTION_NESTING + 1),
+            Err(ChunkIoError::Postcard(_))
+        ));
+    }
+
+    #[test]
+    fn verifies_shared_functions_once() {
+        // Every level makes two closures of the next, so verifying each
+        // closure's function separately would take 2^64 runs.
+        let mut storage = Storage::new();
+        let name = storage.intern("f");
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil);
+        chunk.write(OpCode::Ret);
+        for level in (0..64).rev() {
+            let func = LoxFunction::boxed(name, 0, chunk, Box::new([]));
+            chunk = Chunk::default();
+            let addr = chunk
+                .add_constant(Value::Object(storage.add_obj(func)))
+                .unwrap();
+            for _ in 0..2 {
+                chunk.write(OpCode::Closure(addr.try_into().unwrap()));
+                chunk.write(OpCode::Pop);
+            }
+            if level > 0 {
+                chunk.write(OpCode::Nil);
+                chunk.write(OpCode::Ret);
+            }
+        }
+        let mut bytes = Vec::new();
+        chunk.serialize(&storage, &mut bytes).unwrap();
+
+        let start = std::time::Instant::now();
+        Chunk::from_bytes(&mut Storage::new(), &bytes).unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn round_trips_long_constant_ops() {
+        let mut src = Storage::new();
+        let mut chunk = Chunk::default();
+        for i in 0..300 {
+            chunk.add_constant(Value::number(i as f64)).unwrap();
+        }
+        let foo = chunk
+            .add_constant(Value::symbol(src.intern("foo")))
+            .unwrap();
+        chunk.write_with_line(1, OpCode::ConstantLong(299));
+        chunk.write_with_line(1, OpCode::DefGlobalLong(foo));
+        chunk.write_with_line(2, OpCode::Ret);
+
+        let mut bytes = Vec::new();
+        chunk.serialize(&src, &mut bytes).unwrap();
+
+        let mut vm = VirtualMachine::default();
+        let loaded = Chunk::load(vm.storage(), &mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.constant(299), &Value::number(299.0));
+        vm.run(loaded).unwrap();
+    }
 }
@@ -0,0 +1,555 @@
+//! Static checks for chunks that didn't come out of our own compiler.
+//!
+//! The VM trusts its bytecode: operand indices go straight into `Vec`s and the
+//! `Stack` accessors `expect` the compiler to have balanced every
+//! push and pop. [`Chunk::verify`] re-establishes those invariants for loaded
+//! chunks so a corrupted image is rejected up front instead of panicking (or
+//! worse) halfway through execution.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::enconding::{DecodeError, LongAddr, OpCode, OpDecoder};
+use crate::object::ObjKind;
+use crate::object::function::{Capture, LoxFunction};
+use crate::value::Value;
+
+/// How deep function constants may nest, so that neither loading nor
+/// verifying a chunk recurses without bound.
+pub const MAX_FUNCTION_NESTING: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error("invalid instruction at offset {offset}: {source}")]
+    Decode { offset: u64, source: DecodeError },
+    #[error("constant {addr} at offset {offset} is out of range (pool holds {len})")]
+    ConstantOutOfRange {
+        offset: u64,
+        addr: LongAddr,
+        len: usize,
+    },
+    #[error("constant {addr} at offset {offset} is not a {expected}")]
+    ConstantKind {
+        offset: u64,
+        addr: LongAddr,
+        expected: &'static str,
+    },
+    #[error("jump at offset {offset} targets {target}, which is not an instruction boundary")]
+    BadJumpTarget { offset: u64, target: i64 },
+    #[error("local slot {slot} at offset {offset} is out of range (stack depth {depth})")]
+    LocalOutOfRange { offset: u64, slot: u8, depth: usize },
+    #[error("upvalue slot {slot} at offset {offset} is out of range (closure holds {count})")]
+    UpvalueOutOfRange { offset: u64, slot: u8, count: usize },
+    #[error("stack underflow at offset {offset}")]
+    StackUnderflow { offset: u64 },
+    #[error("stack depth at offset {offset} is {found} on one path and {expected} on another")]
+    StackMismatch {
+        offset: u64,
+        expected: usize,
+        found: usize,
+    },
+    #[error("function execution runs past the end of its code")]
+    FallsOffEnd,
+    #[error("function constants nest more than {MAX_FUNCTION_NESTING} deep")]
+    TooDeep,
+    #[error("in function constant {addr}: {source}")]
+    InFunction {
+        addr: LongAddr,
+        source: Box<VerifyError>,
+    },
+}
+
+impl Chunk {
+    /// Check that this chunk is safe to run as a top-level script.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        Verifier::new(self, Frame::Script, 0).run()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Frame {
+    /// Starts with an empty stack and may run off the end of its code.
+    Script,
+    /// Starts with the callee and its arguments, and must `Ret`.
+    Function { arity: u8, upvalues: usize },
+}
+
+struct Verifier<'c> {
+    chunk: &'c Chunk,
+    frame: Frame,
+    /// Decoded instructions as `(offset, op)`, in code order.
+    ops: Vec<(u64, OpCode)>,
+    /// Instruction index by offset; the end of the code maps to `ops.len()`.
+    index: HashMap<u64, usize>,
+    /// Functions enclosing this chunk.
+    nesting: usize,
+}
+
+impl<'c> Verifier<'c> {
+    fn new(chunk: &'c Chunk, frame: Frame, nesting: usize) -> Self {
+        Self {
+            chunk,
+            frame,
+            ops: Vec::new(),
+            index: HashMap::new(),
+            nesting,
+        }
+    }
+
+    fn run(mut self) -> Result<(), VerifyError> {
+        self.decode()?;
+        for &(offset, op) in &self.ops {
+            self.check_operands(offset, op)?;
+        }
+        self.check_functions()?;
+        self.check_stack()
+    }
+
+    fn decode(&mut self) -> Result<(), VerifyError> {
+        let mut decoder = Cursor::new(self.chunk.code.as_slice());
+        loop {
+            let offset = decoder.position();
+            self.index.insert(offset, self.ops.len());
+            match decoder.decode_op::<OpCode>() {
+                Ok(Some(op)) => self.ops.push((offset, op)),
+                Ok(None) => return Ok(()),
+                Err(source) => return Err(VerifyError::Decode { offset, source }),
+            }
+        }
+    }
+
+    fn next_offset(&self, i: usize) -> u64 {
+        self.ops
+            .get(i + 1)
+            .map_or(self.chunk.current(), |&(offset, _)| offset)
+    }
+
+    /// Index of the instruction `op` (the `i`th) jumps to, if it jumps.
+    fn jump_target(&self, i: usize) -> Result<Option<usize>, VerifyError> {
+        let (offset, op) = self.ops[i];
+        let distance = match op {
+            OpCode::JmpIfFalse(d) | OpCode::Jmp(d) => d as i64,
+            OpCode::JmpIfFalseLong(d) | OpCode::JmpLong(d) => d as i64,
+            OpCode::Loop(d) => -(d as i64),
+            OpCode::LoopLong(d) => -(d as i64),
+            _ => return Ok(None),
+        };
+        let target = self.next_offset(i) as i64 + distance;
+        u64::try_from(target)
+            .ok()
+            .and_then(|t| self.index.get(&t).copied())
+            .map(Some)
+            .ok_or(VerifyError::BadJumpTarget { offset, target })
+    }
+
+    fn constant(&self, offset: u64, addr: LongAddr) -> Result<&'c Value, VerifyError> {
+        self.chunk
+            .constants
+            .get(addr as usize)
+            .ok_or(VerifyError::ConstantOutOfRange {
+                offset,
+                addr,
+                len: self.chunk.constants.len(),
+            })
+    }
+
+    fn function(&self, offset: u64, addr: LongAddr) -> Result<&'c LoxFunction, VerifyError> {
+        match self.constant(offset, addr)? {
+            // SAFETY: matched kind witnesses the dynamic type.
+            Value::Object(obj) if obj.kind() == ObjKind::Function => {
+                Ok(unsafe { obj.downcast_ref::<LoxFunction>() })
+            }
+            _ => Err(VerifyError::ConstantKind {
+                offset,
+                addr,
+                expected: "function",
+            }),
+        }
+    }
+
+    fn check_operands(&self, offset: u64, op: OpCode) -> Result<(), VerifyError> {
+        let symbol = |addr: LongAddr| match self.constant(offset, addr)? {
+            Value::Symbol(_) => Ok(()),
+            _ => Err(VerifyError::ConstantKind {
+                offset,
+                addr,
+                expected: "name",
+            }),
+        };
+        match op {
+            OpCode::Constant(addr) => self.constant(offset, addr.into()).map(drop),
+            OpCode::ConstantLong(addr) => self.constant(offset, addr).map(drop),
+            OpCode::Closure(addr) => self.function(offset, addr.into()).map(drop),
+            OpCode::ClosureLong(addr) => self.function(offset, addr).map(drop),
+            OpCode::DefGlobal(addr)
+            | OpCode::GetGlobal(addr)
+            | OpCode::SetGlobal(addr)
+            | OpCode::Class(addr)
+            | OpCode::GetProperty(addr)
+            | OpCode::SetProperty(addr)
+            | OpCode::Method(addr)
+            | OpCode::GetSuper(addr) => symbol(addr.into()),
+            OpCode::DefGlobalLong(addr)
+            | OpCode::GetGlobalLong(addr)
+            | OpCode::SetGlobalLong(addr)
+            | OpCode::ClassLong(addr)
+            | OpCode::GetPropertyLong(addr)
+            | OpCode::SetPropertyLong(addr)
+            | OpCode::MethodLong(addr)
+            | OpCode::GetSuperLong(addr) => symbol(addr),
+            OpCode::GetUpvalue(slot) | OpCode::SetUpvalue(slot) => {
+                let count = self.upvalues();
+                match (slot.0 as usize) < count {
+                    true => Ok(()),
+                    false => Err(VerifyError::UpvalueOutOfRange {
+                        offset,
+                        slot: slot.0,
+                        count,
+                    }),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The function each closure is made from must verify in a frame of its
+    /// shape. Closures sharing a function constant share the check, or a chain
+    /// of functions that each make two closures of the next would take time
+    /// exponential in its depth.
+    fn check_functions(&self) -> Result<(), VerifyError> {
+        let closures: BTreeMap<LongAddr, u64> = self
+            .ops
+            .iter()
+            // Later entries win, so each function is blamed on its first closure.
+            .rev()
+            .filter_map(|&(offset, op)| match op {
+                OpCode::Closure(addr) => Some((addr.into(), offset)),
+                OpCode::ClosureLong(addr) => Some((addr, offset)),
+                _ => None,
+            })
+            .collect();
+        for (addr, offset) in closures {
+            let func = self.function(offset, addr)?;
+            let frame = Frame::Function {
+                arity: func.arity,
+                upvalues: func.captures.len(),
+            };
+            let nested = match self.nesting < MAX_FUNCTION_NESTING {
+                true => Verifier::new(&func.chunk, frame, self.nesting + 1).run(),
+                false => Err(VerifyError::TooDeep),
+            };
+            nested.map_err(|source| VerifyError::InFunction {
+                addr,
+                source: Box::new(source),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn upvalues(&self) -> usize {
+        match self.frame {
+            Frame::Script => 0,
+            Frame::Function { upvalues, .. } => upvalues,
+        }
+    }
+
+    /// Walk every reachable path, tracking the frame's stack depth, and make
+    /// sure paths agree wherever they meet.
+    fn check_stack(&self) -> Result<(), VerifyError> {
+        let initial = match self.frame {
+            Frame::Script => 0,
+            // Slot 0 holds the callee, followed by the arguments.
+            Frame::Function { arity, .. } => 1 + arity as usize,
+        };
+        let mut depths: Vec<Option<usize>> = vec![None; self.ops.len()];
+        let mut pending = vec![(0, initial)];
+
+        while let Some((i, depth)) = pending.pop() {
+            let Some(&(offset, op)) = self.ops.get(i) else {
+                match self.frame {
+                    Frame::Script => continue,
+                    Frame::Function { .. } => return Err(VerifyError::FallsOffEnd),
+                }
+            };
+            match depths[i] {
+                Some(expected) if expected != depth => {
+                    return Err(VerifyError::StackMismatch {
+                        offset,
+                        expected,
+                        found: depth,
+                    });
+                }
+                Some(_) => continue,
+                None => depths[i] = Some(depth),
+            }
+
+            let after = self.step(offset, op, depth)?;
+            if let Some(target) = self.jump_target(i)? {
+                pending.push((target, after));
+            }
+            let falls_through = !matches!(
+                op,
+                OpCode::Ret
+                    | OpCode::Jmp(_)
+                    | OpCode::JmpLong(_)
+                    | OpCode::Loop(_)
+                    | OpCode::LoopLong(_)
+            );
+            if falls_through {
+                pending.push((i + 1, after));
+            }
+        }
+        Ok(())
+    }
+
+    /// Stack depth after executing `op` at `depth`.
+    fn step(&self, offset: u64, op: OpCode, depth: usize) -> Result<usize, VerifyError> {
+        let local = |slot: u8| match (slot as usize) < depth {
+            true => Ok(()),
+            false => Err(VerifyError::LocalOutOfRange {
+                offset,
+                slot,
+                depth,
+            }),
+        };
+        let (pops, pushes) = match op {
+            OpCode::NoOp | OpCode::Jmp(_) | OpCode::JmpLong(_) => (0, 0),
+            OpCode::Loop(_) | OpCode::LoopLong(_) => (0, 0),
+            OpCode::Ret => match self.frame {
+                Frame::Script => (0, 0),
+                Frame::Function { .. } => (1, 0),
+            },
+            OpCode::Constant(_) | OpCode::ConstantLong(_) => (0, 1),
+            OpCode::True | OpCode::False | OpCode::Nil => (0, 1),
+            OpCode::GetGlobal(_) | OpCode::GetGlobalLong(_) => (0, 1),
+            OpCode::GetUpvalue(_) => (0, 1),
+            OpCode::Class(_) | OpCode::ClassLong(_) => (0, 1),
+            OpCode::Neg | OpCode::Not => (1, 1),
+            OpCode::SetGlobal(_) | OpCode::SetGlobalLong(_) | OpCode::SetUpvalue(_) => (1, 1),
+            OpCode::GetProperty(_) | OpCode::GetPropertyLong(_) => (1, 1),
+            OpCode::JmpIfFalse(_) | OpCode::JmpIfFalseLong(_) => (1, 1),
+            OpCode::Print | OpCode::Pop | OpCode::CloseUpvalue => (1, 0),
+            OpCode::DefGlobal(_) | OpCode::DefGlobalLong(_) => (1, 0),
+            OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div => (2, 1),
+            OpCode::Equal | OpCode::Greater | OpCode::Less => (2, 1),
+            OpCode::SetProperty(_) | OpCode::SetPropertyLong(_) => (2, 1),
+            OpCode::Method(_) | OpCode::MethodLong(_) => (2, 1),
+            OpCode::Inherit | OpCode::GetSuper(_) | OpCode::GetSuperLong(_) => (2, 1),
+            OpCode::PopN(n) => (n as usize, 0),
+            OpCode::Call(argc) => (argc as usize + 1, 1),
+            OpCode::GetLocal(slot) => {
+                local(slot.0)?;
+                (0, 1)
+            }
+            OpCode::SetLocal(slot) => {
+                local(slot.0)?;
+                (1, 1)
+            }
+            OpCode::Closure(addr) => {
+                self.check_closure(offset, addr.into(), depth)?;
+                (0, 1)
+            }
+            OpCode::ClosureLong(addr) => {
+                self.check_closure(offset, addr, depth)?;
+                (0, 1)
+            }
+        };
+        depth
+            .checked_sub(pops)
+            .map(|d| d + pushes)
+            .ok_or(VerifyError::StackUnderflow { offset })
+    }
+
+    /// Captures must refer to live slots of this frame, or the one the
+    /// closure itself is about to fill, for local recursion.
+    fn check_closure(&self, offset: u64, addr: LongAddr, depth: usize) -> Result<(), VerifyError> {
+        let func = self.function(offset, addr)?;
+        for capture in &func.captures {
+            match *capture {
+                Capture::Local(slot) if slot.0 as usize > depth => {
+                    return Err(VerifyError::LocalOutOfRange {
+                        offset,
+                        slot: slot.0,
+                        depth,
+                    });
+                }
+                Capture::Upvalue(slot) if slot.0 as usize >= self.upvalues() => {
+                    return Err(VerifyError::UpvalueOutOfRange {
+                        offset,
+                        slot: slot.0,
+                        count: self.upvalues(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enconding::{LocalSlot, UpvalueSlot};
+    use crate::storage::Storage;
+
+    fn chunk(ops: &[OpCode]) -> Chunk {
+        let mut chunk = Chunk::default();
+        for op in ops {
+            chunk.write(*op);
+        }
+        chunk
+    }
+
+    #[test]
+    fn accepts_compiled_programs() {
+        let src = r#"
+            var a = 1;
+            fun outer(x) {
+                var y = x;
+                fun inner() { return x + y; }
+                return inner;
+            }
+            class A { init(n) { this.n = n; } get() { return this.n; } }
+            class B < A { get() { return super.get() + 1; } }
+            for (var i = 0; i < 3; i = i + 1) {
+                if (i == 1) continue;
+                if (i == 2) break;
+                print outer(i)() and B(i).get() or nil;
+            }
+        "#;
+        let mut storage = Storage::new();
+        let mut err = Vec::new();
+        let chunk = crate::compiler::Compiler::new(
+            lexer::Scanner::new(src),
+            &mut report::Reporter::new(src, &mut err),
+            &mut storage,
+            &(),
+        )
+        .compile()
+        .unwrap();
+        chunk.verify().unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_ops() {
+        let mut truncated = chunk(&[OpCode::Nil]);
+        truncated.code.push(0x18); // `Jmp` missing its operand
+        assert!(matches!(
+            truncated.verify(),
+            Err(VerifyError::Decode { offset: 1, .. })
+        ));
+
+        let mut unknown = Chunk::default();
+        unknown.code.push(0xFF);
+        assert!(matches!(unknown.verify(), Err(VerifyError::Decode { .. })));
+    }
+
+    #[test]
+    fn rejects_bad_constants() {
+        let missing = chunk(&[OpCode::Constant(0), OpCode::Pop]);
+        assert!(matches!(
+            missing.verify(),
+            Err(VerifyError::ConstantOutOfRange { addr: 0, .. })
+        ));
+
+        let mut not_a_name = chunk(&[OpCode::GetGlobal(0), OpCode::Pop]);
+        not_a_name.add_constant(Value::number(1.0)).unwrap();
+        assert!(matches!(
+            not_a_name.verify(),
+            Err(VerifyError::ConstantKind {
+                expected: "name",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_jumps_into_operands() {
+        // Lands on the operand byte of the `Constant` below.
+        let mut chunk = chunk(&[OpCode::Jmp(1), OpCode::Constant(0), OpCode::Pop]);
+        chunk.add_constant(Value::Nil).unwrap();
+        assert!(matches!(
+            chunk.verify(),
+            Err(VerifyError::BadJumpTarget { target: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_out_of_range_slots() {
+        let local = chunk(&[OpCode::Nil, OpCode::GetLocal(LocalSlot(1))]);
+        assert!(matches!(
+            local.verify(),
+            Err(VerifyError::LocalOutOfRange {
+                slot: 1,
+                depth: 1,
+                ..
+            })
+        ));
+
+        let upvalue = chunk(&[OpCode::GetUpvalue(UpvalueSlot(0))]);
+        assert!(matches!(
+            upvalue.verify(),
+            Err(VerifyError::UpvalueOutOfRange { count: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_functions_nested_too_deep() {
+        let mut storage = Storage::new();
+        let name = storage.intern("f");
+        let nested = |storage: &mut Storage, depth| {
+            let mut chunk = chunk(&[OpCode::Nil, OpCode::Ret]);
+            for level in (0..depth).rev() {
+                let func = LoxFunction::boxed(name, 0, chunk, Box::new([]));
+                chunk = Chunk::default();
+                let addr = chunk
+                    .add_constant(Value::Object(storage.add_obj(func)))
+                    .unwrap();
+                chunk.write(OpCode::Closure(addr.try_into().unwrap()));
+                chunk.write(OpCode::Pop);
+                if level > 0 {
+                    chunk.write(OpCode::Nil);
+                    chunk.write(OpCode::Ret);
+                }
+            }
+            chunk.verify()
+        };
+        nested(&mut storage, MAX_FUNCTION_NESTING).unwrap();
+
+        let mut err = nested(&mut storage, MAX_FUNCTION_NESTING + 1).unwrap_err();
+        while let VerifyError::InFunction { source, .. } = err {
+            err = *source;
+        }
+        assert!(matches!(err, VerifyError::TooDeep));
+    }
+
+    #[test]
+    fn rejects_unbalanced_stacks() {
+        let underflow = chunk(&[OpCode::Nil, OpCode::Add]);
+        assert!(matches!(
+            underflow.verify(),
+            Err(VerifyError::StackUnderflow { offset: 1 })
+        ));
+
+        // The taken branch skips the `Nil`, so the paths meet at different depths.
+        let mismatch = chunk(&[
+            OpCode::True,
+            OpCode::JmpIfFalse(1),
+            OpCode::Nil,
+            OpCode::Pop,
+            OpCode::Ret,
+        ]);
+        assert!(matches!(
+            mismatch.verify(),
+            Err(VerifyError::StackMismatch {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+    }
+}
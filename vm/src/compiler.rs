@@ -6,7 +6,7 @@ use lexer::{
     Scanner,
     tokens::{Token, TokenType},
 };
-use report::{Reporter, error::ParsingError};
+use report::{Code, Reporter, diagnostic::Annotate, error::ParsingError};
 use report::{Span, error::LexingError};
 use scopeguard::ScopeGuard;
 use smallvec::SmallVec;
@@ -14,12 +14,15 @@ use smallvec::SmallVec;
 use crate::{
     chunk::Chunk,
     compiler::{
-        context::{Compilation, FunctionKind},
+        context::{Compilation, FunctionKind, LoopContext},
         error::CompileError,
     },
-    enconding::{Addr, LocalSlot, OpCode},
+    debug::LocalInfo,
+    enconding::{
+        Addr, LocalSlot, LongAddr, LongOffset, MAX_LONG_OFFSET, Offset, OpCode, UpvalueSlot,
+    },
     object::function::LoxFunction,
-    storage::Storage,
+    storage::{Storage, gc::Trace},
     value::Value,
 };
 
@@ -29,16 +32,19 @@ pub mod scopes;
 
 // program          => declaration* EOF ;
 //
-// declaration      => funDecl | varDecl | statement ;
+// declaration      => classDecl | funDecl | varDecl | statement ;
 // statement        => exprStmt
 //                  | ifStmt;
 //                  | printStmt
 //                  | returnStmt
 //                  | whileStmt
 //                  | forStmt
+//                  | breakStmt
+//                  | continueStmt
 //                  | block ;
 // block            => "{" declaration* "}" ;
 //
+// classDecl        => "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
 // funDecl          => "fun" function ;
 // function         => IDENTIFIER "(" parameters? ")" block ;
 // parameters       => IDENTIFIER ( "," IDENTIFIER )* ;
@@ -48,6 +54,8 @@ pub mod scopes;
 // printStmt        => "print" expression ";" ;
 // returnStmt       => "return" expression? ";" ;
 // whileStmt        => "while" "(" expression ")" statement ;
+// breakStmt        => "break" ";" ;
+// continueStmt     => "continue" ";" ;
 // forStmt          => "for" "(" ( varDecl | exprStmt | ";" )
 //                  expression? ";"
 //                  expression? ")" statement ;
@@ -55,7 +63,7 @@ pub mod scopes;
 //                  ( "else" statement )? ;
 //
 // expression       => assignment ;
-// assignment       => IDENTIFIER "=" assignment | logicOr ;
+// assignment       => ( call "." )? IDENTIFIER "=" assignment | logicOr ;
 // logicOr          => logicAnd ( "or" logicAnd )*
 // logicAnd         => equality ( "and" equality )*
 // equality         => comparison ( ("!=" | "==") comparison )* ;
@@ -64,11 +72,12 @@ pub mod scopes;
 // factor           => unary ( ("/" | "*") unary )* ;
 // unary            => ("!" | "-") unary
 //                  | call ;
-// call             => primary ( "(" arguments? ")" )* ;
+// call             => primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
 // arguments        => expression ( "," expression )* ;
 //
 // primary          => NUMBER | STRING
-//                  | "true" | "false" | "nil"
+//                  | "true" | "false" | "nil" | "this"
+//                  | "super" "." IDENTIFIER
 //                  | "(" expression ")"
 //                  | IDENTIFIER ;
 
@@ -97,8 +106,11 @@ fn prefix_bp(tok: &TokenType) -> Option<u8> {
     })
 }
 
-fn postfix_bp(_tok: &TokenType) -> Option<u8> {
-    None
+fn postfix_bp(tok: &TokenType) -> Option<u8> {
+    Some(match tok {
+        TokenType::LeftParen => 17,
+        _ => return None,
+    })
 }
 
 fn infix_bp(tok: &TokenType) -> Option<(u8, u8)> {
@@ -112,7 +124,7 @@ fn infix_bp(tok: &TokenType) -> Option<(u8, u8)> {
         }
         TokenType::Plus | TokenType::Minus => (11, 12),
         TokenType::Star | TokenType::Slash => (13, 14),
-        // TokenType::Dot => (15, 16),
+        TokenType::Dot => (17, 18),
         _ => return None,
     })
 }
@@ -131,25 +143,64 @@ enum Handle {
 }
 
 impl Handle {
-    fn global(addr: Addr, line: u32) -> Self {
-        Self::Place(Place::Global { addr, line })
+    fn global(addr: LongAddr, span: Span) -> Self {
+        Self::Place(Place::Global { addr, span })
+    }
+
+    fn local(slot: LocalSlot, span: Span) -> Self {
+        Self::Place(Place::Local { slot, span })
+    }
+
+    fn upvalue(slot: UpvalueSlot, span: Span) -> Self {
+        Self::Place(Place::Upvalue { slot, span })
     }
 
-    fn local(slot: LocalSlot, line: u32) -> Self {
-        Self::Place(Place::Local { slot, line })
+    fn property(addr: LongAddr, span: Span) -> Self {
+        Self::Place(Place::Property { addr, span })
+    }
+
+    /// Where a named place was named, if this is one.
+    fn span(&self) -> Option<Span> {
+        match *self {
+            Self::Value => None,
+            Self::Place(
+                Place::Global { span, .. }
+                | Place::Local { span, .. }
+                | Place::Upvalue { span, .. }
+                | Place::Property { span, .. },
+            ) => Some(span),
+        }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 enum Place {
-    Global { addr: Addr, line: u32 },
-    Local { slot: LocalSlot, line: u32 },
+    Global {
+        addr: LongAddr,
+        span: Span,
+    },
+    Local {
+        slot: LocalSlot,
+        span: Span,
+    },
+    Upvalue {
+        slot: UpvalueSlot,
+        span: Span,
+    },
+    /// Named field of the instance already pushed on the stack.
+    Property {
+        addr: LongAddr,
+        span: Span,
+    },
 }
 
 pub struct Compiler<'s, 'st, 'w, 'r> {
     scanner: Peekable<Scanner<'s>>,
     reporter: &'r mut Reporter<'s, 'w>,
     storage: &'st mut Storage,
+    /// Objects kept alive by whoever owns `storage` (e.g. the VM's globals),
+    /// traced alongside the chunks being compiled when collecting.
+    roots: &'st dyn Trace,
     context: Compilation,
     errored: bool,
 }
@@ -159,30 +210,98 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         scanner: Scanner<'s>,
         reporter: &'r mut Reporter<'s, 'w>,
         storage: &'st mut Storage,
+        roots: &'st dyn Trace,
     ) -> Self {
         Self {
             scanner: scanner.peekable(),
             reporter,
             storage,
+            roots,
             context: Compilation::default(),
             errored: false,
         }
     }
 
+    /// Compile the whole script, reporting every error found along the way.
+    /// The error returned only says there were some.
     pub fn compile(&mut self) -> Result<Chunk, anyhow::Error> {
-        while self.peek()?.is_some() {
-            if let Err(e) = self.declaration() {
-                self.errored = true;
+        if let Err(e) = self.declarations() {
+            self.errored = true;
+            self.reporter.report(&e);
+        }
+
+        match self.errored {
+            true => bail!("Compilation failed"),
+            false => {
+                let mut chunk = std::mem::take(self.context.chunk_mut());
+                chunk.shorten_jumps();
+                Ok(chunk)
+            }
+        }
+    }
+
+    /// Compile a single expression as the body of a nullary function nested
+    /// in a frame whose named locals are `locals`, sorted by slot. Those names
+    /// become captures of the frame; any other name is a global.
+    pub fn compile_eval(
+        &mut self,
+        locals: &[(Spur, LocalSlot)],
+    ) -> Result<Box<LoxFunction>, anyhow::Error> {
+        match self.eval_function(locals) {
+            Ok(func) => Ok(func),
+            Err(e) => {
                 self.report_err(e);
-                self.synchronize()?;
+                bail!("Compilation failed")
+            }
+        }
+    }
+
+    /// Fails on the first lexing error, which ends compilation as it does
+    /// on the tree-walk interpreter.
+    fn declarations(&mut self) -> Result<(), LexingError> {
+        while self.peek()?.is_some() {
+            match self.declaration() {
+                Ok(()) => {}
+                Err(CompileError::Lexing(e)) => return Err(e),
+                Err(e) => {
+                    self.errored = true;
+                    self.report_err(e);
+                    self.synchronize()?;
+                }
             }
         }
         self.emit_return();
+        Ok(())
+    }
 
-        match self.errored {
-            true => bail!("Compilation failed"),
-            false => Ok(std::mem::take(self.context.chunk_mut())),
+    fn eval_function(
+        &mut self,
+        locals: &[(Spur, LocalSlot)],
+    ) -> Result<Box<LoxFunction>, CompileError> {
+        let scopes = self.context.scopes_mut();
+        for &(name, slot) in locals {
+            while scopes.local_count() < slot.0 as usize {
+                scopes.reserve().context("declaring local")?;
+            }
+            scopes.declare(name).context("declaring local")?;
+        }
+        let this_sym = self.storage.intern("this");
+        if locals.iter().any(|&(name, _)| name == this_sym) {
+            self.context.enter_class();
+        }
+
+        let name = self.storage.intern("<eval>");
+        let mut unit = self.begin_unit(FunctionKind::Function);
+        let span = unit.peek()?.map(|tok| tok.span).unwrap_or_default();
+        unit.expression()?;
+        if let Some(tok) = unit.peek()? {
+            return Err(ParsingError::custom(tok, "Expect end of expression.").into());
         }
+        unit.emit_return_and_span(span);
+
+        let this = ScopeGuard::into_inner(unit);
+        let (chunk, captures) = this.context.pop_unit();
+        Ok(LoxFunction::boxed(name, 0, chunk, captures))
     }
 
     pub fn report_err(&mut self, err: CompileError) {
@@ -199,6 +318,7 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         };
 
         match tok.ty() {
+            TokenType::Class => self.class_decl(),
             TokenType::Var => self.var_decl(),
             TokenType::Fun => self.function_decl(),
             _ => self.statement(),
@@ -213,20 +333,105 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             "function identifier",
         )?;
         let name = self.storage.intern(&ident.as_str());
-        let global = self.context.at_global().then(|| self.ident_constant(name));
+        let global = self
+            .context
+            .at_global()
+            .then(|| self.ident_constant(name))
+            .transpose()?;
 
         if global.is_none() {
             self.declare_local(name)?;
         }
-        self.function(name, FunctionKind::Function, ident.line())?;
+        self.function(name, FunctionKind::Function, ident.span)?;
 
         if let Some(addr) = global {
-            self.emit_op_and_line(ident.line(), OpCode::DefGlobal(addr));
+            self.emit_addr_op_and_span(ident.span, addr, OpCode::DefGlobal, OpCode::DefGlobalLong);
+        }
+        Ok(())
+    }
+
+    fn class_decl(&mut self) -> Result<(), CompileError> {
+        self.consume(TokenType::Class)
+            .expect("matched token before entering this branch");
+        let ident = self.consume_with(
+            |t| matches!(t, TokenType::Identifier(_)),
+            "class identifier",
+        )?;
+        let name = self.storage.intern(&ident.as_str());
+        let addr = self.ident_constant(name)?;
+        let at_global = self.context.at_global();
+
+        if !at_global {
+            self.declare_local(name)?;
+        }
+        self.emit_addr_op_and_span(ident.span, addr, OpCode::Class, OpCode::ClassLong);
+        if at_global {
+            self.emit_addr_op_and_span(ident.span, addr, OpCode::DefGlobal, OpCode::DefGlobalLong);
+        }
+
+        let mut this = self.begin_class();
+        // Holds the `super` local, if any, for the duration of the body.
+        let mut this = this.begin_scope();
+        if this.advance_if(TokenType::Less)?.is_some() {
+            let superclass =
+                this.consume_with(|t| matches!(t, TokenType::Identifier(_)), "superclass name")?;
+            if superclass.as_str() == ident.as_str() {
+                return Err(ParsingError::custom(
+                    &superclass,
+                    "A class can't inherit from itself.",
+                )
+                .with_code(Code::InheritFromSelf)
+                .with_label(&ident, "class declared here")
+                .into());
+            }
+            let handle = this.named_variable(superclass)?;
+            this.materialize(handle);
+            let super_sym = this.storage.intern("super");
+            this.declare_local(super_sym)?;
+
+            let class = this.named_variable(ident.clone())?;
+            this.materialize(class);
+            this.emit_op_and_span(ident.span, OpCode::Inherit);
+            this.context
+                .class_mut()
+                .expect("inside begin_class")
+                .has_superclass = true;
+        }
+
+        // Load the class back so each `Method` can attach to it.
+        let class = this.named_variable(ident)?;
+        this.materialize(class);
+
+        this.consume(TokenType::LeftBrace)
+            .context("expect '{' before class body.")?;
+        while let Some(tok) = this.peek()?
+            && tok.ty != TokenType::RightBrace
+        {
+            this.method()?;
         }
+        this.consume(TokenType::RightBrace)
+            .context("expect '}' after class body.")?;
+        this.emit_pops(1);
         Ok(())
     }
 
-    fn function(&mut self, name: Spur, kind: FunctionKind, line: u32) -> Result<(), CompileError> {
+    fn method(&mut self) -> Result<(), CompileError> {
+        let ident = self.consume_with(
+            |t| matches!(t, TokenType::Identifier(_)),
+            "method identifier",
+        )?;
+        let name = self.storage.intern(&ident.as_str());
+        let addr = self.ident_constant(name)?;
+        let kind = match ident.as_str().as_ref() {
+            "init" => FunctionKind::Initializer,
+            _ => FunctionKind::Method,
+        };
+        self.function(name, kind, ident.span)?;
+        self.emit_addr_op_and_span(ident.span, addr, OpCode::Method, OpCode::MethodLong);
+        Ok(())
+    }
+
+    fn function(&mut self, name: Spur, kind: FunctionKind, span: Span) -> Result<(), CompileError> {
         let mut unit = self.begin_unit(kind);
 
         unit.consume(TokenType::LeftParen)
@@ -253,14 +458,18 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         unit.consume(TokenType::LeftBrace)
             .context("expect '{' before function body.")?;
         unit.block()?;
-        unit.emit_op(OpCode::Nil);
-        unit.emit_return_and_line(line);
+        unit.emit_implicit_return(span);
 
         let this = ScopeGuard::into_inner(unit);
-        let chunk = this.context.pop_unit();
-
-        let obj = this.storage.add_obj(LoxFunction::boxed(name, arity, chunk));
-        this.emit_constant_and_line(line, Value::object(obj));
+        // Collect while the finished chunk is still rooted by its unit.
+        this.collect_garbage_if_needed();
+        let (chunk, captures) = this.context.pop_unit();
+
+        let obj = this
+            .storage
+            .add_obj(LoxFunction::boxed(name, arity, chunk, captures));
+        let addr = this.add_constant(Value::object(obj))?;
+        this.emit_addr_op_and_span(span, addr, OpCode::Closure, OpCode::ClosureLong);
         Ok(())
     }
 
@@ -271,30 +480,56 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             "variable identifier",
         )?;
         let name = self.storage.intern(&ident.as_str());
-        let global = self.context.at_global().then(|| self.ident_constant(name));
+        let global = self
+            .context
+            .at_global()
+            .then(|| self.ident_constant(name))
+            .transpose()?;
         self.var_initializer(&ident)?;
         let semi = self.consume(TokenType::Semicolon)?;
 
         match global {
             None => self.declare_local(name)?,
-            Some(addr) => self.emit_op_and_line(semi.line(), OpCode::DefGlobal(addr)),
+            Some(addr) => self.emit_addr_op_and_span(
+                semi.span,
+                addr,
+                OpCode::DefGlobal,
+                OpCode::DefGlobalLong,
+            ),
         }
         Ok(())
     }
 
     fn declare_local(&mut self, name: Spur) -> Result<(), CompileError> {
-        self.context
+        let slot = self
+            .context
             .scopes_mut()
             .declare(name)
             .context("declaring local")?;
+        let chunk = self.context.chunk_mut();
+        let start = chunk.current();
+        chunk.locals.push(LocalInfo::new(name, slot, start));
         Ok(())
     }
 
+    /// Close the debug ranges of the locals a scope exit just dropped.
+    fn end_locals(&mut self) {
+        let live = self.context.scopes().local_count();
+        let chunk = self.context.chunk_mut();
+        let end = chunk.current();
+        for info in chunk.locals.iter_mut().rev().filter(|info| info.is_open()) {
+            if (info.slot.0 as usize) < live {
+                break;
+            }
+            info.live.end = end;
+        }
+    }
+
     fn var_initializer(&mut self, ident: &Token) -> Result<(), CompileError> {
         match self.advance_if(TokenType::Equal)? {
             Some(_) => self.expression(),
             None => {
-                self.emit_op_and_line(ident.line(), OpCode::Nil);
+                self.emit_op_and_span(ident.span, OpCode::Nil);
                 Ok(())
             }
         }
@@ -306,9 +541,12 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         };
         match tok.ty() {
             TokenType::Print => self.print_stmt(),
+            TokenType::Return => self.return_stmt(),
             TokenType::If => self.if_stmt(),
             TokenType::While => self.while_stmt(),
             TokenType::For => self.for_stmt(),
+            TokenType::Break => self.break_stmt(),
+            TokenType::Continue => self.continue_stmt(),
             TokenType::LeftBrace => self.block_stmt(),
             _ => self.expression_stmt(),
         }
@@ -324,18 +562,18 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             .consume(TokenType::RightParen)
             .context("expect ')' after condition.")?;
 
-        let then_jmp = self.emit_jmp_and_line(tok.line(), OpCode::JmpIfFalse(0));
+        let then_jmp = self.emit_jmp_and_span(tok.span, OpCode::JmpIfFalseLong(0));
         self.emit_pops(1);
         self.statement()?;
 
-        let else_jmp = self.emit_jmp(OpCode::Jmp(0));
-        self.patch_jmp(then_jmp);
+        let else_jmp = self.emit_jmp(OpCode::JmpLong(0));
+        self.patch_jmp(then_jmp, &tok)?;
         self.emit_pops(1);
 
         if self.advance_if(TokenType::Else)?.is_some() {
             self.statement()?;
         }
-        self.patch_jmp(else_jmp);
+        self.patch_jmp(else_jmp, &tok)?;
 
         Ok(())
     }
@@ -351,19 +589,22 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             .consume(TokenType::RightParen)
             .context("Expect ')' after 'condition'.")?;
 
-        let exit_jmp = self.emit_jmp_and_line(tok.line(), OpCode::JmpIfFalse(0));
-        self.emit_op_and_line(tok.line(), OpCode::Pop);
-        self.statement()?;
-        self.emit_loop(loop_start);
+        let exit_jmp = self.emit_jmp_and_span(tok.span, OpCode::JmpIfFalseLong(0));
+        self.emit_op_and_span(tok.span, OpCode::Pop);
+        let mut this = self.begin_loop(loop_start);
+        this.statement()?;
+        this.emit_loop(loop_start, &tok)?;
 
-        self.patch_jmp(exit_jmp);
-        self.emit_pops(1);
+        this.patch_jmp(exit_jmp, &tok)?;
+        this.emit_pops(1);
+        this.patch_breaks(&tok)?;
         Ok(())
     }
 
     fn for_stmt(&mut self) -> Result<(), CompileError> {
         let mut this = self.begin_scope();
-        this.consume(TokenType::For)
+        let keyword = this
+            .consume(TokenType::For)
             .expect("matched token before entering this branch");
         this.consume(TokenType::LeftParen)
             .context("Expect '(' after 'for'.")?;
@@ -386,7 +627,7 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             let tok = this
                 .consume(TokenType::Semicolon)
                 .context("Expect ';' after a loop condition.")?;
-            let exit_jmp = this.emit_jmp_and_line(tok.line(), OpCode::JmpIfFalse(0));
+            let exit_jmp = this.emit_jmp_and_span(tok.span, OpCode::JmpIfFalseLong(0));
             this.emit_pops(1);
             Some(exit_jmp)
         } else {
@@ -394,25 +635,75 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         };
 
         if this.advance_if(TokenType::RightParen)?.is_none() {
-            let body_jmp = this.emit_jmp(OpCode::Jmp(0));
+            let body_jmp = this.emit_jmp(OpCode::JmpLong(0));
             let inc_start = this.context.chunk().current();
             this.expression()?;
             this.emit_pops(1);
             this.consume(TokenType::RightParen)
                 .context("Expect ')' after for clauses.")?;
 
-            this.emit_loop(loop_start);
+            this.emit_loop(loop_start, &keyword)?;
             loop_start = inc_start;
-            this.patch_jmp(body_jmp);
+            this.patch_jmp(body_jmp, &keyword)?;
         }
 
-        this.statement()?;
-        this.emit_loop(loop_start);
+        let mut body = this.begin_loop(loop_start);
+        body.statement()?;
+        body.emit_loop(loop_start, &keyword)?;
 
         if let Some(exit_jmp) = exit_jmp {
-            this.patch_jmp(exit_jmp);
-            this.emit_pops(1);
+            body.patch_jmp(exit_jmp, &keyword)?;
+            body.emit_pops(1);
         }
+        body.patch_breaks(&keyword)?;
+        Ok(())
+    }
+
+    fn break_stmt(&mut self) -> Result<(), CompileError> {
+        let tok = self
+            .consume(TokenType::Break)
+            .expect("matched token before entering this branch");
+        let Some(depth) = self.context.innermost_loop().map(|l| l.depth) else {
+            return Err(
+                ParsingError::custom(&tok, "Can't use 'break' outside of a loop.")
+                    .with_code(Code::LoopControlOutsideLoop)
+                    .into(),
+            );
+        };
+        self.consume(TokenType::Semicolon)
+            .context("Expect ';' after 'break'.")?;
+
+        self.emit_scope_exit(&self.context.scopes().unwind(depth));
+        let jmp = self.emit_jmp_and_span(tok.span, OpCode::JmpLong(0));
+        self.context
+            .innermost_loop_mut()
+            .expect("checked above")
+            .breaks
+            .push(jmp);
+        Ok(())
+    }
+
+    fn continue_stmt(&mut self) -> Result<(), CompileError> {
+        let tok = self
+            .consume(TokenType::Continue)
+            .expect("matched token before entering this branch");
+        let Some(&LoopContext {
+            continue_target,
+            depth,
+            ..
+        }) = self.context.innermost_loop()
+        else {
+            return Err(
+                ParsingError::custom(&tok, "Can't use 'continue' outside of a loop.")
+                    .with_code(Code::LoopControlOutsideLoop)
+                    .into(),
+            );
+        };
+        self.consume(TokenType::Semicolon)
+            .context("Expect ';' after 'continue'.")?;
+
+        self.emit_scope_exit(&self.context.scopes().unwind(depth));
+        self.emit_loop(continue_target, &tok)?;
         Ok(())
     }
 
@@ -423,7 +714,38 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         let tok = self
             .consume(TokenType::Semicolon)
             .context("Missing semicolon")?;
-        self.emit_op_and_line(tok.line(), OpCode::Print);
+        self.emit_op_and_span(tok.span, OpCode::Print);
+        Ok(())
+    }
+
+    fn return_stmt(&mut self) -> Result<(), CompileError> {
+        let tok = self
+            .consume(TokenType::Return)
+            .expect("matched token before entering this branch");
+        if self.context.kind() == FunctionKind::Script {
+            return Err(
+                ParsingError::custom(&tok, "Can't return from top-level code.")
+                    .with_code(Code::ReturnFromTopLevel)
+                    .into(),
+            );
+        }
+
+        if self.advance_if(TokenType::Semicolon)?.is_some() {
+            self.emit_implicit_return(tok.span);
+            return Ok(());
+        }
+        self.expression()?;
+        self.consume(TokenType::Semicolon)
+            .context("expect ';' after return value.")?;
+        if self.context.kind() == FunctionKind::Initializer {
+            return Err(
+                ParsingError::custom(&tok, "Can't return a value from an initializer.")
+                    .with_code(Code::ReturnValueFromInitializer)
+                    .with_help("initializers always return `this`; use a bare `return;`")
+                    .into(),
+            );
+        }
+        self.emit_return_and_span(tok.span);
         Ok(())
     }
 
@@ -493,6 +815,8 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             TokenType::String(_) => self.string(tok),
             TokenType::True | TokenType::False | TokenType::Nil => self.literal(tok),
             TokenType::Identifier(_) => self.named_variable(tok),
+            TokenType::This => self.this(tok),
+            TokenType::Super => self.super_(tok),
             _ => Err(ParsingError::expected(&tok, "expression", &tok).into()),
         }
     }
@@ -512,13 +836,16 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             TokenType::Equal => self.assignment(tok, lhs),
             TokenType::And => self.and(tok, lhs),
             TokenType::Or => self.or(tok, lhs),
+            TokenType::Dot => self.dot(tok, lhs),
             _ => Err(ParsingError::expected(&tok, "expression", &tok).into()),
         }
     }
 
-    #[expect(unused)]
     fn parse_postfix(&mut self, tok: Token, lhs: Handle) -> Result<Handle, CompileError> {
-        unimplemented!("no postfix ops atm");
+        match tok.ty() {
+            TokenType::LeftParen => self.call(tok, lhs),
+            _ => Err(ParsingError::expected(&tok, "expression", &tok).into()),
+        }
     }
 
     fn grouping(&mut self, _tok: Token) -> Result<Handle, CompileError> {
@@ -532,10 +859,10 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         let operand = self.parse_bp(r_bp)?;
         self.materialize(operand);
 
-        let line = op.line();
+        let span = op.span;
         match op.ty() {
-            TokenType::Minus => self.emit_op_and_line(line, OpCode::Neg),
-            TokenType::Bang => self.emit_op_and_line(line, OpCode::Not),
+            TokenType::Minus => self.emit_op_and_span(span, OpCode::Neg),
+            TokenType::Bang => self.emit_op_and_span(span, OpCode::Not),
             _ => panic!("expected minus token as prefix"),
         };
 
@@ -550,7 +877,7 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         else {
             unreachable!("expected number token");
         };
-        self.emit_constant_and_line(span.line_start, Value::number(num));
+        self.emit_constant_and_span(span, Value::number(num))?;
         Ok(Handle::Value)
     }
 
@@ -563,16 +890,16 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
             unreachable!("expected string token");
         };
         let key = self.storage.intern(&s);
-        self.emit_constant_and_line(span.line_start, Value::symbol(key));
+        self.emit_constant_and_span(span, Value::symbol(key))?;
         Ok(Handle::Value)
     }
 
     fn literal(&mut self, tok: Token) -> Result<Handle, CompileError> {
-        let line = tok.line();
+        let span = tok.span;
         match tok.ty() {
-            TokenType::True => self.emit_op_and_line(line, OpCode::True),
-            TokenType::False => self.emit_op_and_line(line, OpCode::False),
-            TokenType::Nil => self.emit_op_and_line(line, OpCode::Nil),
+            TokenType::True => self.emit_op_and_span(span, OpCode::True),
+            TokenType::False => self.emit_op_and_span(span, OpCode::False),
+            TokenType::Nil => self.emit_op_and_span(span, OpCode::Nil),
             _ => panic!("expected literal tokens"),
         }
         Ok(Handle::Value)
@@ -580,13 +907,90 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
 
     fn named_variable(&mut self, tok: Token) -> Result<Handle, CompileError> {
         let name = self.storage.intern(&tok.as_str());
-        let line = tok.line();
+        self.variable(name, &tok)
+    }
+
+    /// Resolve `name` as a local, upvalue or global; `tok` is only used for
+    /// the span and error reporting.
+    fn variable(&mut self, name: Spur, tok: &Token) -> Result<Handle, CompileError> {
+        let span = tok.span;
         // Locals shadow globals.
         if let Some(slot) = self.context.scopes().resolve(name) {
-            return Ok(Handle::local(slot, line));
+            return Ok(Handle::local(slot, span));
         }
-        let addr = self.ident_constant(name);
-        Ok(Handle::global(addr, line))
+        if let Some(slot) = self.context.resolve_upvalue(name).map_err(|_| {
+            ParsingError::custom(tok, "Too many closure variables in function.")
+                .with_code(Code::LimitExceeded)
+        })? {
+            return Ok(Handle::upvalue(slot, span));
+        }
+        let addr = self.ident_constant(name)?;
+        Ok(Handle::global(addr, span))
+    }
+
+    fn this(&mut self, tok: Token) -> Result<Handle, CompileError> {
+        if self.context.class().is_none() {
+            return Err(
+                ParsingError::custom(&tok, "Can't use 'this' outside of a class.")
+                    .with_code(Code::ThisOutsideClass)
+                    .into(),
+            );
+        }
+        // `this` is readable but never an assignment target.
+        let handle = self.named_variable(tok)?;
+        self.materialize(handle);
+        Ok(Handle::Value)
+    }
+
+    fn super_(&mut self, tok: Token) -> Result<Handle, CompileError> {
+        match self.context.class() {
+            None => {
+                return Err(
+                    ParsingError::custom(&tok, "Can't use 'super' outside of a class.")
+                        .with_code(Code::SuperOutsideClass)
+                        .into(),
+                );
+            }
+            Some(class) if !class.has_superclass => {
+                return Err(ParsingError::custom(
+                    &tok,
+                    "Can't use 'super' in a class with no superclass.",
+                )
+                .with_code(Code::SuperWithoutSuperclass)
+                .into());
+            }
+            Some(_) => {}
+        }
+        self.consume(TokenType::Dot)
+            .context("expect '.' after 'super'.")?;
+        let ident = self
+            .consume_with(
+                |t| matches!(t, TokenType::Identifier(_)),
+                "superclass method name",
+            )
+            .context("expect superclass method name.")?;
+        let name = self.storage.intern(&ident.as_str());
+        let addr = self.ident_constant(name)?;
+
+        // Bind the superclass method to the current receiver.
+        let this_sym = self.storage.intern("this");
+        let receiver = self.variable(this_sym, &tok)?;
+        self.materialize(receiver);
+        let super_sym = self.storage.intern("super");
+        let superclass = self.variable(super_sym, &tok)?;
+        self.materialize(superclass);
+        self.emit_addr_op_and_span(ident.span, addr, OpCode::GetSuper, OpCode::GetSuperLong);
+        Ok(Handle::Value)
+    }
+
+    fn dot(&mut self, _dot: Token, lhs: Handle) -> Result<Handle, CompileError> {
+        self.materialize(lhs);
+        let ident = self
+            .consume_with(|t| matches!(t, TokenType::Identifier(_)), "property name")
+            .context("expect property name after '.'.")?;
+        let name = self.storage.intern(&ident.as_str());
+        let addr = self.ident_constant(name)?;
+        Ok(Handle::property(addr, ident.span))
     }
 
     fn binary(&mut self, op: Token, lhs: Handle) -> Result<Handle, CompileError> {
@@ -595,43 +999,67 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         let rhs = self.parse_bp(r_bp)?;
         self.materialize(rhs);
 
-        let line = op.line();
+        let span = op.span;
         #[rustfmt::skip]
         match op.ty() {
-            TokenType::Plus => self.emit_op_and_line(line, OpCode::Add),
-            TokenType::Minus => self.emit_op_and_line(line, OpCode::Sub),
-            TokenType::Star => self.emit_op_and_line(line, OpCode::Mul),
-            TokenType::Slash => self.emit_op_and_line(line, OpCode::Div),
+            TokenType::Plus => self.emit_op_and_span(span, OpCode::Add),
+            TokenType::Minus => self.emit_op_and_span(span, OpCode::Sub),
+            TokenType::Star => self.emit_op_and_span(span, OpCode::Mul),
+            TokenType::Slash => self.emit_op_and_span(span, OpCode::Div),
             TokenType::BangEqual => {
-                self.emit_op_and_line(line, OpCode::Equal);
-                self.emit_op_and_line(line, OpCode::Not);
+                self.emit_op_and_span(span, OpCode::Equal);
+                self.emit_op_and_span(span, OpCode::Not);
             }
-            TokenType::EqualEqual => self.emit_op_and_line(line, OpCode::Equal),
-            TokenType::Greater => self.emit_op_and_line(line, OpCode::Greater),
+            TokenType::EqualEqual => self.emit_op_and_span(span, OpCode::Equal),
+            TokenType::Greater => self.emit_op_and_span(span, OpCode::Greater),
             TokenType::GreaterEqual => {
-                self.emit_op_and_line(line, OpCode::Less);
-                self.emit_op_and_line(line, OpCode::Not);
+                self.emit_op_and_span(span, OpCode::Less);
+                self.emit_op_and_span(span, OpCode::Not);
             }
-            TokenType::Less => self.emit_op_and_line(line, OpCode::Less),
+            TokenType::Less => self.emit_op_and_span(span, OpCode::Less),
             TokenType::LessEqual => {
-                self.emit_op_and_line(line, OpCode::Greater);
-                self.emit_op_and_line(line, OpCode::Not);
+                self.emit_op_and_span(span, OpCode::Greater);
+                self.emit_op_and_span(span, OpCode::Not);
             }
             _ => panic!("unexpected binary token: {op}"),
         };
         Ok(Handle::Value)
     }
 
+    fn call(&mut self, _l_paren: Token, callee: Handle) -> Result<Handle, CompileError> {
+        self.materialize(callee);
+        let argc = self
+            .list_separated(TokenType::Comma, |this| {
+                if matches!(this.peek()?, Some(t) if t.ty == TokenType::RightParen) {
+                    return Ok(None);
+                }
+                this.expression()?;
+                Ok(Some(()))
+            })
+            .try_fold(0usize, |argc, arg| arg.map(|_| argc + 1))?;
+        let r_paren = self
+            .consume(TokenType::RightParen)
+            .context("expect ')' after arguments.")?;
+        let argc = u8::try_from(argc)
+            .ok()
+            .context("can't have more than 255 arguments")?;
+
+        // Call errors point at the callee, if it has a name to point at.
+        let span = callee.span().unwrap_or(r_paren.span);
+        self.emit_op_and_span(span, OpCode::Call(argc));
+        Ok(Handle::Value)
+    }
+
     fn and(&mut self, tok: Token, lhs: Handle) -> Result<Handle, CompileError> {
         let (_l_bp, r_bp) = infix_bp(tok.ty()).expect("expected infix op token");
         self.materialize(lhs);
 
-        let short_circuit = self.emit_jmp_and_line(tok.line(), OpCode::JmpIfFalse(0));
-        self.emit_op_and_line(tok.line(), OpCode::Pop);
+        let short_circuit = self.emit_jmp_and_span(tok.span, OpCode::JmpIfFalseLong(0));
+        self.emit_op_and_span(tok.span, OpCode::Pop);
 
         let rhs = self.parse_bp(r_bp)?;
         self.materialize(rhs);
-        self.patch_jmp(short_circuit);
+        self.patch_jmp(short_circuit, &tok)?;
 
         Ok(Handle::Value)
     }
@@ -640,22 +1068,24 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         let (_l_bp, r_bp) = infix_bp(tok.ty()).expect("expected infix op token");
         self.materialize(lhs);
 
-        let else_jmp = self.emit_jmp_and_line(tok.line(), OpCode::JmpIfFalse(0));
-        let end_jmp = self.emit_jmp_and_line(tok.line(), OpCode::Jmp(0));
+        let else_jmp = self.emit_jmp_and_span(tok.span, OpCode::JmpIfFalseLong(0));
+        let end_jmp = self.emit_jmp_and_span(tok.span, OpCode::JmpLong(0));
 
-        self.patch_jmp(else_jmp);
-        self.emit_op_and_line(tok.line(), OpCode::Pop);
+        self.patch_jmp(else_jmp, &tok)?;
+        self.emit_op_and_span(tok.span, OpCode::Pop);
 
         let rhs = self.parse_bp(r_bp)?;
         self.materialize(rhs);
-        self.patch_jmp(end_jmp);
+        self.patch_jmp(end_jmp, &tok)?;
 
         Ok(Handle::Value)
     }
 
     fn assignment(&mut self, equal: Token, lhs: Handle) -> Result<Handle, CompileError> {
         let Handle::Place(place) = lhs else {
-            return Err(ParsingError::expected(&equal, "lvalue", "rvalue").into());
+            return Err(ParsingError::expected(&equal, "lvalue", "rvalue")
+                .with_code(Code::InvalidAssignmentTarget)
+                .into());
         };
         let (_, r_bp) = infix_bp(equal.ty()).expect("=");
         let rhs = self.parse_bp(r_bp)?;
@@ -667,27 +1097,55 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
     fn materialize(&mut self, handle: Handle) {
         match handle {
             Handle::Value => {}
-            Handle::Place(Place::Global { addr, line }) => {
-                self.emit_op_and_line(line, OpCode::GetGlobal(addr));
+            Handle::Place(Place::Global { addr, span }) => {
+                self.emit_addr_op_and_span(span, addr, OpCode::GetGlobal, OpCode::GetGlobalLong);
+            }
+            Handle::Place(Place::Local { slot, span }) => {
+                self.emit_op_and_span(span, OpCode::GetLocal(slot));
+            }
+            Handle::Place(Place::Upvalue { slot, span }) => {
+                self.emit_op_and_span(span, OpCode::GetUpvalue(slot));
             }
-            Handle::Place(Place::Local { slot, line }) => {
-                self.emit_op_and_line(line, OpCode::GetLocal(slot));
+            Handle::Place(Place::Property { addr, span }) => {
+                self.emit_addr_op_and_span(
+                    span,
+                    addr,
+                    OpCode::GetProperty,
+                    OpCode::GetPropertyLong,
+                );
             }
         }
     }
 
     fn store(&mut self, place: Place) {
         match place {
-            Place::Global { addr, line } => {
-                self.emit_op_and_line(line, OpCode::SetGlobal(addr));
+            Place::Global { addr, span } => {
+                self.emit_addr_op_and_span(span, addr, OpCode::SetGlobal, OpCode::SetGlobalLong);
             }
-            Place::Local { slot, line } => {
-                self.emit_op_and_line(line, OpCode::SetLocal(slot));
+            Place::Local { slot, span } => {
+                self.emit_op_and_span(span, OpCode::SetLocal(slot));
             }
+            Place::Upvalue { slot, span } => {
+                self.emit_op_and_span(span, OpCode::SetUpvalue(slot));
+            }
+            Place::Property { addr, span } => {
+                self.emit_addr_op_and_span(
+                    span,
+                    addr,
+                    OpCode::SetProperty,
+                    OpCode::SetPropertyLong,
+                );
+            }
+        }
+    }
+
+    fn collect_garbage_if_needed(&mut self) {
+        if self.storage.should_collect() {
+            self.storage.collect(&[&self.context, self.roots]);
         }
     }
 
-    fn ident_constant(&mut self, name: Spur) -> Addr {
+    fn ident_constant(&mut self, name: Spur) -> Result<LongAddr, CompileError> {
         self.add_constant(Value::symbol(name))
     }
 
@@ -698,8 +1156,9 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
     {
         self.context.scopes_mut().enter();
         scopeguard::guard(self, |this| {
-            let pop_count = this.context.scopes_mut().exit();
-            this.emit_pops(pop_count);
+            let dropped = this.context.scopes_mut().exit();
+            this.end_locals();
+            this.emit_scope_exit(&dropped);
         })
     }
 
@@ -709,12 +1168,55 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         kind: FunctionKind,
     ) -> ScopeGuard<&'c mut Compiler<'s, 'st, 'w, 'r>, impl FnOnce(&'c mut Compiler<'s, 'st, 'w, 'r>)>
     {
-        self.context.push_unit(kind);
+        let receiver = kind.has_receiver().then(|| self.storage.intern("this"));
+        self.context.push_unit(kind, receiver);
         scopeguard::guard(self, |this| {
             this.context.pop_unit();
         })
     }
 
+    /// Compile a loop body. Pending `break`s are resolved by `patch_breaks`
+    /// once the loop's exit code is emitted.
+    #[must_use]
+    fn begin_loop<'c>(
+        &'c mut self,
+        continue_target: u64,
+    ) -> ScopeGuard<&'c mut Compiler<'s, 'st, 'w, 'r>, impl FnOnce(&'c mut Compiler<'s, 'st, 'w, 'r>)>
+    {
+        self.context.enter_loop(continue_target);
+        scopeguard::guard(self, |this| {
+            this.context.exit_loop();
+        })
+    }
+
+    #[must_use]
+    fn begin_class<'c>(
+        &'c mut self,
+    ) -> ScopeGuard<&'c mut Compiler<'s, 'st, 'w, 'r>, impl FnOnce(&'c mut Compiler<'s, 'st, 'w, 'r>)>
+    {
+        self.context.enter_class();
+        scopeguard::guard(self, |this| {
+            this.context.exit_class();
+        })
+    }
+
+    /// Discard the locals of a finished scope, innermost first. Runs of plain
+    /// locals are popped together; captured ones are hoisted into their
+    /// upvalue one at a time.
+    fn emit_scope_exit(&mut self, captured: &[bool]) {
+        let mut pending = 0;
+        for &is_captured in captured {
+            if is_captured {
+                self.emit_pops(pending);
+                pending = 0;
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                pending += 1;
+            }
+        }
+        self.emit_pops(pending);
+    }
+
     fn emit_pops(&mut self, count: usize) {
         debug_assert!(count <= u8::MAX as usize, "Scopes caps locals at u8::MAX");
         match count {
@@ -728,41 +1230,63 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         self.context.chunk_mut().write(op);
     }
 
-    fn emit_op_and_line(&mut self, line: u32, op: OpCode) {
-        self.context.chunk_mut().write_with_line(line, op);
+    fn emit_op_and_span(&mut self, span: Span, op: OpCode) {
+        self.context.chunk_mut().write_with_span(span, op);
+    }
+
+    /// Emit the one-byte operand form of a constant-indexed op when `addr`
+    /// fits, falling back to its `*Long` twin otherwise.
+    fn emit_addr_op_and_span(
+        &mut self,
+        span: Span,
+        addr: LongAddr,
+        short: fn(Addr) -> OpCode,
+        long: fn(LongAddr) -> OpCode,
+    ) {
+        let op = Addr::try_from(addr).map_or_else(|_| long(addr), short);
+        self.emit_op_and_span(span, op);
     }
 
     fn emit_return(&mut self) {
         self.emit_op(OpCode::Ret);
     }
 
-    fn emit_return_and_line(&mut self, line: u32) {
-        self.emit_op_and_line(line, OpCode::Ret);
+    fn emit_return_and_span(&mut self, span: Span) {
+        self.emit_op_and_span(span, OpCode::Ret);
     }
 
-    fn add_constant(&mut self, value: Value) -> Addr {
-        if let Some(addr) = self
-            .context
-            .chunk()
-            .constants
-            .iter()
-            .rposition(|v| v == &value)
-        {
-            return addr as Addr;
+    /// Return with no explicit value: initializers hand back `this`, everything
+    /// else returns `nil`.
+    fn emit_implicit_return(&mut self, span: Span) {
+        match self.context.kind() {
+            FunctionKind::Initializer => {
+                self.emit_op_and_span(span, OpCode::GetLocal(LocalSlot(0)))
+            }
+            _ => self.emit_op_and_span(span, OpCode::Nil),
         }
-        self.context.chunk_mut().add_constant(value)
+        self.emit_return_and_span(span);
     }
 
-    fn emit_constant_and_line(&mut self, line: u32, value: Value) -> Addr {
-        let addr = self.add_constant(value);
-        self.context
-            .chunk_mut()
-            .write_with_line(line, OpCode::Constant(addr));
-        addr
+    fn add_constant(&mut self, value: Value) -> Result<LongAddr, CompileError> {
+        let addr = self
+            .context
+            .add_constant(value)
+            .context("adding constant")?;
+        Ok(addr)
     }
 
-    fn emit_jmp_and_line(&mut self, line: u32, op: OpCode) -> u64 {
-        self.emit_op_and_line(line, op);
+    fn emit_constant_and_span(
+        &mut self,
+        span: Span,
+        value: Value,
+    ) -> Result<LongAddr, CompileError> {
+        let addr = self.add_constant(value)?;
+        self.emit_addr_op_and_span(span, addr, OpCode::Constant, OpCode::ConstantLong);
+        Ok(addr)
+    }
+
+    fn emit_jmp_and_span(&mut self, span: Span, op: OpCode) -> u64 {
+        self.emit_op_and_span(span, op);
         self.context.chunk().current()
     }
 
@@ -771,18 +1295,55 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
         self.context.chunk().current()
     }
 
-    fn emit_loop(&mut self, loop_start: u64) {
-        let offset = self.context.chunk().current() - loop_start + 3; // sizeof(OP_LOOP) = 3
-        assert!(offset <= u16::MAX as u64, "Loop body too large.");
-        self.emit_op(OpCode::Loop(offset as u16));
+    /// Jump back to `loop_start`, picking `LoopLong` when the body is too
+    /// large for a 16-bit offset. `tok` is only used for error reporting.
+    fn emit_loop(&mut self, loop_start: u64, tok: &Token) -> Result<(), CompileError> {
+        let distance = self.context.chunk().current() - loop_start;
+        match Offset::try_from(distance + 3) {
+            // sizeof(OP_LOOP) = 3
+            Ok(offset) => self.emit_op(OpCode::Loop(offset)),
+            // sizeof(OP_LOOP_LONG) = 4
+            Err(_) if distance + 4 <= MAX_LONG_OFFSET => {
+                self.emit_op(OpCode::LoopLong((distance + 4) as LongOffset))
+            }
+            Err(_) => {
+                return Err(ParsingError::custom(tok, "Loop body too large.")
+                    .with_code(Code::LimitExceeded)
+                    .into());
+            }
+        }
+        Ok(())
     }
 
-    fn patch_jmp(&mut self, offset: u64) {
+    /// Point the forward jump ending at `offset` to the current position.
+    /// Forward jumps are emitted in their long form since the body size isn't
+    /// known yet, and shortened where they fit once the chunk is done.
+    fn patch_jmp(&mut self, offset: u64, tok: &Token) -> Result<(), CompileError> {
         let jmp = self.context.chunk().current() - offset;
-        assert!(jmp <= u16::MAX as u64, "too much code to jump over.");
+        if jmp > MAX_LONG_OFFSET {
+            return Err(ParsingError::custom(tok, "Too much code to jump over.")
+                .with_code(Code::LimitExceeded)
+                .into());
+        }
+        let [b0, b1, b2, _] = (jmp as LongOffset).to_le_bytes();
         self.context
             .chunk_mut()
-            .write_raw(offset - 2, &(jmp as u16).to_le_bytes());
+            .write_raw(offset - 3, &[b0, b1, b2]);
+        Ok(())
+    }
+
+    fn patch_breaks(&mut self, tok: &Token) -> Result<(), CompileError> {
+        let breaks = std::mem::take(
+            &mut self
+                .context
+                .innermost_loop_mut()
+                .expect("inside begin_loop")
+                .breaks,
+        );
+        for jmp in breaks {
+            self.patch_jmp(jmp, tok)?;
+        }
+        Ok(())
     }
 
     fn advance(&mut self) -> Result<Option<Token>, LexingError> {
@@ -869,7 +1430,10 @@ impl<'s, 'st, 'w, 'r> Compiler<'s, 'st, 'w, 'r> {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+
     use super::*;
+    use crate::enconding::OpDecoder;
 
     fn compile(src: &str) -> Chunk {
         let mut err = std::io::stderr();
@@ -877,6 +1441,7 @@ mod tests {
             Scanner::new(src),
             &mut Reporter::new(src, &mut err),
             &mut Storage::new(),
+            &(),
         )
         .compile()
         .unwrap_or_else(|_| panic!("failed to compile `{src}`"))
@@ -1042,6 +1607,150 @@ mod tests {
         compile("var g = 1; fun f() { print g; }");
     }
 
+    #[test]
+    fn call_no_args() {
+        compile("fun f() {} f();");
+    }
+
+    #[test]
+    fn call_with_args() {
+        compile("fun add(a, b) { return a + b; } print add(1, 2);");
+    }
+
+    #[test]
+    fn call_chained() {
+        compile("fun f() { return f; } f()()();");
+    }
+
+    #[test]
+    fn return_without_value() {
+        compile("fun f() { return; }");
+    }
+
+    #[test]
+    fn call_emits_arg_count() {
+        let chunk = compile("fun f(a, b, c) {} f(1, 2, 3);");
+        let mut decoder = Cursor::new(chunk.code.as_slice());
+        let mut calls = Vec::new();
+        while let Some(op) = decoder.decode_op::<OpCode>().unwrap() {
+            if let OpCode::Call(argc) = op {
+                calls.push(argc);
+            }
+        }
+        assert_eq!(calls, [3]);
+    }
+
+    fn ops(chunk: &Chunk) -> Vec<OpCode> {
+        let mut decoder = Cursor::new(chunk.code.as_slice());
+        std::iter::from_fn(|| decoder.decode_op::<OpCode>().unwrap()).collect()
+    }
+
+    #[test]
+    fn function_decl_emits_closure() {
+        let chunk = compile("fun f() {}");
+        assert!(matches!(ops(&chunk)[0], OpCode::Closure(_)));
+    }
+
+    #[test]
+    fn block_closes_captured_locals() {
+        let chunk = compile("{ var a = 1; var b = 2; fun f() { print a; } }");
+        let tail: Vec<_> = ops(&chunk).into_iter().rev().skip(1).take(3).collect();
+        // `f` and `b` are popped, then `a` is hoisted into its upvalue.
+        assert!(matches!(
+            tail.as_slice(),
+            [OpCode::CloseUpvalue, OpCode::PopN(2), OpCode::Closure(_)]
+        ));
+    }
+
+    #[test]
+    fn break_pops_body_locals_and_exits_loop() {
+        let chunk = compile("while (true) { var a = 1; var b = 2; break; }");
+        let ops = ops(&chunk);
+        let at = ops
+            .iter()
+            .position(|op| matches!(op, OpCode::PopN(2)))
+            .expect("break discards the body locals");
+        let OpCode::Jmp(offset) = ops[at + 1] else {
+            panic!("break jumps out of the loop");
+        };
+        assert!(offset > 0);
+    }
+
+    #[test]
+    fn continue_outside_loop_errors() {
+        let src = "continue;";
+        let mut err = Vec::new();
+        let result = Compiler::new(
+            Scanner::new(src),
+            &mut Reporter::new(src, &mut err),
+            &mut Storage::new(),
+            &(),
+        )
+        .compile();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn switches_to_long_ops_past_255_constants() {
+        let src = (0..300).map(|i| format!("{i};")).collect::<String>() + "var late = 1;";
+        let chunk = compile(&src);
+        let ops = ops(&chunk);
+        assert!(matches!(ops[0], OpCode::Constant(0)));
+        assert!(ops.iter().any(|op| matches!(op, OpCode::ConstantLong(256))));
+        assert!(
+            ops.iter()
+                .any(|op| matches!(op, OpCode::DefGlobalLong(300)))
+        );
+    }
+
+    #[test]
+    fn picks_long_loop_for_large_bodies() {
+        let small = compile("while (false) { nil; }");
+        assert!(ops(&small).iter().any(|op| matches!(op, OpCode::Loop(_))));
+
+        let body = "nil;".repeat(40_000);
+        let large = compile(&format!("while (false) {{ {body} }}"));
+        let ops = ops(&large);
+        assert!(ops.iter().any(|op| matches!(op, OpCode::LoopLong(_))));
+        assert!(!ops.iter().any(|op| matches!(op, OpCode::Loop(_))));
+    }
+
+    #[test]
+    fn class_with_methods() {
+        let chunk = compile("class A { init(x) { this.x = x; } get() { return this.x; } }");
+        let methods = ops(&chunk)
+            .into_iter()
+            .filter(|op| matches!(op, OpCode::Method(_)))
+            .count();
+        assert_eq!(methods, 2);
+    }
+
+    #[test]
+    fn subclass_inherits_and_calls_super() {
+        let chunk = compile("class A { f() {} } class B < A { f() { super.f(); } }");
+        let ops = ops(&chunk);
+        let inherits = ops
+            .iter()
+            .filter(|op| matches!(op, OpCode::Inherit))
+            .count();
+        assert_eq!(inherits, 1);
+        // `super` is captured by `f`, so leaving the class body closes it.
+        assert!(ops.iter().any(|op| matches!(op, OpCode::CloseUpvalue)));
+    }
+
+    #[test]
+    fn property_assignment_emits_set_property() {
+        let chunk = compile("var a; a.b.c = 1;");
+        let props: Vec<_> = ops(&chunk)
+            .into_iter()
+            .filter(|op| matches!(op, OpCode::GetProperty(_) | OpCode::SetProperty(_)))
+            .collect();
+        assert!(matches!(
+            props.as_slice(),
+            [OpCode::GetProperty(_), OpCode::SetProperty(_)]
+        ));
+    }
+
     #[test]
     fn dedups_repeated_number_literal() {
         let chunk = compile("print 1; print 1; print 1;");
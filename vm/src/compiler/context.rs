@@ -1,16 +1,50 @@
-use crate::{chunk::Chunk, compiler::scopes::Scopes};
+use std::collections::{HashMap, hash_map::Entry};
+
+use lasso::Spur;
+
+use crate::{
+    chunk::{Chunk, TooManyConstants},
+    compiler::scopes::{Scopes, TooManyUpvalues},
+    debug::LocalInfo,
+    enconding::{LongAddr, UpvalueSlot},
+    object::function::Capture,
+    storage::gc::{Trace, Tracer},
+    value::Value,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum FunctionKind {
     #[default]
     Script,
     Function,
+    Method,
+    Initializer,
+}
+
+impl FunctionKind {
+    /// Methods and initializers bind the receiver to `this` in slot 0.
+    pub fn has_receiver(self) -> bool {
+        matches!(self, FunctionKind::Method | FunctionKind::Initializer)
+    }
 }
 
 struct CompileUnit {
     chunk: Chunk,
     scopes: Scopes,
     kind: FunctionKind,
+    /// Loops enclosing the current position, innermost last. Per unit, since
+    /// `break` can't cross a function boundary.
+    loops: Vec<LoopContext>,
+    /// Where each number and name already in the chunk's constant pool is,
+    /// so repeats share an entry without scanning the pool.
+    constants: HashMap<ConstantKey, LongAddr>,
+}
+
+/// Numbers are keyed by their bits, so `0` and `-0` get entries of their own.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    Symbol(Spur),
 }
 
 impl CompileUnit {
@@ -23,37 +57,109 @@ impl CompileUnit {
             chunk: Chunk::default(),
             scopes: Scopes::default(),
             kind,
+            loops: Vec::new(),
+            constants: HashMap::new(),
         }
     }
 }
 
+/// A class body being compiled; `this` and `super` are only valid inside one.
+#[derive(Debug, Default)]
+pub struct ClassContext {
+    pub has_superclass: bool,
+}
+
+/// A loop body being compiled; `break` and `continue` are only valid inside one.
+#[derive(Debug)]
+pub struct LoopContext {
+    /// Where `continue` jumps back to: the increment of a `for`, otherwise
+    /// the condition.
+    pub continue_target: u64,
+    /// Scope depth the loop was entered at; jumping out of the body discards
+    /// every local declared deeper.
+    pub depth: u32,
+    /// Pending `break` jumps, patched once the loop's exit is known.
+    pub breaks: Vec<u64>,
+}
+
 pub struct Compilation {
     units: Vec<CompileUnit>,
+    /// Class bodies enclosing the current position, innermost last.
+    classes: Vec<ClassContext>,
 }
 
 impl Default for Compilation {
     fn default() -> Self {
         Self {
             units: vec![CompileUnit::script()],
+            classes: Vec::new(),
         }
     }
 }
 
+/// Constants of the chunks still being compiled keep their objects alive.
+impl Trace for Compilation {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.units.iter().for_each(|u| u.chunk.trace(tracer));
+    }
+}
+
 impl Compilation {
-    pub fn push_unit(&mut self, kind: FunctionKind) {
+    /// `receiver` names slot 0 for methods so `this` resolves to it.
+    pub fn push_unit(&mut self, kind: FunctionKind, receiver: Option<Spur>) {
         self.units.push(CompileUnit::of(kind));
-        // Slot 0 holds the callee at runtime; the script has none.
+        // Slot 0 holds the callee (or receiver) at runtime; the script has none.
         if kind != FunctionKind::Script {
-            self.scopes_mut()
-                .reserve()
-                .expect("reserving slot 0 in a fresh unit cannot overflow");
+            let scopes = self.scopes_mut();
+            let slot = match receiver {
+                Some(name) => scopes.declare(name),
+                None => scopes.reserve(),
+            }
+            .expect("reserving slot 0 in a fresh unit cannot overflow");
+            if let Some(name) = receiver {
+                self.chunk_mut().locals.push(LocalInfo::new(name, slot, 0));
+            }
         }
     }
 
-    /// Never pops the script unit at the bottom of the stack.
-    pub fn pop_unit(&mut self) -> Chunk {
+    /// Never pops the script unit at the bottom of the stack. Returns the
+    /// compiled chunk along with what the function captures from its
+    /// enclosing units.
+    pub fn pop_unit(&mut self) -> (Chunk, Box<[Capture]>) {
         assert!(self.units.len() > 1, "cannot pop the script unit");
-        self.units.pop().expect("len checked above").chunk
+        let mut unit = self.units.pop().expect("len checked above");
+        unit.chunk.shorten_jumps();
+        (unit.chunk, unit.scopes.into_upvalues())
+    }
+
+    /// Resolve `name` as a local of some enclosing unit, threading the capture
+    /// through every function in between. `None` means it's a global.
+    pub fn resolve_upvalue(&mut self, name: Spur) -> Result<Option<UpvalueSlot>, TooManyUpvalues> {
+        self.resolve_upvalue_in(self.units.len() - 1, name)
+    }
+
+    fn resolve_upvalue_in(
+        &mut self,
+        unit: usize,
+        name: Spur,
+    ) -> Result<Option<UpvalueSlot>, TooManyUpvalues> {
+        let Some(enclosing) = unit.checked_sub(1) else {
+            return Ok(None);
+        };
+        if let Some(slot) = self.units[enclosing].scopes.resolve(name) {
+            self.units[enclosing].scopes.capture(slot);
+            return self.units[unit]
+                .scopes
+                .add_upvalue(Capture::Local(slot))
+                .map(Some);
+        }
+        match self.resolve_upvalue_in(enclosing, name)? {
+            Some(slot) => self.units[unit]
+                .scopes
+                .add_upvalue(Capture::Upvalue(slot))
+                .map(Some),
+            None => Ok(None),
+        }
     }
 
     pub fn chunk(&self) -> &Chunk {
@@ -72,6 +178,24 @@ impl Compilation {
             .chunk
     }
 
+    /// Add `value` to the current chunk's constant pool, reusing the entry
+    /// of an equal number or name.
+    pub fn add_constant(&mut self, value: Value) -> Result<LongAddr, TooManyConstants> {
+        let unit = self
+            .units
+            .last_mut()
+            .expect("always at least the global unit");
+        let key = match value {
+            Value::Number(n) => ConstantKey::Number(n.to_bits()),
+            Value::Symbol(name) => ConstantKey::Symbol(name),
+            _ => return unit.chunk.add_constant(value),
+        };
+        match unit.constants.entry(key) {
+            Entry::Occupied(e) => Ok(*e.get()),
+            Entry::Vacant(e) => Ok(*e.insert(unit.chunk.add_constant(value)?)),
+        }
+    }
+
     pub fn scopes(&self) -> &Scopes {
         &self
             .units
@@ -88,6 +212,64 @@ impl Compilation {
             .scopes
     }
 
+    pub fn kind(&self) -> FunctionKind {
+        self.units
+            .last()
+            .expect("always at least the global unit")
+            .kind
+    }
+
+    pub fn enter_class(&mut self) {
+        self.classes.push(ClassContext::default());
+    }
+
+    pub fn exit_class(&mut self) {
+        debug_assert!(!self.classes.is_empty(), "exit_class outside of a class");
+        self.classes.pop();
+    }
+
+    pub fn class(&self) -> Option<&ClassContext> {
+        self.classes.last()
+    }
+
+    pub fn class_mut(&mut self) -> Option<&mut ClassContext> {
+        self.classes.last_mut()
+    }
+
+    pub fn enter_loop(&mut self, continue_target: u64) {
+        let depth = self.scopes().depth();
+        self.unit_mut().loops.push(LoopContext {
+            continue_target,
+            depth,
+            breaks: Vec::new(),
+        });
+    }
+
+    pub fn exit_loop(&mut self) -> LoopContext {
+        self.unit_mut()
+            .loops
+            .pop()
+            .expect("exit_loop outside of a loop")
+    }
+
+    pub fn innermost_loop(&self) -> Option<&LoopContext> {
+        self.units
+            .last()
+            .expect("always at least the global unit")
+            .loops
+            .last()
+    }
+
+    pub fn innermost_loop_mut(&mut self) -> Option<&mut LoopContext> {
+        self.unit_mut().loops.last_mut()
+    }
+
+    fn unit_mut(&mut self) -> &mut CompileUnit {
+        self.units
+            .last_mut()
+            .expect("always at least the global unit")
+    }
+
     pub fn at_global(&self) -> bool {
         let unit = self.units.last().expect("always at least the global unit");
         unit.kind == FunctionKind::Script && unit.scopes.is_root()
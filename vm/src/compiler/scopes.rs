@@ -1,24 +1,39 @@
 use lasso::Spur;
+use smallvec::SmallVec;
 use thiserror::Error;
 
-use crate::enconding::LocalSlot;
+use crate::{
+    enconding::{LocalSlot, UpvalueSlot},
+    object::function::Capture,
+};
 
 /// Maximum number of locals live at once. Capped at `u8::MAX` to match the
 /// chunk-constant limit and let any scope's pop count fit in a single `PopN`.
 const MAX_LOCALS: usize = u8::MAX as usize;
 
+/// Maximum number of upvalues a single function can capture; every index
+/// still fits in an `UpvalueSlot`.
+const MAX_UPVALUES: usize = u8::MAX as usize + 1;
+
 #[derive(Debug, Clone, Copy)]
 struct Local {
     /// `None` is a reserved slot no identifier can `resolve` to.
     name: Option<Spur>,
     depth: u32,
+    /// Set once an inner function closes over this local, so leaving its
+    /// scope must hoist it into the upvalue instead of just popping it.
+    captured: bool,
 }
 
+/// Captured flags of the locals dropped by [`Scopes::exit`], innermost first.
+pub type Dropped = SmallVec<[bool; 8]>;
+
 /// Locals form a stack; depths are monotonically non-decreasing front-to-back,
 /// since inner scopes are fully drained before their enclosing scope ends.
 #[derive(Default)]
 pub struct Scopes {
     locals: Vec<Local>,
+    upvalues: Vec<Capture>,
     depth: u32,
 }
 
@@ -26,28 +41,55 @@ pub struct Scopes {
 #[error("too many locals in scope (max {MAX_LOCALS})")]
 pub struct TooManyLocals;
 
+#[derive(Debug, Error)]
+#[error("too many closure variables in function (max {MAX_UPVALUES})")]
+pub struct TooManyUpvalues;
+
 impl Scopes {
     pub fn is_root(&self) -> bool {
         self.depth == 0
     }
 
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Number of slots taken by locals, named or reserved.
+    pub fn local_count(&self) -> usize {
+        self.locals.len()
+    }
+
     pub fn enter(&mut self) {
         self.depth += 1;
     }
 
-    /// Drop all locals declared in the current scope. Returns how many were
-    /// dropped — the caller emits matching `OpPop`s.
-    pub fn exit(&mut self) -> usize {
+    /// Drop all locals declared in the current scope. Returns whether each
+    /// dropped local was captured — the caller emits a matching `OpPop` or
+    /// `OpCloseUpvalue` per local.
+    pub fn exit(&mut self) -> Dropped {
         debug_assert!(self.depth > 0, "exit at global scope");
-        let pop_count = self
+        let dropped: Dropped = self
             .locals
             .iter()
             .rev()
             .take_while(|l| l.depth == self.depth)
-            .count();
-        self.locals.truncate(self.locals.len() - pop_count);
+            .map(|l| l.captured)
+            .collect();
+        self.locals.truncate(self.locals.len() - dropped.len());
         self.depth -= 1;
-        pop_count
+        dropped
+    }
+
+    /// Captured flags of every local declared deeper than `depth`, innermost
+    /// first, without dropping them. Used to discard the locals a `break` or
+    /// `continue` jumps out of while the scopes themselves stay open.
+    pub fn unwind(&self, depth: u32) -> Dropped {
+        self.locals
+            .iter()
+            .rev()
+            .take_while(|l| l.depth > depth)
+            .map(|l| l.captured)
+            .collect()
     }
 
     /// Push a new local at the current scope depth. Shadowing an existing
@@ -71,6 +113,7 @@ impl Scopes {
         self.locals.push(Local {
             name,
             depth: self.depth,
+            captured: false,
         });
         Ok(LocalSlot(slot as u8))
     }
@@ -84,6 +127,29 @@ impl Scopes {
             .map(|i| i as u8)
             .map(LocalSlot)
     }
+
+    /// Mark a local as closed over by an inner function.
+    pub fn capture(&mut self, slot: LocalSlot) {
+        self.locals[slot.0 as usize].captured = true;
+    }
+
+    /// Register an upvalue of this function, reusing the existing slot if the
+    /// same variable was already captured.
+    pub fn add_upvalue(&mut self, capture: Capture) -> Result<UpvalueSlot, TooManyUpvalues> {
+        if let Some(i) = self.upvalues.iter().position(|&c| c == capture) {
+            return Ok(UpvalueSlot(i as u8));
+        }
+        if self.upvalues.len() >= MAX_UPVALUES {
+            return Err(TooManyUpvalues);
+        }
+        let slot = UpvalueSlot(self.upvalues.len() as u8);
+        self.upvalues.push(capture);
+        Ok(slot)
+    }
+
+    pub fn into_upvalues(self) -> Box<[Capture]> {
+        self.upvalues.into_boxed_slice()
+    }
 }
 
 #[cfg(test)]
@@ -127,7 +193,7 @@ mod tests {
         scopes.enter();
         scopes.declare(s[1]).unwrap();
         scopes.declare(s[2]).unwrap();
-        assert_eq!(scopes.exit(), 2);
+        assert_eq!(scopes.exit().len(), 2);
         assert_eq!(scopes.resolve(s[0]), Some(LocalSlot(0)));
         assert_eq!(scopes.resolve(s[1]), None);
         assert_eq!(scopes.resolve(s[2]), None);
@@ -154,6 +220,46 @@ mod tests {
         assert_eq!(scopes.resolve(s[0]), None);
     }
 
+    #[test]
+    fn exit_reports_captured_locals_innermost_first() {
+        let (_r, s) = make(&["a", "b"]);
+        let mut scopes = Scopes::default();
+        scopes.enter();
+        let a = scopes.declare(s[0]).unwrap();
+        scopes.declare(s[1]).unwrap();
+        scopes.capture(a);
+        assert_eq!(scopes.exit().as_slice(), &[false, true]);
+    }
+
+    #[test]
+    fn unwind_reports_deeper_locals_without_dropping() {
+        let (_r, s) = make(&["a", "b", "c"]);
+        let mut scopes = Scopes::default();
+        scopes.enter();
+        scopes.declare(s[0]).unwrap();
+        let depth = scopes.depth();
+        scopes.enter();
+        let b = scopes.declare(s[1]).unwrap();
+        scopes.enter();
+        scopes.declare(s[2]).unwrap();
+        scopes.capture(b);
+        assert_eq!(scopes.unwind(depth).as_slice(), &[false, true]);
+        assert_eq!(scopes.resolve(s[2]), Some(LocalSlot(2)));
+    }
+
+    #[test]
+    fn add_upvalue_dedups_same_capture() {
+        let mut scopes = Scopes::default();
+        let first = scopes.add_upvalue(Capture::Local(LocalSlot(1))).unwrap();
+        let other = scopes
+            .add_upvalue(Capture::Upvalue(UpvalueSlot(1)))
+            .unwrap();
+        let again = scopes.add_upvalue(Capture::Local(LocalSlot(1))).unwrap();
+        assert_eq!(first, UpvalueSlot(0));
+        assert_eq!(other, UpvalueSlot(1));
+        assert_eq!(again, first);
+    }
+
     #[test]
     fn too_many_locals_errors() {
         let (_r, s) = make(&["x"]);
@@ -3,11 +3,15 @@ use std::fmt::Display;
 use std::ops::Range;
 use std::{fmt, io::Cursor};
 
+use lasso::Spur;
 use report::Span;
 use serde::{Deserialize, Serialize};
 
 use crate::chunk::Chunk;
-use crate::enconding::{Addr, OpCode, OpDecoder};
+use crate::enconding::{LocalSlot, LongAddr, OpCode, OpDecoder};
+use crate::object::{ObjKind, function::LoxFunction};
+use crate::storage::Storage;
+use crate::value::Value;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LineInfo {
@@ -16,25 +20,90 @@ pub struct LineInfo {
 }
 
 impl LineInfo {
+    /// A span covering no source text, for code compiled without spans.
     pub fn to_span(&self) -> Span {
         Span {
-            start: 0,
-            end: 0,
             line_start: self.line,
             line_end: self.line,
+            ..Span::default()
         }
     }
 }
 
+/// The source bytes a run of instructions was compiled from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanInfo {
+    pub source: Range<usize>,
+    pub byte_range: Range<u64>,
+}
+
+/// Where a named local lives while it's in scope, so a debugger can show it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalInfo {
+    pub name: Spur,
+    pub slot: LocalSlot,
+    /// Offsets the local is in scope for. Ends at `u64::MAX` when it stays in
+    /// scope until the function returns.
+    pub live: Range<u64>,
+}
+
+impl LocalInfo {
+    pub fn new(name: Spur, slot: LocalSlot, start: u64) -> Self {
+        Self {
+            name,
+            slot,
+            live: start..u64::MAX,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.live.end == u64::MAX
+    }
+}
+
+/// Renders chunks as the textual listing [`Chunk::assemble`] reads back.
 pub struct Disassembler<'a, 'f> {
     f: &'a mut fmt::Formatter<'f>,
     name: &'a str,
     chunk: &'a Chunk,
+    storage: Option<&'a Storage>,
 }
 
 impl<'a, 'f> Disassembler<'a, 'f> {
     pub fn new(f: &'a mut fmt::Formatter<'f>, chunk: &'a Chunk, name: &'a str) -> Self {
-        Self { f, name, chunk }
+        Self {
+            f,
+            name,
+            chunk,
+            storage: None,
+        }
+    }
+
+    /// Resolve string constants and function names through `storage`.
+    /// Without it the listing can't be assembled again.
+    pub fn with_storage(self, storage: &'a Storage) -> Self {
+        Self {
+            storage: Some(storage),
+            ..self
+        }
+    }
+
+    /// Disassemble the chunk, then the chunks of the functions in its constant
+    /// pool, depth first and in constant order.
+    pub fn disassemble_all(&mut self) -> fmt::Result {
+        self.disassemble_chunk()?;
+        for func in self.chunk.constants.iter().filter_map(as_function) {
+            let name = function_name(func, self.storage);
+            writeln!(self.f)?;
+            Disassembler {
+                f: &mut *self.f,
+                name: &name,
+                chunk: &func.chunk,
+                storage: self.storage,
+            }
+            .disassemble_all()?;
+        }
+        Ok(())
     }
 
     pub fn disassemble_chunk(&mut self) -> fmt::Result {
@@ -81,7 +150,40 @@ impl<'a, 'f> Disassembler<'a, 'f> {
         line_str: Cow<'_, str>,
     ) -> fmt::Result {
         write!(self.f, "{offset:04} {line_str:>4} ")?;
-        opcode.disassemble(self.f, self.chunk)
+        opcode.disassemble(self.f, self.chunk, self.storage)
+    }
+}
+
+fn as_function(value: &Value) -> Option<&LoxFunction> {
+    match value {
+        // SAFETY: matched kind witnesses the dynamic type.
+        Value::Object(obj) if obj.kind() == ObjKind::Function => {
+            Some(unsafe { obj.downcast_ref::<LoxFunction>() })
+        }
+        _ => None,
+    }
+}
+
+/// `<fn name/arity>`, which is all [`Chunk::assemble`] needs besides the
+/// function's own listing and the captures on its `OP_CLOSURE`.
+fn function_name(func: &LoxFunction, storage: Option<&Storage>) -> String {
+    match storage {
+        Some(storage) => format!("<fn {}/{}>", storage.resolve(func.name), func.arity),
+        None => func.to_string(),
+    }
+}
+
+/// Strings are quoted so that the listing stays unambiguous.
+fn constant_text(value: &Value, storage: Option<&Storage>) -> String {
+    match (value, storage) {
+        (Value::Symbol(key), Some(storage)) => format!("{:?}", storage.resolve(*key)),
+        (Value::Object(obj), Some(_)) if obj.kind() == ObjKind::String => {
+            format!("{:?}", obj.as_str())
+        }
+        _ => match as_function(value) {
+            Some(func) => function_name(func, storage),
+            None => value.to_string(),
+        },
     }
 }
 
@@ -90,39 +192,118 @@ fn write_args1(f: &mut fmt::Formatter<'_>, verb: &'static str, arg: impl Display
 }
 
 impl OpCode {
-    pub fn disassemble(&self, f: &mut fmt::Formatter<'_>, chunk: &Chunk) -> fmt::Result {
-        let write_addr = |f: &mut fmt::Formatter<'_>, verb: &'static str, addr: &Addr| {
-            let constant = &chunk.constants[*addr as usize];
+    /// The name listings use for this instruction.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::NoOp => "NOOP",
+            OpCode::Ret => "OP_RETURN",
+            OpCode::Constant(..) => "OP_CONSTANT",
+            OpCode::Neg => "OP_NEG",
+            OpCode::Add => "OP_ADD",
+            OpCode::Sub => "OP_SUB",
+            OpCode::Mul => "OP_MUL",
+            OpCode::Div => "OP_DIV",
+            OpCode::True => "OP_TRUE",
+            OpCode::False => "OP_FALSE",
+            OpCode::Nil => "OP_NIL",
+            OpCode::Not => "OP_NOT",
+            OpCode::Equal => "OP_EQUAL",
+            OpCode::Greater => "OP_GREATER",
+            OpCode::Less => "OP_LESS",
+            OpCode::Print => "OP_PRINT",
+            OpCode::Pop => "OP_POP",
+            OpCode::DefGlobal(..) => "OP_DEFINE_GLOBAL",
+            OpCode::GetGlobal(..) => "OP_GET_GLOBAL",
+            OpCode::SetGlobal(..) => "OP_SET_GLOBAL",
+            OpCode::GetLocal(..) => "OP_GET_LOCAL",
+            OpCode::SetLocal(..) => "OP_SET_LOCAL",
+            OpCode::PopN(..) => "OP_POPN",
+            OpCode::JmpIfFalse(..) => "OP_JMP_IF_FALSE",
+            OpCode::Jmp(..) => "OP_JMP",
+            OpCode::Loop(..) => "OP_LOOP",
+            OpCode::Call(..) => "OP_CALL",
+            OpCode::Closure(..) => "OP_CLOSURE",
+            OpCode::GetUpvalue(..) => "OP_GET_UPVALUE",
+            OpCode::SetUpvalue(..) => "OP_SET_UPVALUE",
+            OpCode::CloseUpvalue => "OP_CLOSE_UPVALUE",
+            OpCode::Class(..) => "OP_CLASS",
+            OpCode::GetProperty(..) => "OP_GET_PROPERTY",
+            OpCode::SetProperty(..) => "OP_SET_PROPERTY",
+            OpCode::Method(..) => "OP_METHOD",
+            OpCode::Inherit => "OP_INHERIT",
+            OpCode::GetSuper(..) => "OP_GET_SUPER",
+            OpCode::ConstantLong(..) => "OP_CONSTANT_LONG",
+            OpCode::DefGlobalLong(..) => "OP_DEFINE_GLOBAL_LONG",
+            OpCode::GetGlobalLong(..) => "OP_GET_GLOBAL_LONG",
+            OpCode::SetGlobalLong(..) => "OP_SET_GLOBAL_LONG",
+            OpCode::ClosureLong(..) => "OP_CLOSURE_LONG",
+            OpCode::ClassLong(..) => "OP_CLASS_LONG",
+            OpCode::GetPropertyLong(..) => "OP_GET_PROPERTY_LONG",
+            OpCode::SetPropertyLong(..) => "OP_SET_PROPERTY_LONG",
+            OpCode::MethodLong(..) => "OP_METHOD_LONG",
+            OpCode::GetSuperLong(..) => "OP_GET_SUPER_LONG",
+            OpCode::JmpIfFalseLong(..) => "OP_JMP_IF_FALSE_LONG",
+            OpCode::JmpLong(..) => "OP_JMP_LONG",
+            OpCode::LoopLong(..) => "OP_LOOP_LONG",
+        }
+    }
+
+    pub fn disassemble(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        chunk: &Chunk,
+        storage: Option<&Storage>,
+    ) -> fmt::Result {
+        let write_addr = |f: &mut fmt::Formatter<'_>, verb: &'static str, addr: LongAddr| {
+            let constant = constant_text(chunk.constant(addr), storage);
             write!(f, "{:<16} {:<4}[{addr:<03}]", verb, constant)
         };
+        let write_closure = |f: &mut fmt::Formatter<'_>, verb: &'static str, addr: LongAddr| {
+            write_addr(f, verb, addr)?;
+            let Some(func) = as_function(chunk.constant(addr)) else {
+                return Ok(());
+            };
+            for capture in &func.captures {
+                write!(f, " {capture}")?;
+            }
+            Ok(())
+        };
 
+        let verb = self.mnemonic();
         match self {
-            OpCode::NoOp => write!(f, "NOOP"),
-            OpCode::Ret => write!(f, "OP_RETURN"),
-            OpCode::Constant(addr) => write_addr(f, "OP_CONSTANT", addr),
-            OpCode::Neg => write!(f, "OP_NEG"),
-            OpCode::Add => write!(f, "OP_ADD"),
-            OpCode::Sub => write!(f, "OP_SUB"),
-            OpCode::Mul => write!(f, "OP_MUL"),
-            OpCode::Div => write!(f, "OP_DIV"),
-            OpCode::True => write!(f, "OP_TRUE"),
-            OpCode::False => write!(f, "OP_FALSE"),
-            OpCode::Nil => write!(f, "OP_NIL"),
-            OpCode::Not => write!(f, "OP_NOT"),
-            OpCode::Equal => write!(f, "OP_EQUAL"),
-            OpCode::Greater => write!(f, "OP_GREATER"),
-            OpCode::Less => write!(f, "OP_LESS"),
-            OpCode::Print => write!(f, "OP_PRINT"),
-            OpCode::Pop => write!(f, "OP_POP"),
-            OpCode::DefGlobal(addr) => write_addr(f, "OP_DEFINE_GLOBAL", addr),
-            OpCode::GetGlobal(addr) => write_addr(f, "OP_GET_GLOBAL", addr),
-            OpCode::SetGlobal(addr) => write_addr(f, "OP_SET_GLOBAL", addr),
-            OpCode::GetLocal(slot) => write_args1(f, "OP_GET_LOCAL", slot.0),
-            OpCode::SetLocal(slot) => write_args1(f, "OP_SET_LOCAL", slot.0),
-            OpCode::PopN(n) => write_args1(f, "OP_POPN", n),
-            OpCode::JmpIfFalse(offset) => write_args1(f, "OP_JMP_IF_FALSE", offset),
-            OpCode::Jmp(offset) => write_args1(f, "OP_JMP", offset),
-            OpCode::Loop(offset) => write_args1(f, "OP_LOOP", offset),
+            OpCode::Constant(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::DefGlobal(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::GetGlobal(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::SetGlobal(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::GetLocal(slot) => write_args1(f, verb, slot.0),
+            OpCode::SetLocal(slot) => write_args1(f, verb, slot.0),
+            OpCode::PopN(n) => write_args1(f, verb, n),
+            OpCode::JmpIfFalse(offset) => write_args1(f, verb, offset),
+            OpCode::Jmp(offset) => write_args1(f, verb, offset),
+            OpCode::Loop(offset) => write_args1(f, verb, offset),
+            OpCode::Call(argc) => write_args1(f, verb, argc),
+            OpCode::Closure(addr) => write_closure(f, verb, (*addr).into()),
+            OpCode::GetUpvalue(slot) => write_args1(f, verb, slot.0),
+            OpCode::SetUpvalue(slot) => write_args1(f, verb, slot.0),
+            OpCode::Class(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::GetProperty(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::SetProperty(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::Method(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::GetSuper(addr) => write_addr(f, verb, (*addr).into()),
+            OpCode::ConstantLong(addr) => write_addr(f, verb, *addr),
+            OpCode::DefGlobalLong(addr) => write_addr(f, verb, *addr),
+            OpCode::GetGlobalLong(addr) => write_addr(f, verb, *addr),
+            OpCode::SetGlobalLong(addr) => write_addr(f, verb, *addr),
+            OpCode::ClosureLong(addr) => write_closure(f, verb, *addr),
+            OpCode::ClassLong(addr) => write_addr(f, verb, *addr),
+            OpCode::GetPropertyLong(addr) => write_addr(f, verb, *addr),
+            OpCode::SetPropertyLong(addr) => write_addr(f, verb, *addr),
+            OpCode::MethodLong(addr) => write_addr(f, verb, *addr),
+            OpCode::GetSuperLong(addr) => write_addr(f, verb, *addr),
+            OpCode::JmpIfFalseLong(offset) => write_args1(f, verb, offset),
+            OpCode::JmpLong(offset) => write_args1(f, verb, offset),
+            OpCode::LoopLong(offset) => write_args1(f, verb, offset),
+            _ => write!(f, "{verb}"),
         }
     }
 }
@@ -1,5 +1,6 @@
 use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 // PERF: maybe just read bytes in VM instead of storing in the enum
@@ -32,18 +33,53 @@ pub enum OpCode {
     JmpIfFalse(Offset) = 0x17,
     Jmp(Offset) = 0x18,
     Loop(Offset) = 0x19,
-    // Call(Addr) = 0x1A,
+    Call(u8) = 0x1A,
+    Closure(Addr) = 0x1B,
+    GetUpvalue(UpvalueSlot) = 0x1C,
+    SetUpvalue(UpvalueSlot) = 0x1D,
+    CloseUpvalue = 0x1E,
+    Class(Addr) = 0x1F,
+    GetProperty(Addr) = 0x20,
+    SetProperty(Addr) = 0x21,
+    Method(Addr) = 0x22,
+    Inherit = 0x23,
+    GetSuper(Addr) = 0x24,
+    ConstantLong(LongAddr) = 0x25,
+    DefGlobalLong(LongAddr) = 0x26,
+    GetGlobalLong(LongAddr) = 0x27,
+    SetGlobalLong(LongAddr) = 0x28,
+    ClosureLong(LongAddr) = 0x29,
+    ClassLong(LongAddr) = 0x2A,
+    GetPropertyLong(LongAddr) = 0x2B,
+    SetPropertyLong(LongAddr) = 0x2C,
+    MethodLong(LongAddr) = 0x2D,
+    GetSuperLong(LongAddr) = 0x2E,
+    JmpIfFalseLong(LongOffset) = 0x2F,
+    JmpLong(LongOffset) = 0x30,
+    LoopLong(LongOffset) = 0x31,
 }
 
 pub type Addr = u8;
+/// Constant index of the `*Long` opcodes, encoded in 3 little-endian bytes.
+pub type LongAddr = u32;
 pub type Offset = u16;
+/// Jump distance of the `*Long` jump opcodes, encoded in 3 little-endian bytes.
+pub type LongOffset = u32;
+
+/// Largest constant index a `LongAddr` can encode, plus one.
+pub const MAX_LONG_ADDR: usize = 1 << 24;
+/// Largest distance a `LongOffset` can encode.
+pub const MAX_LONG_OFFSET: u64 = (1 << 24) - 1;
 
 // #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 // pub struct Slot(pub u8);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct LocalSlot(pub u8);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct UpvalueSlot(pub u8);
+
 pub trait Decode: Sized {
     fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError>;
 }
@@ -104,6 +140,16 @@ fn read_one<R: Read + ?Sized>(reader: &mut R) -> Result<u8, DecodeError> {
     read::<1, _>(reader).map(|[b]| b)
 }
 
+fn read_u24<R: Read + ?Sized>(reader: &mut R) -> Result<u32, DecodeError> {
+    read::<3, _>(reader).map(|[b0, b1, b2]| u32::from_le_bytes([b0, b1, b2, 0]))
+}
+
+fn u24_bytes(tag: u8, operand: u32) -> [u8; 4] {
+    debug_assert!(operand < 1 << 24, "operand doesn't fit in 3 bytes");
+    let [b0, b1, b2, _] = operand.to_le_bytes();
+    [tag, b0, b1, b2]
+}
+
 impl Decode for OpCode {
     fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
         let tag = read_one(reader)?;
@@ -134,6 +180,30 @@ impl Decode for OpCode {
             0x17 => OpCode::JmpIfFalse(Offset::from_le_bytes(read::<2, _>(reader)?)),
             0x18 => OpCode::Jmp(Offset::from_le_bytes(read::<2, _>(reader)?)),
             0x19 => OpCode::Loop(Offset::from_le_bytes(read::<2, _>(reader)?)),
+            0x1A => OpCode::Call(read_one(reader)?),
+            0x1B => OpCode::Closure(read_one(reader)?),
+            0x1C => OpCode::GetUpvalue(UpvalueSlot(read_one(reader)?)),
+            0x1D => OpCode::SetUpvalue(UpvalueSlot(read_one(reader)?)),
+            0x1E => OpCode::CloseUpvalue,
+            0x1F => OpCode::Class(read_one(reader)?),
+            0x20 => OpCode::GetProperty(read_one(reader)?),
+            0x21 => OpCode::SetProperty(read_one(reader)?),
+            0x22 => OpCode::Method(read_one(reader)?),
+            0x23 => OpCode::Inherit,
+            0x24 => OpCode::GetSuper(read_one(reader)?),
+            0x25 => OpCode::ConstantLong(read_u24(reader)?),
+            0x26 => OpCode::DefGlobalLong(read_u24(reader)?),
+            0x27 => OpCode::GetGlobalLong(read_u24(reader)?),
+            0x28 => OpCode::SetGlobalLong(read_u24(reader)?),
+            0x29 => OpCode::ClosureLong(read_u24(reader)?),
+            0x2A => OpCode::ClassLong(read_u24(reader)?),
+            0x2B => OpCode::GetPropertyLong(read_u24(reader)?),
+            0x2C => OpCode::SetPropertyLong(read_u24(reader)?),
+            0x2D => OpCode::MethodLong(read_u24(reader)?),
+            0x2E => OpCode::GetSuperLong(read_u24(reader)?),
+            0x2F => OpCode::JmpIfFalseLong(read_u24(reader)?),
+            0x30 => OpCode::JmpLong(read_u24(reader)?),
+            0x31 => OpCode::LoopLong(read_u24(reader)?),
             unknown => return Err(DecodeError::UnknownOpCode(unknown)),
         };
         Ok(op)
@@ -182,6 +252,30 @@ impl Encode for OpCode {
                 let buf = offset.to_le_bytes();
                 write(&[0x19, buf[0], buf[1]])
             }
+            OpCode::Call(argc) => write(&[0x1A, *argc]),
+            OpCode::Closure(addr) => write(&[0x1B, *addr]),
+            OpCode::GetUpvalue(slot) => write(&[0x1C, slot.0]),
+            OpCode::SetUpvalue(slot) => write(&[0x1D, slot.0]),
+            OpCode::CloseUpvalue => write(&[0x1E]),
+            OpCode::Class(addr) => write(&[0x1F, *addr]),
+            OpCode::GetProperty(addr) => write(&[0x20, *addr]),
+            OpCode::SetProperty(addr) => write(&[0x21, *addr]),
+            OpCode::Method(addr) => write(&[0x22, *addr]),
+            OpCode::Inherit => write(&[0x23]),
+            OpCode::GetSuper(addr) => write(&[0x24, *addr]),
+            OpCode::ConstantLong(addr) => write(&u24_bytes(0x25, *addr)),
+            OpCode::DefGlobalLong(addr) => write(&u24_bytes(0x26, *addr)),
+            OpCode::GetGlobalLong(addr) => write(&u24_bytes(0x27, *addr)),
+            OpCode::SetGlobalLong(addr) => write(&u24_bytes(0x28, *addr)),
+            OpCode::ClosureLong(addr) => write(&u24_bytes(0x29, *addr)),
+            OpCode::ClassLong(addr) => write(&u24_bytes(0x2A, *addr)),
+            OpCode::GetPropertyLong(addr) => write(&u24_bytes(0x2B, *addr)),
+            OpCode::SetPropertyLong(addr) => write(&u24_bytes(0x2C, *addr)),
+            OpCode::MethodLong(addr) => write(&u24_bytes(0x2D, *addr)),
+            OpCode::GetSuperLong(addr) => write(&u24_bytes(0x2E, *addr)),
+            OpCode::JmpIfFalseLong(offset) => write(&u24_bytes(0x2F, *offset)),
+            OpCode::JmpLong(offset) => write(&u24_bytes(0x30, *offset)),
+            OpCode::LoopLong(offset) => write(&u24_bytes(0x31, *offset)),
         }
     }
 }
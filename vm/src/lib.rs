@@ -4,41 +4,262 @@
 use std::{
     fs,
     io::{self, BufRead, BufReader, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use anyhow::Context;
 use lexer::Scanner;
-use report::{Error, Reporter};
+use report::{Error, FileId, Format, Reporter, SourceMap};
 
 use crate::{
+    chunk::{Chunk, LoxcHeader, MAGIC, SourceInfo},
     compiler::Compiler,
-    vm::{VirtualMachine, error::VirtualMachineError},
+    storage::{Storage, WithStorage},
+    vm::{
+        VirtualMachine,
+        debugger::{Debugger, dap, prompt::Prompt},
+        error::VirtualMachineError,
+        profiler::Profiler,
+    },
 };
 
 pub mod chunk;
 pub mod compiler;
-pub(crate) mod debug;
+pub mod debug;
 pub mod enconding;
 pub mod object;
 pub mod storage;
 pub mod value;
 pub mod vm;
 
-pub fn run_file(path: &Path) -> Result<(), Error> {
+/// Directory the bytecode cache lives in, next to the scripts it caches.
+pub const CACHE_DIR: &str = "__loxcache__";
+
+pub fn run_file(path: &Path, format: Format) -> Result<(), Error> {
+    let source = read_source(path)?;
+    run_script(&source, path, &mut VirtualMachine::default(), format)
+}
+
+/// Run the script at `path` under the step debugger, driven from stdin.
+pub fn debug_file(path: &Path, format: Format) -> Result<(), Error> {
+    let source = read_source(path)?;
+    let prompt = Prompt::new(source.as_str(), io::stdin().lock(), io::stdout());
+    run_script(
+        &source,
+        path,
+        &mut VirtualMachine::default().with_debugger(Debugger::new(prompt)),
+        format,
+    )
+}
+
+/// Run the script at `path` under the profiler and report on stderr once it
+/// ends, however it ends. With `folded`, also write its call stacks there for
+/// flamegraph tools.
+pub fn profile_file(path: &Path, folded: Option<&Path>, format: Format) -> Result<(), Error> {
+    let source = read_source(path)?;
+    let mut vm = VirtualMachine::default().with_profiler(Profiler::new());
+    let result = run_script(&source, path, &mut vm, format);
+    let profile = vm.take_profile().expect("profiler installed above");
+    eprint!("{profile}");
+    if let Some(folded) = folded {
+        let context = || format!("could not write folded stacks to {}", folded.display());
+        let mut file = io::BufWriter::new(fs::File::create(folded).with_context(context)?);
+        profile
+            .write_folded(&mut file)
+            .and_then(|()| file.flush())
+            .with_context(context)?;
+    }
+    result
+}
+
+/// Speak the Debug Adapter Protocol over stdio for one debugging session.
+/// Errors go to the client as plain text in `output` events.
+pub fn serve_dap() -> Result<(), Error> {
+    dap::serve(io::stdin().lock(), io::stdout()).context("debug adapter connection failed")?;
+    Ok(())
+}
+
+/// Like [`run_file`], but reuses the script's cached bytecode while the source
+/// is unchanged, and refreshes the cache otherwise.
+pub fn run_file_cached(path: &Path, format: Format) -> Result<(), Error> {
+    let source = read_source(path)?;
+    let cache = cache_path(path);
+    let mut vm = VirtualMachine::default();
+    let mut stderr = io::stderr();
+    let mut reporter = Reporter::new(&source, &mut stderr)
+        .with_path(path)
+        .with_format(format);
+
+    let chunk = match load_cached(&cache, &source, &mut vm) {
+        Some(chunk) => chunk,
+        None => {
+            let chunk = compile(&source, &mut vm, &mut reporter)?;
+            // The cache is only an optimization, so failing to write it is fine.
+            let _ = write_bytecode(&chunk, vm.storage(), source_info(path, &source), &cache);
+            chunk
+        }
+    };
+    execute(chunk, &mut vm, &mut reporter, &mut io::stdout())
+}
+
+/// Compile the script at `path` into a bytecode file at `output`.
+pub fn compile_file(path: &Path, output: &Path, format: Format) -> Result<(), Error> {
+    let source = read_source(path)?;
+    let mut vm = VirtualMachine::default();
+    let mut stderr = io::stderr();
+    let chunk = compile(
+        &source,
+        &mut vm,
+        &mut Reporter::new(&source, &mut stderr)
+            .with_path(path)
+            .with_format(format),
+    )?;
+    write_bytecode(&chunk, vm.storage(), source_info(path, &source), output).inspect_err(
+        |err| {
+            Reporter::new("", &mut stderr)
+                .with_path(output)
+                .with_format(format)
+                .report_unspanned(err)
+        },
+    )?;
+    Ok(())
+}
+
+/// Run a bytecode file written by [`compile_file`].
+pub fn run_bytecode_file(path: &Path, format: Format) -> Result<(), Error> {
+    let mut vm = VirtualMachine::default();
+    let mut stderr = io::stderr();
+    let (chunk, source_path, source) = load_bytecode(path, &mut vm).inspect_err(|err| {
+        Reporter::new("", &mut stderr)
+            .with_path(path)
+            .with_format(format)
+            .report_unspanned(err)
+    })?;
+    // Runtime errors have lines in the source, wherever that went.
+    let source_path = source_path.as_deref().map_or(path, Path::new);
+    let mut reporter = Reporter::new(&source, &mut stderr)
+        .with_path(source_path)
+        .with_format(format);
+    execute(chunk, &mut vm, &mut reporter, &mut io::stdout())
+}
+
+/// Print the bytecode listing of a script or of a bytecode file, nested
+/// functions included.
+pub fn disassemble_file(path: &Path, format: Format) -> Result<(), Error> {
+    let mut vm = VirtualMachine::default();
+    let mut stderr = io::stderr();
+    let bytes =
+        fs::read(path).with_context(|| format!("could not read file {}", path.display()))?;
+    let chunk = if bytes.starts_with(&MAGIC) {
+        let (chunk, ..) = load_bytecode(path, &mut vm).inspect_err(|err| {
+            Reporter::new("", &mut stderr)
+                .with_path(path)
+                .with_format(format)
+                .report_unspanned(err)
+        })?;
+        chunk
+    } else {
+        let source = read_source(path)?;
+        compile(
+            &source,
+            &mut vm,
+            &mut Reporter::new(&source, &mut stderr)
+                .with_path(path)
+                .with_format(format),
+        )?
+    };
+    print!("{}", WithStorage(&chunk, vm.storage()));
+    Ok(())
+}
+
+/// Where [`run_file_cached`] keeps the bytecode for `script`.
+pub fn cache_path(script: &Path) -> PathBuf {
+    let name = script.file_name().unwrap_or_default();
+    script
+        .with_file_name(CACHE_DIR)
+        .join(name)
+        .with_extension("loxc")
+}
+
+fn read_source(path: &Path) -> Result<String, Error> {
     let source = fs::read_to_string(path)
         .with_context(|| format!("could not read source file {}", path.display()))?;
-    run(source, &mut VirtualMachine::default())
+    Ok(source)
 }
 
-pub fn run_source(source: String) -> Result<(), Error> {
-    run(source, &mut VirtualMachine::debug())
+fn source_info(path: &Path, source: &str) -> SourceInfo {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    SourceInfo::new(path.display().to_string(), source)
 }
 
-pub fn run_prompt() -> Result<(), Error> {
+fn load_cached(cache: &Path, source: &str, vm: &mut VirtualMachine) -> Option<Chunk> {
+    let bytes = fs::read(cache).ok()?;
+    let (header, _) = LoxcHeader::parse(&bytes).ok()?;
+    if !header.is_fresh_for(source) {
+        return None;
+    }
+    Chunk::from_bytes(vm.storage(), &bytes).ok()
+}
+
+/// Load a bytecode file, along with the path of the source it was compiled
+/// from and its text when that's still around, so runtime errors can point
+/// into it.
+fn load_bytecode(
+    path: &Path,
+    vm: &mut VirtualMachine,
+) -> anyhow::Result<(Chunk, Option<String>, String)> {
+    let bytes = fs::read(path)
+        .with_context(|| format!("could not read bytecode file {}", path.display()))?;
+    let context = || format!("could not load bytecode file {}", path.display());
+    let (header, _) = LoxcHeader::parse(&bytes).with_context(context)?;
+    let chunk = Chunk::from_bytes(vm.storage(), &bytes).with_context(context)?;
+    let Some(src) = header.source else {
+        return Ok((chunk, None, String::new()));
+    };
+    let source = fs::read_to_string(&src.path)
+        .ok()
+        .filter(|text| src.matches(text))
+        .unwrap_or_default();
+    Ok((chunk, Some(src.path), source))
+}
+
+/// Write through a temporary file so concurrent runs never see a partial image.
+fn write_bytecode(
+    chunk: &Chunk,
+    storage: &Storage,
+    source: SourceInfo,
+    output: &Path,
+) -> anyhow::Result<()> {
+    let context = || format!("could not write bytecode file {}", output.display());
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(context)?;
+    }
+    let tmp = output.with_extension(format!("loxc.{}.tmp", std::process::id()));
+    let mut bytes = Vec::new();
+    chunk
+        .serialize_with_source(storage, Some(source), &mut bytes)
+        .with_context(context)?;
+    fs::write(&tmp, bytes).with_context(context)?;
+    fs::rename(&tmp, output)
+        .inspect_err(|_| drop(fs::remove_file(&tmp)))
+        .with_context(context)
+}
+
+pub fn run_source(source: String, format: Format) -> Result<(), Error> {
+    let mut vm = VirtualMachine::debug();
+    let mut stderr = io::stderr();
+    let mut reporter = Reporter::new(&source, &mut stderr).with_format(format);
+    let chunk = compile(&source, &mut vm, &mut reporter)?;
+    execute(chunk, &mut vm, &mut reporter, &mut io::stdout())
+}
+
+pub fn run_prompt(format: Format) -> Result<(), Error> {
     let mut buf_reader = BufReader::new(io::stdin());
     let mut vm = VirtualMachine::default();
-    loop {
+    // Chunks compiled from an entry outlive it in globals, so their spans
+    // must keep resolving.
+    let mut sources = SourceMap::new();
+    for entry in 1.. {
         print!("> ");
         io::stdout().flush().context("could not flush stdout")?;
 
@@ -49,15 +270,43 @@ pub fn run_prompt() -> Result<(), Error> {
         if read == 0 {
             break;
         }
-        let _ = run(line, &mut vm);
+        let file = sources.add(format!("<repl {entry}>"), line);
+        let _ = run_entry(&sources, file, &mut vm, format);
     }
     Ok(())
 }
 
+fn run_entry(
+    sources: &SourceMap,
+    file: FileId,
+    vm: &mut VirtualMachine,
+    format: Format,
+) -> Result<(), Error> {
+    let mut stderr = io::stderr();
+    let mut reporter = Reporter::with_sources(sources, file, &mut stderr).with_format(format);
+    let chunk = compile(sources[file].text(), vm, &mut reporter)?;
+    execute(chunk, vm, &mut reporter, &mut io::stdout())
+}
+
 pub fn run(source: String, vm: &mut VirtualMachine) -> Result<(), Error> {
     run_with(source, vm, &mut io::stdout(), &mut io::stderr())
 }
 
+/// Like [`run`], for the script at `path`, which errors are reported against.
+fn run_script(
+    source: &str,
+    path: &Path,
+    vm: &mut VirtualMachine,
+    format: Format,
+) -> Result<(), Error> {
+    let mut stderr = io::stderr();
+    let mut reporter = Reporter::new(source, &mut stderr)
+        .with_path(path)
+        .with_format(format);
+    let chunk = compile(source, vm, &mut reporter)?;
+    execute(chunk, vm, &mut reporter, &mut io::stdout())
+}
+
 pub fn run_with(
     source: String,
     vm: &mut VirtualMachine,
@@ -65,17 +314,27 @@ pub fn run_with(
     err: &mut dyn Write,
 ) -> Result<(), Error> {
     let mut reporter = Reporter::new(&source, err);
-    let scanner = Scanner::new(&source);
+    let chunk = compile(&source, vm, &mut reporter)?;
+    execute(chunk, vm, &mut reporter, out)
+}
 
-    let mut compiler = Compiler::new(scanner, &mut reporter, vm.storage());
-    let chunk = match compiler.compile() {
-        Ok(chunk) => chunk,
-        Err(err) => {
-            reporter.report_unspanned(&err);
-            return Err(err.into());
-        }
-    };
+fn compile<'s>(
+    source: &'s str,
+    vm: &mut VirtualMachine,
+    reporter: &mut Reporter<'s, '_>,
+) -> Result<Chunk, Error> {
+    let scanner = Scanner::new(source).with_file(reporter.file());
+    let (storage, roots) = vm.heap_and_roots();
+    // The compiler has reported its errors by the time it fails.
+    Ok(Compiler::new(scanner, reporter, storage, &roots).compile()?)
+}
 
+fn execute(
+    chunk: Chunk,
+    vm: &mut VirtualMachine,
+    reporter: &mut Reporter<'_, '_>,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
     match vm.run_with(chunk, out) {
         Err(VirtualMachineError::Decode(err)) => {
             let err = anyhow::Error::new(err).context("Corrupted chunk");
@@ -86,10 +345,14 @@ pub fn run_with(
             reporter.report(&err);
             Err(err.into())
         }
+        Err(VirtualMachineError::Halt(err)) => {
+            reporter.report_unspanned(&err.into());
+            Err(err.into())
+        }
         Err(VirtualMachineError::Other(err)) => {
             reporter.report_unspanned(&err);
             Err(err.into())
         }
-        Ok(()) => Ok(()),
+        Err(VirtualMachineError::Stopped) | Ok(()) => Ok(()),
     }
 }
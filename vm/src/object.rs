@@ -1,4 +1,5 @@
 use std::{
+    cell::Cell,
     fmt::{self, Debug, Display, Formatter},
     mem,
     ops::Deref,
@@ -9,12 +10,24 @@ use erasable::{Erasable, ErasedPtr, erase};
 use intrusive_collections::{SinglyLinkedListLink, UnsafeRef, intrusive_adapter};
 
 use crate::{
-    object::{function::LoxFunction, string::LoxString},
-    storage::WithStorage,
+    object::{
+        bound_method::LoxBoundMethod, class::LoxClass, closure::LoxClosure, function::LoxFunction,
+        instance::LoxInstance, native::LoxNative, string::LoxString, upvalue::LoxUpvalue,
+    },
+    storage::{
+        WithStorage,
+        gc::{Trace, Tracer},
+    },
 };
 
+pub mod bound_method;
+pub mod class;
+pub mod closure;
 pub mod function;
+pub mod instance;
+pub mod native;
 pub mod string;
+pub mod upvalue;
 
 /// A concrete object kind that can be stored behind an [`Object`] header.
 ///
@@ -35,6 +48,17 @@ pub unsafe trait ObjectType: Erasable {
         // `OwnedObject::from_raw`'s contract.
         unsafe { OwnedObject::from_raw(raw.cast::<Object>()) }
     }
+
+    /// Heap bytes the object owns outside its own allocation, like a field
+    /// map's table.
+    fn owned_bytes(&self) -> usize {
+        0
+    }
+
+    /// Bytes the object takes, as accounted by the collector.
+    fn footprint(&self) -> usize {
+        mem::size_of_val(self) + self.owned_bytes()
+    }
 }
 
 #[repr(C)]
@@ -42,6 +66,8 @@ pub unsafe trait ObjectType: Erasable {
 pub struct Object {
     kind: ObjKind,
     link: SinglyLinkedListLink,
+    /// Set while the collector traces reachable objects; cleared by the sweep.
+    marked: Cell<bool>,
 }
 
 intrusive_adapter!(pub ObjectAdapter = UnsafeRef<Object>: Object { link => SinglyLinkedListLink });
@@ -50,21 +76,66 @@ intrusive_adapter!(pub ObjectAdapter = UnsafeRef<Object>: Object { link => Singl
 pub enum ObjKind {
     String,
     Function,
+    Native,
+    Closure,
+    Upvalue,
+    Class,
+    Instance,
+    BoundMethod,
+}
+
+impl ObjKind {
+    pub const ALL: [ObjKind; 8] = [
+        ObjKind::String,
+        ObjKind::Function,
+        ObjKind::Native,
+        ObjKind::Closure,
+        ObjKind::Upvalue,
+        ObjKind::Class,
+        ObjKind::Instance,
+        ObjKind::BoundMethod,
+    ];
 }
 
 impl Object {
-    pub fn string() -> Self {
+    fn of(kind: ObjKind) -> Self {
         Self {
-            kind: ObjKind::String,
+            kind,
             link: SinglyLinkedListLink::new(),
+            marked: Cell::new(false),
         }
     }
 
+    pub fn string() -> Self {
+        Self::of(ObjKind::String)
+    }
+
     pub fn function() -> Self {
-        Self {
-            kind: ObjKind::Function,
-            link: SinglyLinkedListLink::new(),
-        }
+        Self::of(ObjKind::Function)
+    }
+
+    pub fn native() -> Self {
+        Self::of(ObjKind::Native)
+    }
+
+    pub fn closure() -> Self {
+        Self::of(ObjKind::Closure)
+    }
+
+    pub fn upvalue() -> Self {
+        Self::of(ObjKind::Upvalue)
+    }
+
+    pub fn class() -> Self {
+        Self::of(ObjKind::Class)
+    }
+
+    pub fn instance() -> Self {
+        Self::of(ObjKind::Instance)
+    }
+
+    pub fn bound_method() -> Self {
+        Self::of(ObjKind::BoundMethod)
     }
 
     /// Downcast a shared reference to a concrete kind.
@@ -116,6 +187,52 @@ impl Object {
         self.kind
     }
 
+    /// Mark the object as reachable. Returns `false` if it already was.
+    pub fn mark(&self) -> bool {
+        !self.marked.replace(true)
+    }
+
+    pub fn is_marked(&self) -> bool {
+        self.marked.get()
+    }
+
+    pub fn unmark(&self) {
+        self.marked.set(false);
+    }
+
+    /// [`ObjectType::footprint`] of the concrete object.
+    pub fn size(self: &UnsafeRef<Self>) -> usize {
+        // SAFETY: matched kind witnesses the dynamic type.
+        unsafe {
+            match self.kind() {
+                ObjKind::String => self.downcast_ref::<LoxString>().footprint(),
+                ObjKind::Function => self.downcast_ref::<LoxFunction>().footprint(),
+                ObjKind::Native => self.downcast_ref::<LoxNative>().footprint(),
+                ObjKind::Closure => self.downcast_ref::<LoxClosure>().footprint(),
+                ObjKind::Upvalue => self.downcast_ref::<LoxUpvalue>().footprint(),
+                ObjKind::Class => self.downcast_ref::<LoxClass>().footprint(),
+                ObjKind::Instance => self.downcast_ref::<LoxInstance>().footprint(),
+                ObjKind::BoundMethod => self.downcast_ref::<LoxBoundMethod>().footprint(),
+            }
+        }
+    }
+
+    /// Mark every object directly referenced by this one.
+    pub fn trace_children(self: &UnsafeRef<Self>, tracer: &mut Tracer) {
+        // SAFETY: matched kind witnesses the dynamic type.
+        unsafe {
+            match self.kind() {
+                ObjKind::String | ObjKind::Native => {}
+                ObjKind::Function => self.downcast_ref::<LoxFunction>().trace(tracer),
+                ObjKind::Closure => self.downcast_ref::<LoxClosure>().trace(tracer),
+                ObjKind::Upvalue => self.downcast_ref::<LoxUpvalue>().trace(tracer),
+                ObjKind::Class => self.downcast_ref::<LoxClass>().trace(tracer),
+                ObjKind::Instance => self.downcast_ref::<LoxInstance>().trace(tracer),
+                ObjKind::BoundMethod => self.downcast_ref::<LoxBoundMethod>().trace(tracer),
+            }
+        }
+    }
+
     pub fn as_str(self: &UnsafeRef<Self>) -> &str {
         // SAFETY: matched kind witnesses the dynamic type on each side.
         match self.kind() {
@@ -132,7 +249,14 @@ impl Object {
             (ObjKind::String, ObjKind::String) => unsafe {
                 self.downcast_ref::<LoxString>() == other.downcast_ref::<LoxString>()
             },
-            (ObjKind::Function, ObjKind::Function) => ptr::eq(self.as_ref(), other.as_ref()),
+            (ObjKind::Function, ObjKind::Function)
+            | (ObjKind::Native, ObjKind::Native)
+            | (ObjKind::Closure, ObjKind::Closure)
+            | (ObjKind::Class, ObjKind::Class)
+            | (ObjKind::Instance, ObjKind::Instance)
+            | (ObjKind::BoundMethod, ObjKind::BoundMethod) => {
+                ptr::eq(self.as_ref(), other.as_ref())
+            }
             _ => false,
         }
     }
@@ -142,6 +266,14 @@ impl Object {
             // SAFETY: matched kind witnesses the dynamic type.
             ObjKind::String => Display::fmt(unsafe { self.downcast_ref::<LoxString>() }, f),
             ObjKind::Function => Display::fmt(unsafe { self.downcast_ref::<LoxFunction>() }, f),
+            ObjKind::Native => Display::fmt(unsafe { self.downcast_ref::<LoxNative>() }, f),
+            ObjKind::Closure => Display::fmt(unsafe { self.downcast_ref::<LoxClosure>() }, f),
+            ObjKind::Upvalue => Display::fmt(unsafe { self.downcast_ref::<LoxUpvalue>() }, f),
+            ObjKind::Class => Display::fmt(unsafe { self.downcast_ref::<LoxClass>() }, f),
+            ObjKind::Instance => Display::fmt(unsafe { self.downcast_ref::<LoxInstance>() }, f),
+            ObjKind::BoundMethod => {
+                Display::fmt(unsafe { self.downcast_ref::<LoxBoundMethod>() }, f)
+            }
         }
     }
 }
@@ -153,7 +285,27 @@ impl Display for WithStorage<'_, UnsafeRef<Object>> {
                 // SAFETY: matched kind witnesses the dynamic type.
                 WithStorage(unsafe { self.0.downcast_ref::<LoxFunction>() }, self.1).fmt(f)
             }
-            ObjKind::String => self.0.display_fmt(f),
+            ObjKind::Native => {
+                // SAFETY: matched kind witnesses the dynamic type.
+                WithStorage(unsafe { self.0.downcast_ref::<LoxNative>() }, self.1).fmt(f)
+            }
+            ObjKind::Closure => {
+                // SAFETY: matched kind witnesses the dynamic type.
+                WithStorage(unsafe { self.0.downcast_ref::<LoxClosure>() }, self.1).fmt(f)
+            }
+            ObjKind::Class => {
+                // SAFETY: matched kind witnesses the dynamic type.
+                WithStorage(unsafe { self.0.downcast_ref::<LoxClass>() }, self.1).fmt(f)
+            }
+            ObjKind::Instance => {
+                // SAFETY: matched kind witnesses the dynamic type.
+                WithStorage(unsafe { self.0.downcast_ref::<LoxInstance>() }, self.1).fmt(f)
+            }
+            ObjKind::BoundMethod => {
+                // SAFETY: matched kind witnesses the dynamic type.
+                WithStorage(unsafe { self.0.downcast_ref::<LoxBoundMethod>() }, self.1).fmt(f)
+            }
+            ObjKind::String | ObjKind::Upvalue => self.0.display_fmt(f),
         }
     }
 }
@@ -212,6 +364,20 @@ impl Drop for OwnedObject {
             ObjKind::Function => {
                 drop(unsafe { Box::from_raw(LoxFunction::unerase(erased).as_ptr()) })
             }
+            ObjKind::Native => drop(unsafe { Box::from_raw(LoxNative::unerase(erased).as_ptr()) }),
+            ObjKind::Closure => {
+                drop(unsafe { Box::from_raw(LoxClosure::unerase(erased).as_ptr()) })
+            }
+            ObjKind::Upvalue => {
+                drop(unsafe { Box::from_raw(LoxUpvalue::unerase(erased).as_ptr()) })
+            }
+            ObjKind::Class => drop(unsafe { Box::from_raw(LoxClass::unerase(erased).as_ptr()) }),
+            ObjKind::Instance => {
+                drop(unsafe { Box::from_raw(LoxInstance::unerase(erased).as_ptr()) })
+            }
+            ObjKind::BoundMethod => {
+                drop(unsafe { Box::from_raw(LoxBoundMethod::unerase(erased).as_ptr()) })
+            }
         }
     }
 }
@@ -0,0 +1,59 @@
+use std::fmt::Display;
+
+use intrusive_collections::UnsafeRef;
+
+use crate::{
+    object::{Object, ObjectType, closure::LoxClosure},
+    storage::{
+        WithStorage,
+        gc::{Trace, Tracer},
+    },
+    value::Value,
+};
+
+/// A method read off an instance, remembering the receiver it binds `this` to.
+#[repr(C)]
+#[derive(Debug)]
+pub struct LoxBoundMethod {
+    obj: Object,
+    pub receiver: Value,
+    pub method: UnsafeRef<LoxClosure>,
+}
+
+// SAFETY: `LoxBoundMethod` is `#[repr(C)]` with `Object` (`obj`) as its first
+// field, so an `Object` header at offset 0 is layout-compatible. Construction
+// goes through `Self::new`, which sets `obj.kind = ObjKind::BoundMethod`.
+unsafe impl ObjectType for LoxBoundMethod {}
+
+impl LoxBoundMethod {
+    pub fn new(receiver: Value, method: UnsafeRef<LoxClosure>) -> Self {
+        Self {
+            obj: Object::bound_method(),
+            receiver,
+            method,
+        }
+    }
+
+    pub fn boxed(receiver: Value, method: UnsafeRef<LoxClosure>) -> Box<Self> {
+        Box::new(Self::new(receiver, method))
+    }
+}
+
+impl Trace for LoxBoundMethod {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark_value(&self.receiver);
+        tracer.mark(&self.method);
+    }
+}
+
+impl Display for LoxBoundMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.method.fmt(f)
+    }
+}
+
+impl Display for WithStorage<'_, LoxBoundMethod> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        WithStorage(&*self.0.method, self.1).fmt(f)
+    }
+}
@@ -0,0 +1,80 @@
+use std::{cell::RefCell, fmt::Display};
+
+use intrusive_collections::UnsafeRef;
+use lasso::Spur;
+
+use crate::{
+    object::{Object, ObjectType, closure::LoxClosure},
+    storage::{
+        SymbolMap, WithStorage,
+        gc::{Trace, Tracer},
+        stats::map_bytes,
+    },
+};
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LoxClass {
+    obj: Object,
+    pub name: Spur,
+    /// Filled in by `OpCode::Method` right after the class is created.
+    methods: RefCell<SymbolMap<UnsafeRef<LoxClosure>>>,
+}
+
+// SAFETY: `LoxClass` is `#[repr(C)]` with `Object` (`obj`) as its first
+// field, so an `Object` header at offset 0 is layout-compatible. Construction
+// goes through `Self::new`, which sets `obj.kind = ObjKind::Class`.
+unsafe impl ObjectType for LoxClass {
+    fn owned_bytes(&self) -> usize {
+        map_bytes(&self.methods.borrow())
+    }
+}
+
+impl LoxClass {
+    pub fn new(name: Spur) -> Self {
+        Self {
+            obj: Object::class(),
+            name,
+            methods: RefCell::default(),
+        }
+    }
+
+    pub fn boxed(name: Spur) -> Box<Self> {
+        Box::new(Self::new(name))
+    }
+
+    pub fn method(&self, name: Spur) -> Option<UnsafeRef<LoxClosure>> {
+        self.methods.borrow().get(&name).cloned()
+    }
+
+    pub fn add_method(&self, name: Spur, method: UnsafeRef<LoxClosure>) {
+        self.methods.borrow_mut().insert(name, method);
+    }
+
+    /// Copy every method of `superclass` down into this class. Runs before
+    /// the subclass's own methods are added, so those override.
+    pub fn inherit(&self, superclass: &LoxClass) {
+        let inherited = superclass.methods.borrow();
+        self.methods
+            .borrow_mut()
+            .extend(inherited.iter().map(|(k, v)| (*k, v.clone())));
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.methods.borrow().values().for_each(|m| tracer.mark(m));
+    }
+}
+
+impl Display for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<class Symbol({})>", self.name.into_inner())
+    }
+}
+
+impl Display for WithStorage<'_, LoxClass> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.1.resolve(self.0.name))
+    }
+}
@@ -0,0 +1,65 @@
+use std::{fmt::Display, mem};
+
+use intrusive_collections::UnsafeRef;
+
+use crate::{
+    object::{Object, ObjectType, function::LoxFunction, upvalue::LoxUpvalue},
+    storage::{
+        WithStorage,
+        gc::{Trace, Tracer},
+    },
+};
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LoxClosure {
+    obj: Object,
+    pub function: UnsafeRef<LoxFunction>,
+    pub upvalues: Box<[UnsafeRef<LoxUpvalue>]>,
+}
+
+// SAFETY: `LoxClosure` is `#[repr(C)]` with `Object` (`obj`) as its first
+// field, so an `Object` header at offset 0 is layout-compatible. Construction
+// goes through `Self::new`, which sets `obj.kind = ObjKind::Closure`.
+unsafe impl ObjectType for LoxClosure {
+    fn owned_bytes(&self) -> usize {
+        mem::size_of_val(&*self.upvalues)
+    }
+}
+
+impl LoxClosure {
+    pub fn new(function: UnsafeRef<LoxFunction>, upvalues: Box<[UnsafeRef<LoxUpvalue>]>) -> Self {
+        debug_assert_eq!(function.captures.len(), upvalues.len());
+        Self {
+            obj: Object::closure(),
+            function,
+            upvalues,
+        }
+    }
+
+    pub fn boxed(
+        function: UnsafeRef<LoxFunction>,
+        upvalues: Box<[UnsafeRef<LoxUpvalue>]>,
+    ) -> Box<Self> {
+        Box::new(Self::new(function, upvalues))
+    }
+}
+
+impl Trace for LoxClosure {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.function);
+        self.upvalues.iter().for_each(|u| tracer.mark(u));
+    }
+}
+
+impl Display for LoxClosure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.function.fmt(f)
+    }
+}
+
+impl Display for WithStorage<'_, LoxClosure> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        WithStorage(&*self.0.function, self.1).fmt(f)
+    }
+}
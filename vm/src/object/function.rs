@@ -1,13 +1,37 @@
-use std::fmt::Display;
+use std::{fmt::Display, mem};
 
 use lasso::Spur;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     chunk::Chunk,
+    enconding::{LocalSlot, UpvalueSlot},
     object::{Object, ObjectType},
-    storage::WithStorage,
+    storage::{
+        WithStorage,
+        gc::{Trace, Tracer},
+    },
 };
 
+/// Where a closure finds one of its upvalues when it is created: a local of
+/// the enclosing frame, or an upvalue already captured by the enclosing closure.
+///
+/// Variant order is part of the bytecode wire format — only ever append.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Capture {
+    Local(LocalSlot),
+    Upvalue(UpvalueSlot),
+}
+
+impl Display for Capture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capture::Local(slot) => write!(f, "[local {}]", slot.0),
+            Capture::Upvalue(slot) => write!(f, "[upvalue {}]", slot.0),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct LoxFunction {
@@ -15,25 +39,37 @@ pub struct LoxFunction {
     pub chunk: Chunk,
     pub name: Spur,
     pub arity: u8,
+    pub captures: Box<[Capture]>,
 }
 
 // SAFETY: `LoxFunction` is `#[repr(C)]` with `Object` (`obj`) as its first
 // field, so an `Object` header at offset 0 is layout-compatible. Construction
 // goes through `Self::new`, which sets `obj.kind = ObjKind::Function`.
-unsafe impl ObjectType for LoxFunction {}
+unsafe impl ObjectType for LoxFunction {
+    fn owned_bytes(&self) -> usize {
+        self.chunk.owned_bytes() + mem::size_of_val(&*self.captures)
+    }
+}
 
 impl LoxFunction {
-    pub fn new(name: Spur, arity: u8, chunk: Chunk) -> Self {
+    pub fn new(name: Spur, arity: u8, chunk: Chunk, captures: Box<[Capture]>) -> Self {
         Self {
             obj: Object::function(),
             chunk,
             name,
             arity,
+            captures,
         }
     }
 
-    pub fn boxed(name: Spur, arity: u8, chunk: Chunk) -> Box<Self> {
-        Box::new(Self::new(name, arity, chunk))
+    pub fn boxed(name: Spur, arity: u8, chunk: Chunk, captures: Box<[Capture]>) -> Box<Self> {
+        Box::new(Self::new(name, arity, chunk, captures))
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.chunk.trace(tracer);
     }
 }
 
@@ -0,0 +1,75 @@
+use std::{cell::RefCell, fmt::Display};
+
+use intrusive_collections::UnsafeRef;
+use lasso::Spur;
+
+use crate::{
+    object::{Object, ObjectType, class::LoxClass},
+    storage::{
+        SymbolMap, WithStorage,
+        gc::{Trace, Tracer},
+        stats::map_bytes,
+    },
+    value::Value,
+};
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LoxInstance {
+    obj: Object,
+    pub class: UnsafeRef<LoxClass>,
+    fields: RefCell<SymbolMap<Value>>,
+}
+
+// SAFETY: `LoxInstance` is `#[repr(C)]` with `Object` (`obj`) as its first
+// field, so an `Object` header at offset 0 is layout-compatible. Construction
+// goes through `Self::new`, which sets `obj.kind = ObjKind::Instance`.
+unsafe impl ObjectType for LoxInstance {
+    fn owned_bytes(&self) -> usize {
+        map_bytes(&self.fields.borrow())
+    }
+}
+
+impl LoxInstance {
+    pub fn new(class: UnsafeRef<LoxClass>) -> Self {
+        Self {
+            obj: Object::instance(),
+            class,
+            fields: RefCell::default(),
+        }
+    }
+
+    pub fn boxed(class: UnsafeRef<LoxClass>) -> Box<Self> {
+        Box::new(Self::new(class))
+    }
+
+    pub fn field(&self, name: Spur) -> Option<Value> {
+        self.fields.borrow().get(&name).cloned()
+    }
+
+    pub fn set_field(&self, name: Spur, value: Value) {
+        self.fields.borrow_mut().insert(name, value);
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.class);
+        self.fields
+            .borrow()
+            .values()
+            .for_each(|v| tracer.mark_value(v));
+    }
+}
+
+impl Display for LoxInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", *self.class)
+    }
+}
+
+impl Display for WithStorage<'_, LoxInstance> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", WithStorage(&*self.0.class, self.1))
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt::Display;
+
+use lasso::Spur;
+
+use crate::{
+    object::{Object, ObjectType},
+    storage::WithStorage,
+    value::Value,
+};
+
+/// Host function callable from Lox. Receives exactly `arity` arguments.
+pub type NativeFn = fn(&[Value]) -> Value;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LoxNative {
+    obj: Object,
+    pub name: Spur,
+    pub arity: u8,
+    pub func: NativeFn,
+}
+
+// SAFETY: `LoxNative` is `#[repr(C)]` with `Object` (`obj`) as its first
+// field, so an `Object` header at offset 0 is layout-compatible. Construction
+// goes through `Self::new`, which sets `obj.kind = ObjKind::Native`.
+unsafe impl ObjectType for LoxNative {}
+
+impl LoxNative {
+    pub fn new(name: Spur, arity: u8, func: NativeFn) -> Self {
+        Self {
+            obj: Object::native(),
+            name,
+            arity,
+            func,
+        }
+    }
+
+    pub fn boxed(name: Spur, arity: u8, func: NativeFn) -> Box<Self> {
+        Box::new(Self::new(name, arity, func))
+    }
+}
+
+impl Display for LoxNative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn Symbol({})>", self.name.into_inner())
+    }
+}
+
+impl Display for WithStorage<'_, LoxNative> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.1.resolve(self.0.name))
+    }
+}
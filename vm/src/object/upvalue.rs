@@ -0,0 +1,74 @@
+use std::{cell::RefCell, fmt::Display};
+
+use crate::{
+    object::{Object, ObjectType},
+    storage::gc::{Trace, Tracer},
+    value::Value,
+};
+
+/// A variable captured by one or more closures. While the variable still lives
+/// on the VM stack the upvalue is open and refers to its slot; once the slot
+/// goes out of scope the value is moved into the upvalue itself.
+#[repr(C)]
+#[derive(Debug)]
+pub struct LoxUpvalue {
+    obj: Object,
+    state: RefCell<UpvalueState>,
+}
+
+#[derive(Debug, Clone)]
+pub enum UpvalueState {
+    /// Absolute index of the captured slot on the VM stack.
+    Open(usize),
+    Closed(Value),
+}
+
+// SAFETY: `LoxUpvalue` is `#[repr(C)]` with `Object` (`obj`) as its first
+// field, so an `Object` header at offset 0 is layout-compatible. Construction
+// goes through `Self::new`, which sets `obj.kind = ObjKind::Upvalue`.
+unsafe impl ObjectType for LoxUpvalue {}
+
+impl LoxUpvalue {
+    pub fn new(slot: usize) -> Self {
+        Self {
+            obj: Object::upvalue(),
+            state: RefCell::new(UpvalueState::Open(slot)),
+        }
+    }
+
+    pub fn boxed(slot: usize) -> Box<Self> {
+        Box::new(Self::new(slot))
+    }
+
+    /// The stack slot this upvalue refers to, if it is still open.
+    pub fn open_slot(&self) -> Option<usize> {
+        match *self.state.borrow() {
+            UpvalueState::Open(slot) => Some(slot),
+            UpvalueState::Closed(_) => None,
+        }
+    }
+
+    pub fn state(&self) -> UpvalueState {
+        self.state.borrow().clone()
+    }
+
+    /// Overwrite the value of a closed upvalue, or move `value` in when closing.
+    pub fn close(&self, value: Value) {
+        *self.state.borrow_mut() = UpvalueState::Closed(value);
+    }
+}
+
+impl Trace for LoxUpvalue {
+    fn trace(&self, tracer: &mut Tracer) {
+        // An open upvalue's value lives on the stack, which is a root already.
+        if let UpvalueState::Closed(value) = &*self.state.borrow() {
+            tracer.mark_value(value);
+        }
+    }
+}
+
+impl Display for LoxUpvalue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "upvalue")
+    }
+}
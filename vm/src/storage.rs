@@ -4,7 +4,16 @@ use intrusive_collections::{SinglyLinkedList, UnsafeRef};
 use lasso::{Rodeo, Spur};
 use rustc_hash::FxBuildHasher;
 
-use crate::object::{Object, ObjectAdapter, ObjectType, OwnedObject};
+use crate::{
+    object::{ObjKind, Object, ObjectAdapter, ObjectType, OwnedObject},
+    storage::{
+        gc::{GcConfig, Trace, Tracer},
+        stats::{HeapStats, HeapUsage},
+    },
+};
+
+pub mod gc;
+pub mod stats;
 
 pub type SymbolMap<V> = HashMap<Spur, V, FxBuildHasher>;
 
@@ -12,10 +21,27 @@ pub type SymbolMap<V> = HashMap<Spur, V, FxBuildHasher>;
 pub struct WithStorage<'a, T: ?Sized>(pub &'a T, pub &'a Storage);
 
 /// Owns the runtime heap (object pool) and the string interner.
-#[derive(Default)]
+///
+/// Allocation never collects by itself: whoever owns the roots checks
+/// [`Storage::should_collect`] at a safepoint and calls [`Storage::collect`]
+/// while everything it still needs is reachable from those roots. The same
+/// goes for the optional heap limit: allocation doesn't enforce it, the owner
+/// checks [`Storage::fits`] first.
 pub struct Storage {
     heap: ObjectPool,
     strings: Rodeo,
+    gc: GcConfig,
+    live: HeapUsage,
+    peak_bytes: usize,
+    collections: usize,
+    limit: Option<usize>,
+    next_gc: usize,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::with_gc(GcConfig::default())
+    }
 }
 
 impl Storage {
@@ -23,6 +49,59 @@ impl Storage {
         Self::default()
     }
 
+    pub fn with_gc(gc: GcConfig) -> Self {
+        Self {
+            heap: ObjectPool::default(),
+            strings: Rodeo::default(),
+            gc,
+            live: HeapUsage::default(),
+            peak_bytes: 0,
+            collections: 0,
+            limit: None,
+            next_gc: gc.threshold,
+        }
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.live.total().bytes
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            live: self.live,
+            peak_bytes: self.peak_bytes,
+            collections: self.collections,
+            limit: self.limit,
+        }
+    }
+
+    /// Cap the bytes live objects may take, or lift the cap.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// Whether `bytes` more fit under the heap limit.
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.limit
+            .is_none_or(|limit| self.bytes_allocated() + bytes <= limit)
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.gc.stress || self.bytes_allocated() > self.next_gc
+    }
+
+    /// Mark everything reachable from `roots` and free the rest.
+    pub fn collect(&mut self, roots: &[&dyn Trace]) {
+        let mut tracer = Tracer::default();
+        for root in roots {
+            root.trace(&mut tracer);
+        }
+        tracer.trace_references();
+        self.live.remove(&self.heap.sweep());
+        self.collections += 1;
+        self.next_gc = self.gc.next_trigger(self.bytes_allocated());
+    }
+
     pub fn intern(&mut self, s: &str) -> Spur {
         self.strings.get_or_intern(s)
     }
@@ -32,7 +111,19 @@ impl Storage {
     }
 
     pub fn add_obj<T: ObjectType + ?Sized>(&mut self, obj: Box<T>) -> UnsafeRef<Object> {
-        self.heap.add(obj)
+        let obj = self.heap.add(obj);
+        self.live.add(obj.kind(), obj.size());
+        self.peak_bytes = self.peak_bytes.max(self.bytes_allocated());
+        obj
+    }
+
+    /// Account for a live object of `kind` having gone from `from` to `to`
+    /// bytes since it was added, e.g. as its field map grew. Objects must be
+    /// resized whenever their footprint changes, since the sweep frees what
+    /// they take at that point.
+    pub fn resize(&mut self, kind: ObjKind, from: usize, to: usize) {
+        self.live.resize(kind, from, to);
+        self.peak_bytes = self.peak_bytes.max(self.bytes_allocated());
     }
 }
 
@@ -55,6 +146,28 @@ impl ObjectPool {
         self.0.push_front(obj_ref.clone());
         obj_ref
     }
+
+    /// Free every unmarked object and clear the marks on the survivors.
+    /// Returns what was freed.
+    pub fn sweep(&mut self) -> HeapUsage {
+        let mut freed = HeapUsage::default();
+        let mut cursor = self.0.cursor_mut();
+        while let Some(obj) = cursor.peek_next().get() {
+            if obj.is_marked() {
+                obj.unmark();
+                cursor.move_next();
+                continue;
+            }
+            let obj_ref = cursor.remove_next().expect("peeked above");
+            freed.add(obj_ref.kind(), obj_ref.size());
+            let raw = UnsafeRef::into_raw(obj_ref);
+            // SAFETY: same as in `Drop`: the entry came from `add`, and an
+            // unmarked object is unreachable from every root, so no live
+            // `UnsafeRef` to it will be dereferenced again.
+            drop(unsafe { OwnedObject::from_raw(raw) });
+        }
+        freed
+    }
 }
 
 impl Drop for ObjectPool {
@@ -74,8 +187,13 @@ impl Drop for ObjectPool {
 
 #[cfg(test)]
 mod tests {
+    use std::mem;
+
     use super::*;
-    use crate::object::{ObjKind, string::LoxString};
+    use crate::{
+        object::{ObjKind, class::LoxClass, instance::LoxInstance, string::LoxString},
+        value::Value,
+    };
 
     #[test]
     fn add_obj_routes_through_pool() {
@@ -119,4 +237,74 @@ mod tests {
         // Don't drop `s` — pool owns the alloc.
         let _ = UnsafeRef::into_raw(s);
     }
+
+    #[test]
+    fn collect_frees_unrooted_objects() {
+        let mut storage = Storage::new();
+        let _ = UnsafeRef::into_raw(storage.add_obj(LoxString::boxed("garbage")));
+        assert!(storage.bytes_allocated() > 0);
+        storage.collect(&[]);
+        assert_eq!(storage.bytes_allocated(), 0);
+    }
+
+    #[test]
+    fn collect_keeps_objects_reachable_from_roots() {
+        let mut storage = Storage::new();
+        let name = storage.intern("A");
+        let class = storage.add_obj(LoxClass::boxed(name));
+        // SAFETY: just allocated as a `LoxClass`.
+        let class = unsafe { class.downcast::<LoxClass>() };
+        let instance = storage.add_obj(LoxInstance::boxed(class));
+        let _ = UnsafeRef::into_raw(storage.add_obj(LoxString::boxed("garbage")));
+
+        let root = Value::object(instance);
+        storage.collect(&[&root]);
+        // The class survives through the instance; only the string is freed.
+        assert_eq!(
+            storage.bytes_allocated(),
+            mem::size_of::<LoxClass>() + mem::size_of::<LoxInstance>()
+        );
+        // Marks are cleared, so a second pass keeps the same objects.
+        storage.collect(&[&root]);
+        assert_eq!(
+            storage.bytes_allocated(),
+            mem::size_of::<LoxClass>() + mem::size_of::<LoxInstance>()
+        );
+    }
+
+    #[test]
+    fn stats_track_each_kind() {
+        let mut storage = Storage::new();
+        let name = storage.intern("A");
+        let class = storage.add_obj(LoxClass::boxed(name));
+        let _ = UnsafeRef::into_raw(storage.add_obj(LoxString::boxed("one")));
+        let _ = UnsafeRef::into_raw(storage.add_obj(LoxString::boxed("two")));
+        let peak = storage.bytes_allocated();
+
+        let root = Value::object(class);
+        storage.collect(&[&root]);
+        let stats = storage.stats();
+        let kinds: Vec<_> = stats.live.kinds().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [ObjKind::Class]);
+        assert_eq!(stats.live.kind(ObjKind::String), Default::default());
+        assert_eq!(stats.live.total().objects, 1);
+        assert_eq!(stats.live.total().bytes, mem::size_of::<LoxClass>());
+        assert_eq!(stats.peak_bytes, peak);
+        assert_eq!(stats.collections, 1);
+    }
+
+    #[test]
+    fn limit_bounds_what_fits() {
+        let mut storage = Storage::new();
+        assert!(storage.fits(usize::MAX / 2));
+        storage.set_limit(Some(64));
+        assert!(storage.fits(64));
+        assert!(!storage.fits(65));
+    }
+
+    #[test]
+    fn stress_mode_always_wants_to_collect() {
+        assert!(!Storage::new().should_collect());
+        assert!(Storage::with_gc(GcConfig::stress()).should_collect());
+    }
 }
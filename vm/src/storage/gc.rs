@@ -0,0 +1,106 @@
+use intrusive_collections::UnsafeRef;
+
+use crate::{
+    chunk::Chunk,
+    object::{Object, ObjectType},
+    value::Value,
+};
+
+/// Bytes allocated before the first collection.
+pub const DEFAULT_THRESHOLD: usize = 1024 * 1024;
+
+/// After a collection, the next one triggers once the heap has grown by this factor.
+const HEAP_GROW_FACTOR: usize = 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+    /// Heap size (in bytes) that triggers the first collection, and the floor
+    /// for every later trigger.
+    pub threshold: usize,
+    /// Collect at every safepoint regardless of heap size. Slow; meant for
+    /// flushing out missing roots in tests.
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            stress: false,
+        }
+    }
+}
+
+impl GcConfig {
+    pub fn stress() -> Self {
+        Self {
+            stress: true,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn next_trigger(&self, live_bytes: usize) -> usize {
+        (live_bytes * HEAP_GROW_FACTOR).max(self.threshold)
+    }
+}
+
+/// Anything that holds references into the heap: roots hand their objects to
+/// the [`Tracer`], and heap objects do the same for their children.
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+
+/// Marks reachable objects and keeps the gray worklist of those whose
+/// children still need tracing.
+#[derive(Default)]
+pub struct Tracer {
+    gray: Vec<UnsafeRef<Object>>,
+}
+
+impl Tracer {
+    pub fn mark_value(&mut self, value: &Value) {
+        if let Value::Object(obj) = value {
+            self.mark_object(obj);
+        }
+    }
+
+    pub fn mark_object(&mut self, obj: &UnsafeRef<Object>) {
+        if obj.mark() {
+            self.gray.push(obj.clone());
+        }
+    }
+
+    /// Mark a typed reference through its `Object` header.
+    pub fn mark<T: ObjectType>(&mut self, obj: &UnsafeRef<T>) {
+        let raw = UnsafeRef::into_raw(obj.clone());
+        // SAFETY: `ObjectType` guarantees `Object` is the first field of the
+        // `#[repr(C)]` `T`, so the cast yields a valid header pointer to the
+        // same allocation.
+        let header = unsafe { UnsafeRef::from_raw(raw.cast::<Object>()) };
+        self.mark_object(&header);
+    }
+
+    /// Drain the gray worklist, marking everything reachable from it.
+    pub(crate) fn trace_references(&mut self) {
+        while let Some(obj) = self.gray.pop() {
+            obj.trace_children(self);
+        }
+    }
+}
+
+impl Trace for Value {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark_value(self);
+    }
+}
+
+impl Trace for Chunk {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.constants.iter().for_each(|v| tracer.mark_value(v));
+    }
+}
+
+/// No roots, for storages that are never collected from outside the VM.
+impl Trace for () {
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
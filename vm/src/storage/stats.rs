@@ -0,0 +1,101 @@
+use std::{
+    fmt::{self, Display},
+    mem,
+};
+
+use lasso::Spur;
+
+use crate::{object::ObjKind, storage::SymbolMap};
+
+/// Roughly the bytes `map`'s table takes: an entry and a control byte for
+/// every slot it has room for.
+pub fn map_bytes<V>(map: &SymbolMap<V>) -> usize {
+    map.capacity() * (mem::size_of::<(Spur, V)>() + 1)
+}
+
+/// Objects and the bytes they take.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+/// Heap usage, overall and per object kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapUsage {
+    total: Usage,
+    by_kind: [Usage; ObjKind::ALL.len()],
+}
+
+impl HeapUsage {
+    pub fn total(&self) -> Usage {
+        self.total
+    }
+
+    pub fn kind(&self, kind: ObjKind) -> Usage {
+        self.by_kind[kind as usize]
+    }
+
+    /// Kinds with live objects, with their usage.
+    pub fn kinds(&self) -> impl Iterator<Item = (ObjKind, Usage)> + '_ {
+        ObjKind::ALL
+            .into_iter()
+            .map(|kind| (kind, self.kind(kind)))
+            .filter(|(_, usage)| usage.objects > 0)
+    }
+
+    pub(super) fn add(&mut self, kind: ObjKind, bytes: usize) {
+        for usage in [&mut self.total, &mut self.by_kind[kind as usize]] {
+            usage.objects += 1;
+            usage.bytes += bytes;
+        }
+    }
+
+    pub(super) fn resize(&mut self, kind: ObjKind, from: usize, to: usize) {
+        for usage in [&mut self.total, &mut self.by_kind[kind as usize]] {
+            usage.bytes = usage.bytes - from + to;
+        }
+    }
+
+    pub(super) fn remove(&mut self, freed: &HeapUsage) {
+        let pairs = self.by_kind.iter_mut().zip(&freed.by_kind);
+        for (usage, freed) in pairs.chain([(&mut self.total, &freed.total)]) {
+            usage.objects -= freed.objects;
+            usage.bytes -= freed.bytes;
+        }
+    }
+}
+
+/// A snapshot of the heap: what's live, what it peaked at, and the cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    pub live: HeapUsage,
+    pub peak_bytes: usize,
+    pub collections: usize,
+    pub limit: Option<usize>,
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.live.total();
+        write!(
+            f,
+            "{} bytes in {} objects (peak {} bytes",
+            total.bytes, total.objects, self.peak_bytes
+        )?;
+        if let Some(limit) = self.limit {
+            write!(f, ", limit {limit} bytes")?;
+        }
+        writeln!(f, ", {} collections)", self.collections)?;
+        for (kind, usage) in self.live.kinds() {
+            writeln!(
+                f,
+                "  {:<12} {:>10} bytes {:>8} objects",
+                format!("{kind:?}"),
+                usage.bytes,
+                usage.objects
+            )?;
+        }
+        Ok(())
+    }
+}
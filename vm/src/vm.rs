@@ -4,36 +4,124 @@ use std::{
     io::{self, Cursor, Write},
     mem,
     ops::{Add, Div, Mul, Sub},
+    ptr,
 };
 
 use anyhow::Context;
+use intrusive_collections::UnsafeRef;
 use lasso::Spur;
-use report::{Span, error::RuntimeError};
+use report::{
+    Code, Span,
+    diagnostic::Annotate,
+    error::{HaltError, RuntimeError, halt::InterruptHandle, runtime::TraceFrame},
+};
 
 use crate::{
     chunk::Chunk,
-    debug::LineInfo,
-    enconding::{Addr, LocalSlot, OpCode, OpDecoder},
-    object::{Object, string::LoxString},
-    storage::{Storage, SymbolMap, WithStorage},
+    enconding::{LocalSlot, LongAddr, LongOffset, OpCode, OpDecoder, UpvalueSlot},
+    object::{
+        ObjKind, Object, ObjectType,
+        bound_method::LoxBoundMethod,
+        class::LoxClass,
+        closure::LoxClosure,
+        function::{Capture, LoxFunction},
+        instance::LoxInstance,
+        native::{LoxNative, NativeFn},
+        string::LoxString,
+        upvalue::{LoxUpvalue, UpvalueState},
+    },
+    storage::{
+        Storage, SymbolMap, WithStorage,
+        gc::{GcConfig, Trace, Tracer},
+        stats::HeapStats,
+    },
     value::{Value, ValueError},
-    vm::{error::VirtualMachineError, frame::CallFrame, stack::Stack},
+    vm::{
+        debugger::Debugger,
+        error::VirtualMachineError,
+        frame::CallFrame,
+        profiler::{Profile, Profiler},
+        stack::Stack,
+    },
 };
 
+pub mod debugger;
 pub mod error;
 pub mod frame;
+pub mod profiler;
 pub mod stack;
 
-#[derive(Default)]
 pub struct VirtualMachine {
     stack: Stack,
     storage: Storage,
     globals: SymbolMap<Value>,
     frames: Vec<CallFrame>,
+    /// Upvalues still pointing into the stack, ordered by slot.
+    open_upvalues: Vec<UnsafeRef<LoxUpvalue>>,
+    /// Interned `init`, looked up on every class call.
+    init_symbol: Spur,
     debug: bool,
+    debugger: Option<Box<Debugger>>,
+    profiler: Option<Box<Profiler>>,
+    /// Instructions each run may execute, if limited.
+    budget: Option<u64>,
+    /// What's left of the budget in the current run.
+    fuel: u64,
+    interrupt: InterruptHandle,
+    /// Call frames a run may stack up, counting the top-level one.
+    max_frames: usize,
+}
+
+/// Everything the VM keeps alive outside the heap, borrowed apart from the
+/// `Storage` so a collection can run while both are in use.
+pub struct VmRoots<'a> {
+    stack: &'a Stack,
+    globals: &'a SymbolMap<Value>,
+    frames: &'a [CallFrame],
+    open_upvalues: &'a [UnsafeRef<LoxUpvalue>],
+}
+
+impl Trace for VmRoots<'_> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.stack.iter().for_each(|v| tracer.mark_value(v));
+        self.globals.values().for_each(|v| tracer.mark_value(v));
+        self.frames.iter().for_each(|f| f.trace(tracer));
+        self.open_upvalues.iter().for_each(|u| tracer.mark(u));
+    }
+}
+
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        Self::with_gc(GcConfig::default())
+    }
 }
 
 impl VirtualMachine {
+    /// Default call depth limit, the same as clox's.
+    pub const MAX_FRAMES: usize = 64;
+
+    pub fn with_gc(gc: GcConfig) -> Self {
+        let mut storage = Storage::with_gc(gc);
+        let init_symbol = storage.intern("init");
+        let mut vm = Self {
+            stack: Stack::default(),
+            storage,
+            globals: SymbolMap::default(),
+            frames: Vec::new(),
+            open_upvalues: Vec::new(),
+            init_symbol,
+            debug: false,
+            debugger: None,
+            profiler: None,
+            budget: None,
+            fuel: 0,
+            interrupt: InterruptHandle::default(),
+            max_frames: Self::MAX_FRAMES,
+        };
+        vm.define_natives();
+        vm
+    }
+
     pub fn debug() -> Self {
         Self {
             debug: true,
@@ -41,10 +129,112 @@ impl VirtualMachine {
         }
     }
 
+    /// Pause execution under `debugger` from the next run on.
+    pub fn with_debugger(self, debugger: Debugger) -> Self {
+        Self {
+            debugger: Some(Box::new(debugger)),
+            ..self
+        }
+    }
+
+    /// Fail allocations that would take the heap past `bytes`, once a
+    /// collection can't make room for them.
+    pub fn with_heap_limit(mut self, bytes: usize) -> Self {
+        self.storage.set_limit(Some(bytes));
+        self
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.storage.stats()
+    }
+
+    /// Stop each run after it executes `instructions` instructions.
+    pub fn with_budget(self, instructions: u64) -> Self {
+        Self {
+            budget: Some(instructions),
+            ..self
+        }
+    }
+
+    /// Fail calls with "Stack overflow." once `frames` call frames are
+    /// active, counting the top-level one.
+    pub fn with_max_call_depth(self, frames: usize) -> Self {
+        Self {
+            max_frames: frames,
+            ..self
+        }
+    }
+
+    /// A handle to stop this VM's runs from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Profile every run from the next one on, until [`take_profile`].
+    ///
+    /// [`take_profile`]: VirtualMachine::take_profile
+    pub fn with_profiler(self, profiler: Profiler) -> Self {
+        Self {
+            profiler: Some(Box::new(profiler)),
+            ..self
+        }
+    }
+
+    /// Stop profiling and sum up what the profiler saw.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let profiler = self.profiler.take()?;
+        Some(profiler.finish(&self.storage))
+    }
+
     pub fn storage(&mut self) -> &mut Storage {
         &mut self.storage
     }
 
+    /// Split the heap from the roots that keep its objects alive, e.g. so the
+    /// compiler can allocate into the heap and still collect safely.
+    pub fn heap_and_roots(&mut self) -> (&mut Storage, VmRoots<'_>) {
+        let roots = VmRoots {
+            stack: &self.stack,
+            globals: &self.globals,
+            frames: &self.frames,
+            open_upvalues: &self.open_upvalues,
+        };
+        (&mut self.storage, roots)
+    }
+
+    /// Add `obj` to the heap, collecting first if the heap is due for it or
+    /// `obj` wouldn't fit under the limit otherwise. Everything `obj`
+    /// references must already be reachable from the roots.
+    fn alloc<T: ObjectType + ?Sized>(
+        &mut self,
+        obj: Box<T>,
+    ) -> Result<UnsafeRef<Object>, RuntimeError> {
+        self.reserve(obj.footprint())?;
+        Ok(self.storage.add_obj(obj))
+    }
+
+    /// Account for a live object having grown from `before` bytes, then
+    /// collect or fail as [`alloc`](Self::alloc) would.
+    fn grown(&mut self, kind: ObjKind, before: usize, after: usize) -> Result<(), RuntimeError> {
+        self.storage.resize(kind, before, after);
+        self.reserve(0)
+    }
+
+    /// Collection safepoint: make room for `bytes` more under the heap limit.
+    fn reserve(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        if self.storage.should_collect() || !self.storage.fits(bytes) {
+            let (storage, roots) = self.heap_and_roots();
+            storage.collect(&[&roots]);
+        }
+        match self.storage.stats().limit {
+            Some(limit) if !self.storage.fits(bytes) => {
+                let message = format!("Out of memory: heap limit of {limit} bytes exceeded.");
+                Err(self.runtime_err(message).with_code(Code::OutOfMemory))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn frame(&self) -> &CallFrame {
         self.frames.last().expect("always has top level call frame")
     }
@@ -65,11 +255,7 @@ impl VirtualMachine {
 
     fn make_span(&self) -> Span {
         let pos = self.frame().pc.position().saturating_sub(1);
-        self.frame()
-            .chunk()
-            .get_line(pos)
-            .map(LineInfo::to_span)
-            .unwrap_or_default()
+        self.frame().chunk().get_span(pos).unwrap_or_default()
     }
 
     fn local(&self, slot: LocalSlot) -> &Value {
@@ -82,6 +268,14 @@ impl VirtualMachine {
         self.stack.get_mut(start + slot.0 as usize)
     }
 
+    fn upvalue(&self, slot: UpvalueSlot) -> &UnsafeRef<LoxUpvalue> {
+        let closure = self
+            .frame()
+            .callee()
+            .expect("top-level code has no upvalues");
+        &closure.upvalues[slot.0 as usize]
+    }
+
     fn runtime_err(&self, message: impl Display) -> RuntimeError {
         RuntimeError::custom(self.make_span(), message)
     }
@@ -100,42 +294,109 @@ impl VirtualMachine {
         out: &mut dyn Write,
     ) -> Result<(), VirtualMachineError> {
         // top level call frame
-        self.frames.push(CallFrame::top_level(chunk, 0));
+        self.frames
+            .push(CallFrame::top_level(chunk, self.stack.len()));
+        self.fuel = self.budget.unwrap_or_default();
+        // A failed run leaves frames and temporaries behind; drop them so the
+        // VM (and its globals) stays usable, e.g. across REPL lines.
+        self.execute(out, 0).map_err(|err| {
+            let err = match err {
+                VirtualMachineError::Runtime(err) => err.with_trace(|| self.backtrace()).into(),
+                err => err,
+            };
+            self.reset();
+            err
+        })
+    }
+
+    /// The call stack, innermost frame first. Every frame is past the
+    /// instruction it's at: the innermost one failed, the others called.
+    fn backtrace(&self) -> Vec<TraceFrame> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let function = frame
+                    .callee()
+                    .map(|closure| self.storage.resolve(closure.function.name).into());
+                let offset = frame.pc.position().saturating_sub(1);
+                let line = frame.chunk().get_line(offset).map_or(0, |info| info.line);
+                TraceFrame { function, line }
+            })
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+    }
 
-        while let Some(op) = self.pc().decode_op::<OpCode>()? {
+    /// Run until the frame above the bottom `base` frames returns, leaving
+    /// its result on the stack unless it is the top-level one.
+    fn execute(&mut self, out: &mut dyn Write, base: usize) -> Result<(), VirtualMachineError> {
+        loop {
+            self.spend_fuel()?;
+            if self.debugger.is_some() {
+                self.debug_hook()?;
+            }
+            let offset = self.frame().pc.position();
+            let Some(op) = self.pc().decode_op::<OpCode>()? else {
+                break;
+            };
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(&self.frames, offset, op);
+            }
             self.trace(op);
 
             match op {
                 OpCode::NoOp => {}
                 OpCode::Ret => {
-                    return Ok(());
-                }
-                OpCode::Constant(addr) => {
-                    let constant = self.chunk().constant(addr);
-                    self.stack.push(constant.clone());
+                    let frame = self.frames.pop().expect("always has top level call frame");
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    let result = self.stack.pop();
+                    self.close_upvalues(frame.stack_start);
+                    self.stack.truncate(frame.stack_start);
+                    self.stack.push(result);
+                    if self.frames.len() == base {
+                        return Ok(());
+                    }
                 }
+                OpCode::Constant(addr) => self.push_constant(addr.into()),
+                OpCode::ConstantLong(addr) => self.push_constant(addr),
                 OpCode::Neg => {
                     let v = self.stack.top_mut();
                     match -v.clone() {
                         Ok(res) => *v = res,
-                        Err(_) => return Err(self.runtime_err("invalid operand").into()),
+                        Err(_) => {
+                            return Err(self
+                                .runtime_err("invalid operand")
+                                .with_code(Code::InvalidOperand)
+                                .into());
+                        }
                     }
                 }
                 OpCode::Add if self.stack.peek(0).is_str() && self.stack.peek(1).is_str() => {
-                    self.concatenate_str()
+                    self.concatenate_str()?
                 }
-                OpCode::Add => self
-                    .binary_op(Value::add)
-                    .map_err(|_| self.runtime_err("invalid operand"))?,
-                OpCode::Sub => self
-                    .binary_op(Value::sub)
-                    .map_err(|_| self.runtime_err("invalid operand"))?,
-                OpCode::Mul => self
-                    .binary_op(Value::mul)
-                    .map_err(|_| self.runtime_err("invalid operand"))?,
-                OpCode::Div => self
-                    .binary_op(Value::div)
-                    .map_err(|_| self.runtime_err("invalid operand"))?,
+                OpCode::Add => self.binary_op(Value::add).map_err(|_| {
+                    self.runtime_err("invalid operand")
+                        .with_code(Code::InvalidOperand)
+                })?,
+                OpCode::Sub => self.binary_op(Value::sub).map_err(|_| {
+                    self.runtime_err("invalid operand")
+                        .with_code(Code::InvalidOperand)
+                })?,
+                OpCode::Mul => self.binary_op(Value::mul).map_err(|_| {
+                    self.runtime_err("invalid operand")
+                        .with_code(Code::InvalidOperand)
+                })?,
+                OpCode::Div => self.binary_op(Value::div).map_err(|_| {
+                    self.runtime_err("invalid operand")
+                        .with_code(Code::InvalidOperand)
+                })?,
                 OpCode::True => {
                     self.stack.push(Value::boolean(true));
                 }
@@ -150,43 +411,26 @@ impl VirtualMachine {
                     *v = Value::Boolean(v.is_falsey());
                 }
                 OpCode::Equal => self.equal(),
-                OpCode::Greater => self
-                    .binary_op(Value::greater)
-                    .map_err(|_| self.runtime_err("invalid operand"))?,
-                OpCode::Less => self
-                    .binary_op(Value::less)
-                    .map_err(|_| self.runtime_err("invalid operand"))?,
+                OpCode::Greater => self.binary_op(Value::greater).map_err(|_| {
+                    self.runtime_err("invalid operand")
+                        .with_code(Code::InvalidOperand)
+                })?,
+                OpCode::Less => self.binary_op(Value::less).map_err(|_| {
+                    self.runtime_err("invalid operand")
+                        .with_code(Code::InvalidOperand)
+                })?,
                 OpCode::Print => {
                     let v = self.stack.pop();
                     self.print_value(&v, out);
                 }
                 OpCode::Pop => _ = self.stack.pop(),
                 OpCode::PopN(n) => self.stack.pop_n(n),
-                OpCode::DefGlobal(addr) => {
-                    self.with_variable(addr, |vm, key, value| {
-                        vm.globals.insert(key, value);
-                    });
-                    self.stack.pop();
-                }
-                OpCode::GetGlobal(addr) => {
-                    let key = self.variable_name(self.chunk(), addr);
-                    match self.globals.get(&key) {
-                        Some(value) => {
-                            let value = value.clone();
-                            self.stack.push(value);
-                        }
-                        None => return Err(RuntimeError::undefined(self.make_span()).into()),
-                    }
-                }
-                OpCode::SetGlobal(addr) => {
-                    self.with_variable(addr, |vm, key, value| {
-                        #[allow(clippy::unit_arg)]
-                        match vm.globals.entry(key) {
-                            Entry::Occupied(mut e) => Ok(*e.get_mut() = value),
-                            Entry::Vacant(_) => Err(RuntimeError::undefined(vm.make_span())),
-                        }
-                    })?;
-                }
+                OpCode::DefGlobal(addr) => self.define_global(addr.into()),
+                OpCode::DefGlobalLong(addr) => self.define_global(addr),
+                OpCode::GetGlobal(addr) => self.get_global(addr.into())?,
+                OpCode::GetGlobalLong(addr) => self.get_global(addr)?,
+                OpCode::SetGlobal(addr) => self.set_global(addr.into())?,
+                OpCode::SetGlobalLong(addr) => self.set_global(addr)?,
                 OpCode::GetLocal(slot) => {
                     let v = self.local(slot).clone();
                     self.stack.push(v);
@@ -197,27 +441,389 @@ impl VirtualMachine {
                     let v = self.stack.top().clone();
                     *self.local_mut(slot) = v;
                 }
-                OpCode::JmpIfFalse(offset) => {
-                    let condition = self.stack.top().is_falsey();
-                    if condition {
-                        self.pc()
-                            .relative_jump(offset as i64)
-                            .with_context(|| "could not jump to offset {offset} {e}")?;
+                OpCode::JmpIfFalse(offset) => self.jump_if_false(offset.into())?,
+                OpCode::JmpIfFalseLong(offset) => self.jump_if_false(offset)?,
+                OpCode::Jmp(offset) => self.jump(offset as i64)?,
+                OpCode::JmpLong(offset) => self.jump(offset as i64)?,
+                OpCode::Loop(offset) => self.jump(-(offset as i64))?,
+                OpCode::LoopLong(offset) => self.jump(-(offset as i64))?,
+                OpCode::Call(argc) => self.call_value(argc)?,
+                OpCode::Closure(addr) => self.closure(addr.into())?,
+                OpCode::ClosureLong(addr) => self.closure(addr)?,
+                OpCode::GetUpvalue(slot) => {
+                    let v = match self.upvalue(slot).state() {
+                        UpvalueState::Open(index) => self.stack.get(index).clone(),
+                        UpvalueState::Closed(v) => v,
+                    };
+                    self.stack.push(v);
+                }
+                OpCode::SetUpvalue(slot) => {
+                    let v = self.stack.top().clone();
+                    let upvalue = self.upvalue(slot).clone();
+                    match upvalue.open_slot() {
+                        Some(index) => *self.stack.get_mut(index) = v,
+                        None => upvalue.close(v),
                     }
                 }
-                OpCode::Jmp(offset) => self
-                    .pc()
-                    .relative_jump(offset as i64)
-                    .with_context(|| format!("could not jump to offset {offset}"))?,
-                OpCode::Loop(offset) => self
-                    .pc()
-                    .relative_jump(-(offset as i64))
-                    .with_context(|| format!("could not loop to offset {}", -(offset as i64)))?,
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::Class(addr) => self.class(addr.into())?,
+                OpCode::ClassLong(addr) => self.class(addr)?,
+                OpCode::Method(addr) => self.method(addr.into())?,
+                OpCode::MethodLong(addr) => self.method(addr)?,
+                OpCode::Inherit => self.inherit()?,
+                OpCode::GetSuper(addr) => self.get_super(addr.into())?,
+                OpCode::GetSuperLong(addr) => self.get_super(addr)?,
+                OpCode::GetProperty(addr) => self.get_property(addr.into())?,
+                OpCode::GetPropertyLong(addr) => self.get_property(addr)?,
+                OpCode::SetProperty(addr) => self.set_property(addr.into())?,
+                OpCode::SetPropertyLong(addr) => self.set_property(addr)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Account for the next instruction, unless the budget is spent or the
+    /// host asked to stop.
+    fn spend_fuel(&mut self) -> Result<(), HaltError> {
+        if self.interrupt.take() {
+            return Err(HaltError::Interrupted);
+        }
+        if let Some(budget) = self.budget {
+            if self.fuel == 0 {
+                return Err(HaltError::OutOfBudget(budget));
+            }
+            self.fuel -= 1;
+        }
+        Ok(())
+    }
+
+    fn debug_hook(&mut self) -> Result<(), VirtualMachineError> {
+        // Taken out while it runs, so whatever it evaluates isn't debugged in turn.
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        let result = debugger.before_instruction(self);
+        self.debugger = Some(debugger);
+        result
+    }
+
+    fn jump(&mut self, offset: i64) -> anyhow::Result<()> {
+        self.pc()
+            .relative_jump(offset)
+            .with_context(|| format!("could not jump to offset {offset}"))
+    }
+
+    fn jump_if_false(&mut self, offset: LongOffset) -> anyhow::Result<()> {
+        if self.stack.top().is_falsey() {
+            self.jump(offset as i64)?;
+        }
+        Ok(())
+    }
+
+    fn push_constant(&mut self, addr: LongAddr) {
+        let constant = self.chunk().constant(addr).clone();
+        self.stack.push(constant);
+    }
+
+    fn define_global(&mut self, addr: LongAddr) {
+        self.with_variable(addr, |vm, key, value| {
+            vm.globals.insert(key, value);
+        });
+        self.stack.pop();
+    }
+
+    fn get_global(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let key = self.variable_name(self.chunk(), addr);
+        match self.globals.get(&key) {
+            Some(value) => {
+                let value = value.clone();
+                self.stack.push(value);
+                Ok(())
+            }
+            None => Err(RuntimeError::undefined(self.make_span())),
+        }
+    }
+
+    fn set_global(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        self.with_variable(addr, |vm, key, value| {
+            #[allow(clippy::unit_arg)]
+            match vm.globals.entry(key) {
+                Entry::Occupied(mut e) => Ok(*e.get_mut() = value),
+                Entry::Vacant(_) => Err(RuntimeError::undefined(vm.make_span())),
+            }
+        })
+    }
+
+    fn closure(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let Value::Object(obj) = self.chunk().constant(addr) else {
+            panic!("closure constant is not a function");
+        };
+        // SAFETY: the compiler only emits `Closure` for function constants.
+        let func = unsafe { obj.clone().downcast::<LoxFunction>() };
+        let upvalues = func
+            .captures
+            .iter()
+            .map(|capture| match *capture {
+                Capture::Local(slot) => {
+                    self.capture_upvalue(self.frame().stack_start + slot.0 as usize)
+                }
+                Capture::Upvalue(slot) => Ok(self.upvalue(slot).clone()),
+            })
+            .collect::<Result<_, _>>()?;
+        let closure = self.alloc(LoxClosure::boxed(func, upvalues))?;
+        self.stack.push(Value::Object(closure));
+        Ok(())
+    }
+
+    fn class(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let name = self.variable_name(self.chunk(), addr);
+        let class = self.alloc(LoxClass::boxed(name))?;
+        self.stack.push(Value::Object(class));
+        Ok(())
+    }
+
+    fn method(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let name = self.variable_name(self.chunk(), addr);
+        // The compiler emits `Method` right after the method's `Closure`, with
+        // its `Class` below it; a loaded chunk only promises the stack depth.
+        let method = match self.stack.peek(0) {
+            // SAFETY: matched kind witnesses the dynamic type.
+            Value::Object(obj) if obj.kind() == ObjKind::Closure => {
+                Some(unsafe { obj.clone().downcast::<LoxClosure>() })
+            }
+            _ => None,
+        };
+        let (Some(class), Some(method)) = (self.class_at(1), method) else {
+            return Err(self.runtime_err("Only closures can be added to classes as methods."));
+        };
+        let before = class.footprint();
+        class.add_method(name, method);
+        self.grown(ObjKind::Class, before, class.footprint())?;
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn inherit(&mut self) -> Result<(), RuntimeError> {
+        let Some(superclass) = self.class_at(1) else {
+            return Err(self
+                .runtime_err("Superclass must be a class.")
+                .with_code(Code::InvalidSuperclass));
+        };
+        // The resolver rules out inheriting from itself, but not in a
+        // loaded chunk.
+        let Some(class) = self
+            .class_at(0)
+            .filter(|class| !ptr::eq(&**class, &*superclass))
+        else {
+            return Err(self
+                .runtime_err("Only a new class can inherit.")
+                .with_code(Code::InvalidSuperclass));
+        };
+        let before = class.footprint();
+        class.inherit(&superclass);
+        self.grown(ObjKind::Class, before, class.footprint())?;
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn get_super(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let name = self.variable_name(self.chunk(), addr);
+        // The `super` local only ever holds a class that passed `Inherit`,
+        // except in a loaded chunk.
+        let Some(superclass) = self.class_at(0) else {
+            return Err(self
+                .runtime_err("Superclass must be a class.")
+                .with_code(Code::InvalidSuperclass));
+        };
+        // Receiver sits right below the superclass.
+        let bound = self.bind_method(superclass, name, 1)?;
+        self.stack.pop();
+        *self.stack.top_mut() = bound;
+        Ok(())
+    }
+
+    fn get_property(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let name = self.variable_name(self.chunk(), addr);
+        let Some(instance) = self.instance_at(0) else {
+            return Err(self
+                .runtime_err("Only instances have properties.")
+                .with_code(Code::NotAnInstance));
+        };
+        match instance.field(name) {
+            Some(value) => *self.stack.top_mut() = value,
+            None => {
+                let bound = self.bind_method(instance.class.clone(), name, 0)?;
+                *self.stack.top_mut() = bound;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_property(&mut self, addr: LongAddr) -> Result<(), RuntimeError> {
+        let name = self.variable_name(self.chunk(), addr);
+        let Some(instance) = self.instance_at(1) else {
+            return Err(self
+                .runtime_err("Only instances have fields.")
+                .with_code(Code::NotAnInstance));
+        };
+        let value = self.stack.pop();
+        let before = instance.footprint();
+        instance.set_field(name, value.clone());
+        // The instance is still on the stack, and now holds `value`.
+        self.grown(ObjKind::Instance, before, instance.footprint())?;
+        *self.stack.top_mut() = value;
+        Ok(())
+    }
+
+    fn call_value(&mut self, argc: u8) -> Result<(), RuntimeError> {
+        let Value::Object(callee) = self.stack.peek(argc as usize) else {
+            return Err(RuntimeError::not_callable(self.make_span()));
+        };
+        match callee.kind() {
+            // SAFETY: matched kind witnesses the dynamic type.
+            ObjKind::Closure => self.call(unsafe { callee.clone().downcast() }, argc),
+            ObjKind::Native => self.call_native(unsafe { callee.clone().downcast() }, argc),
+            ObjKind::Class => self.call_class(unsafe { callee.clone().downcast() }, argc),
+            ObjKind::BoundMethod => {
+                let bound = unsafe { callee.clone().downcast::<LoxBoundMethod>() };
+                // The receiver takes the callee's slot so the method sees it as `this`.
+                *self.stack.peek_mut(argc as usize) = bound.receiver.clone();
+                self.call(bound.method.clone(), argc)
+            }
+            _ => Err(RuntimeError::not_callable(self.make_span())),
+        }
+    }
+
+    fn call(&mut self, closure: UnsafeRef<LoxClosure>, argc: u8) -> Result<(), RuntimeError> {
+        let arity = closure.function.arity;
+        if arity != argc {
+            return Err(RuntimeError::arity(self.make_span(), arity, argc as usize));
+        }
+        if self.frames.len() >= self.max_frames {
+            return Err(RuntimeError::stack_overflow(self.make_span())
+                .with_note(format_args!("calls nest at most {} deep", self.max_frames)));
+        }
+        // Slot 0 of the new frame is the callee itself, followed by the arguments.
+        let stack_start = self.stack.len() - argc as usize - 1;
+        self.frames.push(CallFrame::closure(closure, stack_start));
+        Ok(())
+    }
+
+    fn call_class(&mut self, class: UnsafeRef<LoxClass>, argc: u8) -> Result<(), RuntimeError> {
+        let instance = self.alloc(LoxInstance::boxed(class.clone()))?;
+        *self.stack.peek_mut(argc as usize) = Value::Object(instance);
+        match class.method(self.init_symbol) {
+            Some(init) => self.call(init, argc),
+            None if argc != 0 => Err(RuntimeError::arity(self.make_span(), 0, argc as usize)),
+            None => Ok(()),
+        }
+    }
+
+    /// Bind `class`'s method `name` to the receiver `distance` slots down the
+    /// stack. The caller keeps the receiver (and `class`) on the stack until
+    /// the bound method replaces them.
+    fn bind_method(
+        &mut self,
+        class: UnsafeRef<LoxClass>,
+        name: Spur,
+        distance: usize,
+    ) -> Result<Value, RuntimeError> {
+        let Some(method) = class.method(name) else {
+            let message = format!("Undefined property '{}'.", self.storage.resolve(name));
+            return Err(self.runtime_err(message).with_code(Code::UndefinedProperty));
+        };
+        let receiver = self.stack.peek(distance).clone();
+        let bound = self.alloc(LoxBoundMethod::boxed(receiver, method))?;
+        Ok(Value::Object(bound))
+    }
+
+    fn class_at(&self, distance: usize) -> Option<UnsafeRef<LoxClass>> {
+        match self.stack.peek(distance) {
+            // SAFETY: matched kind witnesses the dynamic type.
+            Value::Object(obj) if obj.kind() == ObjKind::Class => {
+                Some(unsafe { obj.clone().downcast() })
             }
+            _ => None,
+        }
+    }
+
+    fn instance_at(&self, distance: usize) -> Option<UnsafeRef<LoxInstance>> {
+        match self.stack.peek(distance) {
+            // SAFETY: matched kind witnesses the dynamic type.
+            Value::Object(obj) if obj.kind() == ObjKind::Instance => {
+                Some(unsafe { obj.clone().downcast() })
+            }
+            _ => None,
+        }
+    }
+
+    /// Reuse the open upvalue for `index` if some closure already captured
+    /// it, so every closure over the same variable shares one upvalue.
+    fn capture_upvalue(&mut self, index: usize) -> Result<UnsafeRef<LoxUpvalue>, RuntimeError> {
+        let pos = self
+            .open_upvalues
+            .partition_point(|u| u.open_slot().is_some_and(|slot| slot < index));
+        if let Some(existing) = self.open_upvalues.get(pos)
+            && existing.open_slot() == Some(index)
+        {
+            return Ok(existing.clone());
+        }
+        let obj = self.alloc(LoxUpvalue::boxed(index))?;
+        // SAFETY: just allocated as a `LoxUpvalue`.
+        let upvalue = unsafe { obj.downcast::<LoxUpvalue>() };
+        self.open_upvalues.insert(pos, upvalue.clone());
+        Ok(upvalue)
+    }
+
+    /// Move every open upvalue at or above stack index `from` off the stack.
+    fn close_upvalues(&mut self, from: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let Some(index) = upvalue.open_slot().filter(|&i| i >= from) else {
+                break;
+            };
+            upvalue.close(self.stack.get(index).clone());
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn call_native(&mut self, native: UnsafeRef<LoxNative>, argc: u8) -> Result<(), RuntimeError> {
+        if native.arity != argc {
+            return Err(RuntimeError::arity(
+                self.make_span(),
+                native.arity,
+                argc as usize,
+            ));
         }
+        let result = (native.func)(self.stack.top_n(argc as usize));
+        // Arguments and the callee are both consumed by the call.
+        let stack_start = self.stack.len() - argc as usize - 1;
+        self.stack.truncate(stack_start);
+        self.stack.push(result);
         Ok(())
     }
 
+    fn define_natives(&mut self) {
+        self.define_native("clock", 0, |_args| {
+            Value::number(
+                std::time::UNIX_EPOCH
+                    .elapsed()
+                    .expect("couldn't get system time")
+                    .as_millis() as f64,
+            )
+        });
+    }
+
+    fn define_native(&mut self, name: &str, arity: u8, func: NativeFn) {
+        let key = self.storage.intern(name);
+        let obj = self
+            .alloc(LoxNative::boxed(key, arity, func))
+            .expect("natives are defined before any heap limit applies");
+        self.globals.insert(key, Value::object(obj));
+    }
+
     fn binary_op<F>(&mut self, op: F) -> Result<(), ValueError>
     where
         F: Fn(Value, Value) -> Result<Value, ValueError>,
@@ -242,7 +848,7 @@ impl VirtualMachine {
         self.stack.push(Value::boolean(res));
     }
 
-    fn concatenate_str(&mut self) {
+    fn concatenate_str(&mut self) -> Result<(), RuntimeError> {
         // Build the joined string while the operands stay on the stack as GC roots.
         let s = {
             let a = self.stack.peek(1).as_str(&self.storage);
@@ -252,15 +858,16 @@ impl VirtualMachine {
             s.push_str(b);
             s
         };
-        let obj = self.storage.add_obj(LoxString::boxed(&s));
+        let obj = self.alloc(LoxString::boxed(&s))?;
         self.stack.pop();
         self.stack.pop();
         self.stack.push(Value::Object(obj));
+        Ok(())
     }
 
     fn with_variable<T>(
         &mut self,
-        addr: Addr,
+        addr: LongAddr,
         f: impl FnOnce(&mut VirtualMachine, Spur, Value) -> T,
     ) -> T {
         // Value stays on the stack across `f` so it remains a GC root if a
@@ -270,7 +877,7 @@ impl VirtualMachine {
         f(self, key, value)
     }
 
-    fn variable_name(&self, chunk: &Chunk, addr: Addr) -> Spur {
+    fn variable_name(&self, chunk: &Chunk, addr: LongAddr) -> Spur {
         let Value::Symbol(key) = chunk.constant(addr) else {
             panic!("could not get variable name: constant slot is not a Symbol")
         };
@@ -293,3 +900,322 @@ impl VirtualMachine {
         println!(" ]");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stress_gc_keeps_live_objects() {
+        let source = r#"
+            class Counter {
+                init() { this.n = 0; }
+                inc() { this.n = this.n + 1; return this.n; }
+            }
+            fun make() {
+                var c = Counter();
+                fun next() { return "n" + "=" + "x"; }
+                return c;
+            }
+            var c = make();
+            var s = "";
+            for (var i = 0; i < 5; i = i + 1) { s = s + "ab"; c.inc(); }
+            print s;
+            print c.n;
+        "#;
+        let mut vm = VirtualMachine::with_gc(GcConfig::stress());
+        let mut out = Vec::new();
+        crate::run_with(source.to_owned(), &mut vm, &mut out, &mut io::sink()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "ababababab\n5\n");
+    }
+
+    #[test]
+    fn long_jumps_cross_large_bodies() {
+        // Each `nil;` is two bytes, so both bodies overflow a 16-bit offset.
+        let padding = "nil;".repeat(40_000);
+        let source = format!(
+            r#"
+            var i = 0;
+            while (i < 3) {{
+                i = i + 1;
+                if (i == 2) continue;
+                {padding}
+            }}
+            if (false) {{ {padding} }} else {{ print i; }}
+            print false or "or";
+            "#
+        );
+        let mut vm = VirtualMachine::default();
+        let mut out = Vec::new();
+        crate::run_with(source, &mut vm, &mut out, &mut io::sink()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3\nor\n");
+    }
+
+    #[test]
+    fn budget_stops_runaway_loops() {
+        let mut vm = VirtualMachine::default().with_budget(1_000);
+        let mut err = Vec::new();
+        let result = crate::run_with(
+            "while (true) {}".to_owned(),
+            &mut vm,
+            &mut io::sink(),
+            &mut err,
+        );
+        assert!(matches!(
+            result,
+            Err(report::Error::Halt(HaltError::OutOfBudget(1_000)))
+        ));
+        assert!(
+            String::from_utf8(err)
+                .unwrap()
+                .contains("budget of 1000 steps")
+        );
+
+        // Each run gets the whole budget again.
+        let mut out = Vec::new();
+        crate::run_with(
+            "var a = 1; print a + 1;".to_owned(),
+            &mut vm,
+            &mut out,
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn interrupts_from_another_thread() {
+        let mut vm = VirtualMachine::default();
+        let interrupt = vm.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            interrupt.interrupt();
+        });
+        let source = "var i = 0; while (true) { i = i + 1; }".to_owned();
+        let result = crate::run_with(source, &mut vm, &mut io::sink(), &mut io::sink());
+        interrupter.join().unwrap();
+        assert!(matches!(
+            result,
+            Err(report::Error::Halt(HaltError::Interrupted))
+        ));
+
+        let mut out = Vec::new();
+        crate::run_with(
+            "print i > 0;".to_owned(),
+            &mut vm,
+            &mut out,
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "true\n");
+    }
+
+    #[test]
+    fn loaded_chunks_fail_on_operands_of_the_wrong_kind() {
+        let listings = [
+            // A closure where `OP_METHOD` expects the class.
+            r#"
+            == <script> ==
+            0000    1 OP_CLOSURE       <fn f/0>[000]
+            0002    | OP_CLOSURE       <fn f/0>[000]
+            0004    | OP_METHOD        "m" [001]
+            0006    | OP_POP
+            0007    | OP_RETURN
+
+            == <fn f/0> ==
+            0000    1 OP_NIL
+            0001    | OP_RETURN
+            "#,
+            // A class inheriting from itself.
+            r#"
+            == <script> ==
+            0000    1 OP_CLASS         "A" [000]
+            0002    | OP_DEFINE_GLOBAL "A" [000]
+            0004    | OP_GET_GLOBAL    "A" [000]
+            0006    | OP_GET_GLOBAL    "A" [000]
+            0008    | OP_INHERIT
+            0009    | OP_POP
+            0010    | OP_RETURN
+            "#,
+            r#"
+            == <script> ==
+            0000    1 OP_NIL
+            0001    | OP_NIL
+            0002    | OP_GET_SUPER     "m" [000]
+            0004    | OP_POP
+            0005    | OP_RETURN
+            "#,
+        ];
+        for listing in listings {
+            let mut assembled = Storage::new();
+            let chunk = Chunk::assemble(&mut assembled, listing).unwrap();
+            let mut bytes = Vec::new();
+            chunk.serialize(&assembled, &mut bytes).unwrap();
+
+            let mut vm = VirtualMachine::default();
+            let loaded = Chunk::from_bytes(vm.storage(), &bytes).unwrap();
+            assert!(
+                matches!(vm.run(loaded), Err(VirtualMachineError::Runtime(_))),
+                "{listing}"
+            );
+        }
+    }
+
+    #[test]
+    fn deep_recursion_overflows_the_stack() {
+        let mut vm = VirtualMachine::default().with_max_call_depth(8);
+        let run = |vm: &mut VirtualMachine, source: &str| {
+            crate::run_with(source.to_owned(), vm, &mut io::sink(), &mut io::sink())
+        };
+        run(&mut vm, "fun f(n) { if (n > 0) f(n - 1); }").unwrap();
+        // Seven nested calls fill the eight frames with the top-level one.
+        run(&mut vm, "f(6);").unwrap();
+        let result = run(&mut vm, "f(7);");
+        assert!(
+            matches!(result, Err(report::Error::Runtime(err)) if &*err.message == "Stack overflow.")
+        );
+        // Unwinding gave the frames back.
+        run(&mut vm, "f(6);").unwrap();
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_backtrace() {
+        let source = "\
+fun inner() { return nil + 1; }
+fun outer() {
+  inner();
+}
+outer();";
+        let mut err = Vec::new();
+        let result = crate::run_with(
+            source.to_owned(),
+            &mut VirtualMachine::default(),
+            &mut io::sink(),
+            &mut err,
+        );
+        let Err(report::Error::Runtime(runtime)) = result else {
+            panic!("expected a runtime error");
+        };
+        let trace: Vec<_> = runtime.trace.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            trace,
+            [
+                "[line 1] in inner()",
+                "[line 3] in outer()",
+                "[line 5] in script"
+            ]
+        );
+        assert!(
+            String::from_utf8(err)
+                .unwrap()
+                .ends_with("[line 3] in outer()\n[line 5] in script\n")
+        );
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_failing_token() {
+        let failing = |source: &str| {
+            let result = crate::run_with(
+                source.to_owned(),
+                &mut VirtualMachine::default(),
+                &mut io::sink(),
+                &mut io::sink(),
+            );
+            let Err(report::Error::Runtime(err)) = result else {
+                panic!("expected a runtime error");
+            };
+            (err.span.line_start, err.span.slice(source).to_owned())
+        };
+        assert_eq!(failing("var a = 1;\nprint a + nil;"), (2, "+".into()));
+        assert_eq!(failing("fun f() {}\nf(1);"), (2, "f".into()));
+        assert_eq!(failing("print -\"a\";"), (1, "-".into()));
+        assert_eq!(failing("var x;\nx.field = 1;"), (2, "field".into()));
+        assert_eq!(failing("print undefined;"), (1, "undefined".into()));
+    }
+
+    #[test]
+    fn runtime_errors_point_into_the_file_that_failed() {
+        let mut sources = report::SourceMap::new();
+        let lib = sources.add("lib.lox", "fun f() {\n  return -nil;\n}");
+        let main = sources.add("main.lox", "f();");
+        let mut vm = VirtualMachine::default();
+        let mut run = |file| {
+            let mut err = io::sink();
+            let mut reporter = report::Reporter::with_sources(&sources, file, &mut err);
+            let chunk = crate::compile(sources[file].text(), &mut vm, &mut reporter)?;
+            crate::execute(chunk, &mut vm, &mut reporter, &mut io::sink())
+        };
+        run(lib).unwrap();
+        let Err(report::Error::Runtime(err)) = run(main) else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(err.span.file, lib);
+        assert_eq!(sources.location(err.span).unwrap(), "lib.lox:2:10");
+    }
+
+    #[test]
+    fn heap_limit_fails_runaway_allocation() {
+        let mut vm = VirtualMachine::default().with_heap_limit(64 * 1024);
+        let mut err = Vec::new();
+        let source = "var s = \"x\"; while (true) { s = s + s; }".to_owned();
+        let result = crate::run_with(source, &mut vm, &mut io::sink(), &mut err);
+        assert!(matches!(result, Err(report::Error::Runtime(_))));
+        assert!(
+            String::from_utf8(err)
+                .unwrap()
+                .contains("Out of memory: heap limit of 65536 bytes exceeded.")
+        );
+        assert!(vm.heap_stats().peak_bytes <= 64 * 1024);
+
+        // Garbage from the failed run is still collectable.
+        let mut out = Vec::new();
+        crate::run_with(
+            "var t = \"a\" + \"b\"; print t;".to_owned(),
+            &mut vm,
+            &mut out,
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "ab\n");
+    }
+
+    #[test]
+    fn heap_stats_count_live_objects() {
+        let mut vm = VirtualMachine::default();
+        crate::run_with(
+            "class A {} var a = A(); var s = \"x\" + \"y\";".to_owned(),
+            &mut vm,
+            &mut io::sink(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let stats = vm.heap_stats();
+        assert_eq!(stats.live.kind(ObjKind::Class).objects, 1);
+        assert_eq!(stats.live.kind(ObjKind::Instance).objects, 1);
+        assert!(stats.live.kind(ObjKind::String).objects >= 1);
+        assert_eq!(stats.limit, None);
+        assert!(stats.to_string().contains("Instance"));
+    }
+
+    #[test]
+    fn heap_stats_count_what_objects_own() {
+        use crate::storage::stats::Usage;
+
+        let mut vm = VirtualMachine::with_gc(GcConfig::stress());
+        let run = |vm: &mut VirtualMachine, source: &str| {
+            crate::run_with(source.to_owned(), vm, &mut io::sink(), &mut io::sink()).unwrap()
+        };
+        let usage = |vm: &VirtualMachine, kind| vm.heap_stats().live.kind(kind);
+
+        run(&mut vm, "class A { m() {} } var a = A();");
+        assert!(usage(&vm, ObjKind::Class).bytes > mem::size_of::<LoxClass>());
+        let empty = usage(&vm, ObjKind::Instance).bytes;
+        run(&mut vm, "a.x = 1; a.y = 2; a.z = 3;");
+        assert!(usage(&vm, ObjKind::Instance).bytes > empty);
+
+        // Freeing them takes back everything they grew by.
+        run(&mut vm, "a = nil; A = nil; var s = \"x\" + \"y\";");
+        assert_eq!(usage(&vm, ObjKind::Instance), Usage::default());
+        assert_eq!(usage(&vm, ObjKind::Class), Usage::default());
+    }
+}
@@ -0,0 +1,507 @@
+//! Line breakpoints and stepping for the VM.
+//!
+//! A [`Debugger`] watches every instruction the VM is about to run and pauses
+//! at the first instruction of a line when a breakpoint or a pending step asks
+//! for it. What happens while paused is up to its [`DebugFrontend`], which gets
+//! a [`Session`] to inspect the paused program through.
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::io;
+
+use lasso::Spur;
+use lexer::Scanner;
+use report::Reporter;
+
+use crate::compiler::Compiler;
+use crate::enconding::LocalSlot;
+use crate::object::closure::LoxClosure;
+use crate::object::function::{Capture, LoxFunction};
+use crate::storage::WithStorage;
+use crate::value::Value;
+use crate::vm::{VirtualMachine, error::VirtualMachineError, frame::CallFrame};
+
+pub mod dap;
+pub mod prompt;
+
+/// Why execution paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// About to run the first line of the program.
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+impl Display for PauseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PauseReason::Entry => write!(f, "entry"),
+            PauseReason::Breakpoint => write!(f, "breakpoint"),
+            PauseReason::Step => write!(f, "step"),
+        }
+    }
+}
+
+/// How to carry on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next line, in whatever frame it is.
+    StepInto,
+    /// Pause at the next line of this frame, or on returning to its caller.
+    StepOver,
+    /// Pause as soon as this frame returns.
+    StepOut,
+    /// Abandon the program.
+    Stop,
+}
+
+/// Decides what to do whenever execution pauses.
+pub trait DebugFrontend {
+    fn paused(&mut self, session: &mut Session<'_>, reason: PauseReason) -> Resume;
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Entry,
+    /// Frame depth the step was requested at. Every step also ends when that
+    /// frame returns, even mid-line in the caller.
+    Into(usize),
+    Over(usize),
+    Out(usize),
+}
+
+pub struct Debugger {
+    frontend: Box<dyn DebugFrontend>,
+    breakpoints: BTreeSet<u32>,
+    step: Option<Step>,
+    /// Line and offset each frame last ran, outermost first, to tell when
+    /// execution reaches a new line.
+    positions: Vec<Option<(u32, u64)>>,
+}
+
+impl Debugger {
+    /// Pauses on entry, so the frontend gets to set breakpoints first.
+    pub fn new(frontend: impl DebugFrontend + 'static) -> Self {
+        Self {
+            frontend: Box::new(frontend),
+            breakpoints: BTreeSet::new(),
+            step: Some(Step::Entry),
+            positions: Vec::new(),
+        }
+    }
+
+    pub(super) fn before_instruction(
+        &mut self,
+        vm: &mut VirtualMachine,
+    ) -> Result<(), VirtualMachineError> {
+        let depth = vm.frames.len();
+        let offset = vm.frame().pc.position();
+        self.positions.resize(depth, None);
+        let Some(line) = vm.chunk().get_line(offset).map(|info| info.line) else {
+            return Ok(());
+        };
+        // A backward jump re-enters the line it lands on.
+        let new_line = self.positions[depth - 1]
+            .replace((line, offset))
+            .is_none_or(|(prev_line, prev_offset)| prev_line != line || offset < prev_offset);
+
+        let reason = match self.step {
+            Some(Step::Into(from) | Step::Over(from) | Step::Out(from)) if depth < from => {
+                PauseReason::Step
+            }
+            _ if !new_line => return Ok(()),
+            _ if self.breakpoints.contains(&line) => PauseReason::Breakpoint,
+            Some(Step::Entry) => PauseReason::Entry,
+            Some(Step::Into(_)) => PauseReason::Step,
+            Some(Step::Over(from)) if depth <= from => PauseReason::Step,
+            _ => return Ok(()),
+        };
+
+        let mut session = Session {
+            vm,
+            breakpoints: &mut self.breakpoints,
+        };
+        self.step = match self.frontend.paused(&mut session, reason) {
+            Resume::Continue => None,
+            Resume::StepInto => Some(Step::Into(depth)),
+            Resume::StepOver => Some(Step::Over(depth)),
+            Resume::StepOut => Some(Step::Out(depth)),
+            Resume::Stop => return Err(VirtualMachineError::Stopped),
+        };
+        Ok(())
+    }
+}
+
+/// One entry of the call stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub name: String,
+    pub line: Option<u32>,
+}
+
+/// A variable and its value, rendered the way `print` would.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+}
+
+/// The paused program. Frames are numbered from the innermost, `0`.
+pub struct Session<'a> {
+    vm: &'a mut VirtualMachine,
+    breakpoints: &'a mut BTreeSet<u32>,
+}
+
+impl Session<'_> {
+    pub fn breakpoints(&self) -> &BTreeSet<u32> {
+        self.breakpoints
+    }
+
+    /// Returns whether the breakpoint is new.
+    pub fn set_breakpoint(&mut self, line: u32) -> bool {
+        self.breakpoints.insert(line)
+    }
+
+    /// Returns whether there was a breakpoint to clear.
+    pub fn clear_breakpoint(&mut self, line: u32) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.vm.frames.len()
+    }
+
+    /// The call stack, innermost frame first.
+    pub fn frames(&self) -> Vec<FrameInfo> {
+        (0..self.frame_count())
+            .map(|frame| {
+                let (call, offset) = self.position(frame);
+                let name = match call.callee() {
+                    Some(closure) => self.vm.storage.resolve(closure.function.name).to_owned(),
+                    None => "<script>".to_owned(),
+                };
+                let line = call.chunk().get_line(offset).map(|info| info.line);
+                FrameInfo { name, line }
+            })
+            .collect()
+    }
+
+    /// The named locals in scope in `frame`, outermost first.
+    pub fn locals(&self, frame: usize) -> Vec<Variable> {
+        self.scope(frame)
+            .into_iter()
+            .map(|(name, slot)| {
+                let index = self.vm.frames[self.index(frame)].stack_start + slot.0 as usize;
+                Variable {
+                    name: self.vm.storage.resolve(name).to_owned(),
+                    value: self.render(self.vm.stack.get(index)),
+                }
+            })
+            .collect()
+    }
+
+    /// Every global, sorted by name.
+    pub fn globals(&self) -> Vec<Variable> {
+        let mut globals: Vec<_> = self
+            .vm
+            .globals
+            .iter()
+            .map(|(&name, value)| Variable {
+                name: self.vm.storage.resolve(name).to_owned(),
+                value: self.render(value),
+            })
+            .collect();
+        globals.sort_by(|a, b| a.name.cmp(&b.name));
+        globals
+    }
+
+    /// Evaluate an expression as if it appeared in `frame`. It sees the
+    /// frame's locals and may assign to them. Errors come back as the text
+    /// they would be reported with.
+    pub fn evaluate(&mut self, frame: usize, source: &str) -> Result<String, String> {
+        let scope = self.scope(frame);
+        let mut errors = Vec::new();
+        let compiled = {
+            let mut reporter = Reporter::new(source, &mut errors);
+            let (storage, roots) = self.vm.heap_and_roots();
+            Compiler::new(Scanner::new(source), &mut reporter, storage, &roots).compile_eval(&scope)
+        };
+        let Ok(func) = compiled else {
+            return Err(String::from_utf8_lossy(&errors).trim_end().to_owned());
+        };
+        let stack_start = self.vm.frames[self.index(frame)].stack_start;
+        let value = self
+            .vm
+            .run_nested(func, stack_start)
+            .map_err(|err| match err {
+                VirtualMachineError::Runtime(err) => err.message.into(),
+                err => err.to_string(),
+            })?;
+        Ok(self.render(&value))
+    }
+
+    fn index(&self, frame: usize) -> usize {
+        self.frame_count()
+            .checked_sub(frame + 1)
+            .expect("frame number out of range")
+    }
+
+    /// The frame and the offset of the instruction it is at. Callers sit
+    /// just past their `Call`, the innermost frame right before its next
+    /// instruction.
+    fn position(&self, frame: usize) -> (&CallFrame, u64) {
+        let call = &self.vm.frames[self.index(frame)];
+        let offset = call.pc.position();
+        match frame {
+            0 => (call, offset),
+            _ => (call, offset.saturating_sub(1)),
+        }
+    }
+
+    /// Named locals in scope in `frame` that already hold a value, by slot.
+    fn scope(&self, frame: usize) -> Vec<(Spur, LocalSlot)> {
+        let (call, offset) = self.position(frame);
+        let live = self.vm.stack.len() - call.stack_start;
+        let mut scope: Vec<_> = call
+            .chunk()
+            .locals_at(offset)
+            .filter(|info| (info.slot.0 as usize) < live)
+            .map(|info| (info.name, info.slot))
+            .collect();
+        scope.sort_by_key(|&(_, slot)| slot);
+        scope
+    }
+
+    fn render(&self, value: &Value) -> String {
+        WithStorage(value, &self.vm.storage).to_string()
+    }
+}
+
+impl VirtualMachine {
+    /// Call `func` as a closure over the locals of the frame starting at
+    /// `stack_start` and run it to completion. Whatever happens, the VM is
+    /// left as it was.
+    fn run_nested(
+        &mut self,
+        func: Box<LoxFunction>,
+        stack_start: usize,
+    ) -> Result<Value, VirtualMachineError> {
+        let (base, stack_len) = (self.frames.len(), self.stack.len());
+        // Not a safepoint: `func`'s constants are only reachable through it
+        // until it's on the stack.
+        let obj = self.storage.add_obj(func);
+        self.stack.push(Value::Object(obj.clone()));
+        // SAFETY: just allocated as a `LoxFunction`.
+        let func = unsafe { obj.downcast::<LoxFunction>() };
+        let closure = func
+            .captures
+            .iter()
+            .map(|capture| match *capture {
+                Capture::Local(slot) => self.capture_upvalue(stack_start + slot.0 as usize),
+                Capture::Upvalue(_) => {
+                    unreachable!("evaluated code only captures the frame's locals")
+                }
+            })
+            .collect::<Result<_, _>>()
+            .and_then(|upvalues| self.alloc(LoxClosure::boxed(func, upvalues)));
+
+        let result = closure
+            .and_then(|closure| {
+                *self.stack.top_mut() = Value::Object(closure.clone());
+                // SAFETY: just allocated as a `LoxClosure`.
+                let closure = unsafe { closure.downcast::<LoxClosure>() };
+                self.call(closure, 0)
+            })
+            .map_err(VirtualMachineError::from)
+            .and_then(|()| self.execute(&mut io::sink(), base));
+        match result {
+            Ok(()) => Ok(self.stack.pop()),
+            Err(err) => {
+                self.frames.truncate(base);
+                self.close_upvalues(stack_len);
+                self.stack.truncate(stack_len);
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    type Script = Box<dyn FnMut(&mut Session<'_>, PauseReason) -> Resume>;
+    type Log = Vec<(PauseReason, Vec<FrameInfo>)>;
+
+    /// Runs `script` at every pause and logs where it paused.
+    struct Scripted {
+        script: Script,
+        log: Rc<RefCell<Log>>,
+    }
+
+    impl DebugFrontend for Scripted {
+        fn paused(&mut self, session: &mut Session<'_>, reason: PauseReason) -> Resume {
+            self.log.borrow_mut().push((reason, session.frames()));
+            (self.script)(session, reason)
+        }
+    }
+
+    fn debug(
+        source: &str,
+        script: impl FnMut(&mut Session<'_>, PauseReason) -> Resume + 'static,
+    ) -> (Log, String) {
+        let log = Rc::default();
+        let frontend = Scripted {
+            script: Box::new(script),
+            log: Rc::clone(&log),
+        };
+        let mut vm = VirtualMachine::default().with_debugger(Debugger::new(frontend));
+        let mut out = Vec::new();
+        let _ = crate::run_with(source.to_owned(), &mut vm, &mut out, &mut io::sink());
+        let log = log.take();
+        (log, String::from_utf8(out).unwrap())
+    }
+
+    fn lines(log: &[(PauseReason, Vec<FrameInfo>)]) -> Vec<u32> {
+        log.iter()
+            .map(|(_, frames)| frames[0].line.unwrap())
+            .collect()
+    }
+
+    const PROGRAM: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+print add(x, 3);
+";
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let (log, out) = debug(PROGRAM, |session, reason| {
+            if reason == PauseReason::Entry {
+                session.set_breakpoint(3);
+                session.set_breakpoint(6);
+            }
+            Resume::Continue
+        });
+        assert_eq!(out, "3\n6\n");
+        assert_eq!(lines(&log), [1, 3, 6, 3]);
+        assert_eq!(log[0].0, PauseReason::Entry);
+        assert_eq!(log[1].0, PauseReason::Breakpoint);
+        let names: Vec<_> = log[1].1.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["add", "<script>"]);
+        assert_eq!(log[1].1[1].line, Some(5));
+    }
+
+    #[test]
+    fn steps_into_over_and_out() {
+        let mut steps = [
+            Resume::StepOver,
+            Resume::StepInto,
+            Resume::StepInto,
+            Resume::StepOut,
+            Resume::StepOver,
+            Resume::Continue,
+        ]
+        .into_iter();
+        let (log, out) = debug(PROGRAM, move |_, _| steps.next().unwrap());
+        assert_eq!(out, "3\n6\n");
+        // Out of `add` lands back on the call, mid-line.
+        assert_eq!(lines(&log), [1, 5, 2, 3, 5, 6]);
+        assert_eq!(log[2].1.len(), 2);
+        assert_eq!(log[4].1.len(), 1);
+    }
+
+    #[test]
+    fn stepping_over_still_stops_at_breakpoints_in_callees() {
+        let (log, _) = debug(PROGRAM, |session, reason| {
+            if reason == PauseReason::Entry {
+                session.set_breakpoint(2);
+            }
+            Resume::StepOver
+        });
+        // Stepping over `return` lands back in the caller, mid-line.
+        assert_eq!(lines(&log), [1, 5, 2, 3, 5, 6, 7, 2, 3, 7]);
+    }
+
+    #[test]
+    fn loops_reenter_their_line() {
+        let source = "var i = 0;\nwhile (i < 3) i = i + 1;\nprint i;\n";
+        let (log, _) = debug(source, |session, reason| {
+            if reason == PauseReason::Entry {
+                session.set_breakpoint(2);
+            }
+            Resume::Continue
+        });
+        assert_eq!(lines(&log), [1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn inspects_locals_and_globals() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&seen);
+        debug(PROGRAM, move |session, reason| {
+            if reason == PauseReason::Entry {
+                session.set_breakpoint(3);
+                return Resume::Continue;
+            }
+            sink.borrow_mut()
+                .push((session.locals(0), session.globals()));
+            Resume::Stop
+        });
+        let seen = seen.take();
+        let (locals, globals) = &seen[0];
+        let locals: Vec<_> = locals
+            .iter()
+            .map(|v| format!("{}={}", v.name, v.value))
+            .collect();
+        assert_eq!(locals, ["a=1", "b=2", "sum=3"]);
+        let globals: Vec<_> = globals.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(globals, ["add", "clock"]);
+    }
+
+    #[test]
+    fn evaluates_in_the_paused_frame() {
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&results);
+        let (_, out) = debug(PROGRAM, move |session, reason| {
+            if reason == PauseReason::Entry {
+                session.set_breakpoint(3);
+                return Resume::Continue;
+            }
+            let mut results = sink.borrow_mut();
+            if results.is_empty() {
+                results.push(session.evaluate(0, "sum * 10 + a"));
+                results.push(session.evaluate(1, "add(10, 20)"));
+                results.push(session.evaluate(0, "sum = -1"));
+                results.push(session.evaluate(0, "nope"));
+                results.push(session.evaluate(0, "sum +"));
+            }
+            Resume::Continue
+        });
+        // Assigning through the evaluation changed what `add` returned.
+        assert_eq!(out, "-1\n2\n");
+        let results = results.take();
+        assert_eq!(results[0], Ok("31".to_owned()));
+        assert_eq!(results[1], Ok("30".to_owned()));
+        assert_eq!(results[2], Ok("-1".to_owned()));
+        assert_eq!(results[3], Err("Undefined variable.".to_owned()));
+        assert!(results[4].is_err());
+    }
+
+    #[test]
+    fn stop_abandons_the_program() {
+        let (log, out) = debug(PROGRAM, |_, _| Resume::Stop);
+        assert_eq!(log.len(), 1);
+        assert_eq!(out, "");
+    }
+}
@@ -0,0 +1,679 @@
+//! A Debug Adapter Protocol server, so editors can drive the [`Debugger`].
+//!
+//! One client, one program, one thread. Requests are only read while the
+//! program hasn't started, is paused or has ended, so e.g. breakpoints set
+//! while it runs take effect from its next pause on. Scripts are single files:
+//! breakpoints apply to the launched program whatever source they name.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use report::Error;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::vm::VirtualMachine;
+use crate::vm::debugger::{DebugFrontend, Debugger, PauseReason, Resume, Session};
+
+/// The only thread a Lox program has.
+const THREAD_ID: u64 = 1;
+/// `variablesReference` of the globals. Frame `n`'s locals are `LOCALS + n`.
+const GLOBALS: usize = 1;
+const LOCALS: usize = 2;
+
+/// Serve one debugging session: configure, run the launched program under the
+/// debugger, then answer requests until the client disconnects.
+pub fn serve(input: impl BufRead + 'static, output: impl Write + 'static) -> io::Result<()> {
+    let adapter = Rc::new(RefCell::new(Adapter::new(input, output)));
+    let source = loop {
+        let mut adapter = adapter.borrow_mut();
+        let Some(request) = adapter.conn.read()? else {
+            return Ok(());
+        };
+        adapter.handle(&request, None)?;
+        if adapter.quit {
+            return Ok(());
+        }
+        if adapter.configured
+            && let Some(source) = adapter.source.take()
+        {
+            break source;
+        }
+    };
+
+    let mut out = Output::new(&adapter, "stdout");
+    let mut err = Output::new(&adapter, "stderr");
+    let frontend = Frontend(Rc::clone(&adapter));
+    let mut vm = VirtualMachine::default().with_debugger(Debugger::new(frontend));
+    let result = crate::run_with(source, &mut vm, &mut out, &mut err);
+    out.flush()?;
+    err.flush()?;
+
+    let mut adapter = adapter.borrow_mut();
+    if !adapter.quit {
+        let exit_code = exit_code(&result);
+        adapter
+            .conn
+            .event("exited", json!({ "exitCode": exit_code }))?;
+        adapter.conn.event("terminated", Value::Null)?;
+    }
+    while !adapter.quit {
+        let Some(request) = adapter.conn.read()? else {
+            break;
+        };
+        adapter.handle(&request, None)?;
+    }
+    Ok(())
+}
+
+/// What `rlox` would exit with.
+fn exit_code(result: &Result<(), Error>) -> u8 {
+    match result {
+        Ok(()) => 0,
+        Err(Error::Lexing(_) | Error::Parsing(_) | Error::Pass(_)) => 65,
+        Err(Error::Runtime(_) | Error::Halt(_)) => 70,
+        Err(Error::Other(_)) => 1,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    seq: u64,
+    command: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Reads and writes `Content-Length` framed messages.
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: u64,
+}
+
+impl Connection {
+    /// The next request, or `None` once the client hangs up.
+    fn read(&mut self) -> io::Result<Option<Request>> {
+        read_message(&mut self.input)
+    }
+
+    fn respond(&mut self, request: &Request, reply: Result<Value, String>) -> io::Result<()> {
+        let mut message = json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": reply.is_ok(),
+        });
+        match reply {
+            Ok(Value::Null) => {}
+            Ok(body) => message["body"] = body,
+            Err(error) => message["message"] = error.into(),
+        }
+        self.send(message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let body = serde_json::to_vec(&message)?;
+        write!(self.output, "Content-Length: {}\r\n\r\n", body.len())?;
+        self.output.write_all(&body)?;
+        self.output.flush()
+    }
+}
+
+fn read_message<T: DeserializeOwned>(input: &mut dyn BufRead) -> io::Result<Option<T>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && len.is_some() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            let value = value.trim().parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "bad Content-Length header")
+            })?;
+            len = Some(value);
+        }
+    }
+    let mut body = vec![0; len.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArgs {
+    program: PathBuf,
+    #[serde(default)]
+    stop_on_entry: bool,
+}
+
+#[derive(Deserialize)]
+struct SetBreakpointsArgs {
+    #[serde(default)]
+    breakpoints: Vec<SourceBreakpoint>,
+}
+
+#[derive(Deserialize)]
+struct SourceBreakpoint {
+    line: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StackTraceArgs {
+    #[serde(default)]
+    start_frame: usize,
+    /// All frames if zero.
+    #[serde(default)]
+    levels: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopesArgs {
+    frame_id: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariablesArgs {
+    variables_reference: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EvaluateArgs {
+    expression: String,
+    frame_id: Option<usize>,
+}
+
+fn args<T: DeserializeOwned>(arguments: &Value) -> Result<T, String> {
+    T::deserialize(arguments).map_err(|err| format!("Bad arguments: {err}."))
+}
+
+fn paused<'a, 's>(session: Option<&'a mut Session<'s>>) -> Result<&'a mut Session<'s>, String> {
+    session.ok_or_else(|| "The program is not paused.".to_owned())
+}
+
+/// Frame ids start at 1, since clients tend to treat 0 as "no frame".
+fn frame(session: &Session<'_>, id: usize) -> Result<usize, String> {
+    id.checked_sub(1)
+        .filter(|&frame| frame < session.frame_count())
+        .ok_or_else(|| format!("No frame {id}."))
+}
+
+struct Adapter {
+    conn: Connection,
+    program: Option<PathBuf>,
+    /// Taken when the program starts.
+    source: Option<String>,
+    stop_on_entry: bool,
+    configured: bool,
+    /// The client's breakpoints, handed to the debugger whenever it pauses.
+    breakpoints: BTreeSet<u32>,
+    quit: bool,
+}
+
+impl Adapter {
+    fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            conn: Connection {
+                input: Box::new(input),
+                output: Box::new(output),
+                seq: 0,
+            },
+            program: None,
+            source: None,
+            stop_on_entry: false,
+            configured: false,
+            breakpoints: BTreeSet::new(),
+            quit: false,
+        }
+    }
+
+    fn paused(&mut self, session: &mut Session<'_>, reason: PauseReason) -> io::Result<Resume> {
+        self.sync_breakpoints(session);
+        if reason == PauseReason::Entry && !self.stop_on_entry {
+            return Ok(Resume::Continue);
+        }
+        let body = json!({
+            "reason": reason.to_string(),
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        self.conn.event("stopped", body)?;
+        loop {
+            let Some(request) = self.conn.read()? else {
+                self.quit = true;
+                return Ok(Resume::Stop);
+            };
+            if let Some(resume) = self.handle(&request, Some(session))? {
+                return Ok(resume);
+            }
+        }
+    }
+
+    fn sync_breakpoints(&self, session: &mut Session<'_>) {
+        let stale: Vec<_> = session
+            .breakpoints()
+            .difference(&self.breakpoints)
+            .copied()
+            .collect();
+        for line in stale {
+            session.clear_breakpoint(line);
+        }
+        for &line in &self.breakpoints {
+            session.set_breakpoint(line);
+        }
+    }
+
+    /// Answer `request`. Returns how to resume if it resumes the program.
+    fn handle(
+        &mut self,
+        request: &Request,
+        mut session: Option<&mut Session<'_>>,
+    ) -> io::Result<Option<Resume>> {
+        let arguments = &request.arguments;
+        let mut resume = None;
+        let mut resume_with = |how| -> Result<Value, String> {
+            resume = Some(how);
+            Ok(Value::Null)
+        };
+        let reply = match request.command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsEvaluateForHovers": true,
+            })),
+            "launch" => self.launch(arguments),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(Value::Null)
+            }
+            "setBreakpoints" => args(arguments).map(|args: SetBreakpointsArgs| {
+                self.breakpoints = args.breakpoints.iter().map(|bp| bp.line).collect();
+                if let Some(session) = session.as_deref_mut() {
+                    self.sync_breakpoints(session);
+                }
+                let breakpoints: Vec<_> = args
+                    .breakpoints
+                    .iter()
+                    .map(|bp| json!({ "verified": true, "line": bp.line }))
+                    .collect();
+                json!({ "breakpoints": breakpoints })
+            }),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => paused(session).and_then(|session| self.stack_trace(session, arguments)),
+            "scopes" => paused(session).and_then(|session| {
+                let args: ScopesArgs = args(arguments)?;
+                let frame = frame(session, args.frame_id)?;
+                Ok(json!({ "scopes": [
+                    { "name": "Locals", "variablesReference": LOCALS + frame, "expensive": false },
+                    { "name": "Globals", "variablesReference": GLOBALS, "expensive": false },
+                ]}))
+            }),
+            "variables" => paused(session).and_then(|session| {
+                let args: VariablesArgs = args(arguments)?;
+                let variables = match args.variables_reference {
+                    GLOBALS => session.globals(),
+                    reference => reference
+                        .checked_sub(LOCALS)
+                        .filter(|&frame| frame < session.frame_count())
+                        .map(|frame| session.locals(frame))
+                        .ok_or_else(|| format!("No variables with reference {reference}."))?,
+                };
+                let variables: Vec<_> = variables
+                    .into_iter()
+                    .map(|var| json!({ "name": var.name, "value": var.value, "variablesReference": 0 }))
+                    .collect();
+                Ok(json!({ "variables": variables }))
+            }),
+            "evaluate" => paused(session).and_then(|session| {
+                let args: EvaluateArgs = args(arguments)?;
+                let frame = match args.frame_id {
+                    Some(id) => frame(session, id)?,
+                    None => 0,
+                };
+                let result = session.evaluate(frame, &args.expression)?;
+                Ok(json!({ "result": result, "variablesReference": 0 }))
+            }),
+            "continue" => paused(session).and_then(|_| {
+                resume_with(Resume::Continue)?;
+                Ok(json!({ "allThreadsContinued": true }))
+            }),
+            "next" => paused(session).and_then(|_| resume_with(Resume::StepOver)),
+            "stepIn" => paused(session).and_then(|_| resume_with(Resume::StepInto)),
+            "stepOut" => paused(session).and_then(|_| resume_with(Resume::StepOut)),
+            "disconnect" | "terminate" => {
+                self.quit = true;
+                resume_with(Resume::Stop)
+            }
+            command => Err(format!("Unsupported request `{command}`.")),
+        };
+        self.conn.respond(request, reply)?;
+        if request.command == "initialize" {
+            self.conn.event("initialized", Value::Null)?;
+        }
+        Ok(resume)
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let args: LaunchArgs = args(arguments)?;
+        let source = fs::read_to_string(&args.program)
+            .map_err(|err| format!("Could not read {}: {err}.", args.program.display()))?;
+        self.program = Some(args.program);
+        self.source = Some(source);
+        self.stop_on_entry = args.stop_on_entry;
+        Ok(Value::Null)
+    }
+
+    fn stack_trace(&self, session: &Session<'_>, arguments: &Value) -> Result<Value, String> {
+        let args: StackTraceArgs = args(arguments)?;
+        let frames = session.frames();
+        let levels = match args.levels {
+            0 => frames.len(),
+            levels => levels,
+        };
+        let source = self.program.as_ref().map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            json!({ "name": name, "path": path })
+        });
+        let stack_frames: Vec<_> = frames
+            .iter()
+            .enumerate()
+            .skip(args.start_frame)
+            .take(levels)
+            .map(|(i, frame)| {
+                json!({
+                    "id": i + 1,
+                    "name": frame.name,
+                    "source": source,
+                    "line": frame.line.unwrap_or(0),
+                    "column": 1,
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": stack_frames, "totalFrames": frames.len() }))
+    }
+}
+
+struct Frontend(Rc<RefCell<Adapter>>);
+
+impl DebugFrontend for Frontend {
+    fn paused(&mut self, session: &mut Session<'_>, reason: PauseReason) -> Resume {
+        // Without a client there is nobody to resume the program.
+        self.0
+            .borrow_mut()
+            .paused(session, reason)
+            .unwrap_or(Resume::Stop)
+    }
+}
+
+/// Forwards the program's output to the client a line at a time.
+struct Output {
+    adapter: Rc<RefCell<Adapter>>,
+    category: &'static str,
+    buf: Vec<u8>,
+}
+
+impl Output {
+    fn new(adapter: &Rc<RefCell<Adapter>>, category: &'static str) -> Self {
+        Self {
+            adapter: Rc::clone(adapter),
+            category,
+            buf: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let text: Vec<_> = self.buf.drain(..len).collect();
+        let body = json!({
+            "category": self.category,
+            "output": String::from_utf8_lossy(&text),
+        });
+        self.adapter.borrow_mut().conn.event("output", body)
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(bytes);
+        if let Some(end) = self.buf.iter().rposition(|&b| b == b'\n') {
+            self.send(end + 1)?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send(self.buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Output shared with the test after the adapter takes it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Write `source` to a scratch file named after the test.
+    fn program(name: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rlox-dap-{}-{name}.lox", std::process::id()));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    /// Play `requests` to a server as a client that knows in advance what it
+    /// wants, and collect everything the server sent back.
+    fn session(requests: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for (seq, request) in requests.iter().enumerate() {
+            let mut request = request.clone();
+            request["seq"] = (seq + 1).into();
+            request["type"] = "request".into();
+            let body = serde_json::to_vec(&request).unwrap();
+            write!(input, "Content-Length: {}\r\n\r\n", body.len()).unwrap();
+            input.extend(body);
+        }
+        let output = Shared::default();
+        serve(Cursor::new(input), output.clone()).unwrap();
+
+        let output = output.0.take();
+        let mut output = output.as_slice();
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn request(command: &str, arguments: Value) -> Value {
+        json!({ "command": command, "arguments": arguments })
+    }
+
+    fn response<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        messages
+            .iter()
+            .find(|msg| msg["type"] == "response" && msg["command"] == command)
+            .unwrap_or_else(|| panic!("no response to `{command}`"))
+    }
+
+    /// Events in order, as `event` or `event:detail`.
+    fn events(messages: &[Value]) -> Vec<String> {
+        messages
+            .iter()
+            .filter(|msg| msg["type"] == "event")
+            .map(|msg| {
+                let body = &msg["body"];
+                let detail = match msg["event"].as_str().unwrap() {
+                    "stopped" => body["reason"].clone(),
+                    "output" => body["output"].clone(),
+                    "exited" => body["exitCode"].clone(),
+                    _ => Value::Null,
+                };
+                match detail {
+                    Value::Null => msg["event"].as_str().unwrap().to_owned(),
+                    Value::String(detail) => format!("{}:{detail}", msg["event"].as_str().unwrap()),
+                    detail => format!("{}:{detail}", msg["event"].as_str().unwrap()),
+                }
+            })
+            .collect()
+    }
+
+    const PROGRAM: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+print add(x, 3);
+";
+
+    #[test]
+    fn debugs_a_launched_program() {
+        let path = program("launch", PROGRAM);
+        let messages = session(&[
+            request("initialize", json!({ "adapterID": "lox" })),
+            request("launch", json!({ "program": path })),
+            request(
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] }),
+            ),
+            request("configurationDone", Value::Null),
+            request("threads", Value::Null),
+            request("stackTrace", json!({ "threadId": 1 })),
+            request("scopes", json!({ "frameId": 1 })),
+            request("variables", json!({ "variablesReference": LOCALS })),
+            request("evaluate", json!({ "expression": "a + b", "frameId": 1 })),
+            request("next", json!({ "threadId": 1 })),
+            request("continue", json!({ "threadId": 1 })),
+            request(
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [] }),
+            ),
+            request("continue", json!({ "threadId": 1 })),
+            request("disconnect", Value::Null),
+        ]);
+        fs::remove_file(path).unwrap();
+
+        assert!(
+            messages
+                .iter()
+                .all(|msg| msg["type"] != "response" || msg["success"] == true)
+        );
+        assert_eq!(
+            events(&messages),
+            [
+                "initialized",
+                "stopped:breakpoint",
+                "stopped:step",
+                "output:3\n",
+                "stopped:breakpoint",
+                "output:6\n",
+                "exited:0",
+                "terminated",
+            ]
+        );
+
+        let frames = &response(&messages, "stackTrace")["body"]["stackFrames"];
+        let frames: Vec<_> = frames
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| {
+                (
+                    frame["name"].as_str().unwrap(),
+                    frame["line"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(frames, [("add", 3), ("<script>", 5)]);
+
+        let variables = &response(&messages, "variables")["body"]["variables"];
+        let variables: Vec<_> = variables
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|var| {
+                format!(
+                    "{}={}",
+                    var["name"].as_str().unwrap(),
+                    var["value"].as_str().unwrap()
+                )
+            })
+            .collect();
+        assert_eq!(variables, ["a=1", "b=2", "sum=3"]);
+        assert_eq!(response(&messages, "evaluate")["body"]["result"], "3");
+    }
+
+    #[test]
+    fn reports_failures() {
+        let path = program("failures", "print 1;\nprint nil + 1;\n");
+        let messages = session(&[
+            request("initialize", Value::Null),
+            request("stackTrace", json!({ "threadId": 1 })),
+            request(
+                "launch",
+                json!({ "program": path.with_extension("missing") }),
+            ),
+            request("launch", json!({ "program": path, "stopOnEntry": true })),
+            request("configurationDone", Value::Null),
+            request("scopes", json!({ "frameId": 7 })),
+            request("goto", json!({ "threadId": 1 })),
+            request("continue", json!({ "threadId": 1 })),
+            request("disconnect", Value::Null),
+        ]);
+        fs::remove_file(path).unwrap();
+
+        let failure = |command| {
+            let response = response(&messages, command);
+            assert_eq!(response["success"], false);
+            response["message"].as_str().unwrap().to_owned()
+        };
+        assert_eq!(failure("stackTrace"), "The program is not paused.");
+        assert!(failure("launch").starts_with("Could not read"));
+        assert_eq!(failure("scopes"), "No frame 7.");
+        assert_eq!(failure("goto"), "Unsupported request `goto`.");
+
+        let events = events(&messages);
+        assert_eq!(events[..3], ["initialized", "stopped:entry", "output:1\n"]);
+        assert!(events[3].starts_with("output:") && events[3].contains("invalid operand"));
+        assert_eq!(events[4], "output:[line 2] in script\n");
+        assert_eq!(events[5..], ["exited:70", "terminated"]);
+    }
+}
@@ -0,0 +1,244 @@
+//! A line-oriented command prompt for the [`Debugger`](super::Debugger).
+
+use std::io::{self, BufRead, Write};
+
+use crate::vm::debugger::{DebugFrontend, PauseReason, Resume, Session};
+
+const HELP: &str = "\
+break LINE    (b)   set a breakpoint, or list them without LINE
+delete LINE   (d)   remove a breakpoint
+continue      (c)   run to the next breakpoint
+step          (s)   run to the next line, stepping into calls
+next          (n)   run to the next line, stepping over calls
+finish        (f)   run until the current function returns
+backtrace     (bt)  show the call stack
+frame N             select the frame `locals` and `print` look at
+locals        (l)   show the selected frame's locals
+globals       (g)   show the globals
+print EXPR    (p)   evaluate an expression in the selected frame
+quit          (q)   stop the program";
+
+/// Reads commands from `input` and answers on `output`. Running out of input
+/// lets the program run to completion.
+pub struct Prompt<R, W> {
+    source: String,
+    input: R,
+    output: W,
+    /// Back to the innermost frame on every pause.
+    frame: usize,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// `source` is only used to show the line execution paused at.
+    pub fn new(source: impl Into<String>, input: R, output: W) -> Self {
+        Self {
+            source: source.into(),
+            input,
+            output,
+            frame: 0,
+        }
+    }
+
+    fn interact(&mut self, session: &mut Session<'_>, reason: PauseReason) -> io::Result<Resume> {
+        self.frame = 0;
+        self.show_position(session, reason)?;
+        loop {
+            write!(self.output, "(lox) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                return Ok(Resume::Continue);
+            }
+            if let Some(resume) = self.command(session, line.trim())? {
+                return Ok(resume);
+            }
+        }
+    }
+
+    fn show_position(&mut self, session: &Session<'_>, reason: PauseReason) -> io::Result<()> {
+        let Some(line) = session.frames()[0].line else {
+            return Ok(());
+        };
+        let what = match reason {
+            PauseReason::Entry => "Paused on entry",
+            PauseReason::Breakpoint => "Hit breakpoint",
+            PauseReason::Step => "Stepped",
+        };
+        writeln!(self.output, "{what} at line {line}")?;
+        if let Some(text) = self.source.lines().nth(line as usize - 1) {
+            writeln!(self.output, "{line:>4} | {text}")?;
+        }
+        Ok(())
+    }
+
+    /// Run one command. Returns how to resume if the command resumes.
+    fn command(&mut self, session: &mut Session<'_>, line: &str) -> io::Result<Option<Resume>> {
+        let (cmd, arg) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(cmd, arg)| (cmd, arg.trim()));
+        let out = &mut self.output;
+        match cmd {
+            "" => {}
+            "c" | "continue" => return Ok(Some(Resume::Continue)),
+            "s" | "step" => return Ok(Some(Resume::StepInto)),
+            "n" | "next" => return Ok(Some(Resume::StepOver)),
+            "f" | "finish" => return Ok(Some(Resume::StepOut)),
+            "q" | "quit" => return Ok(Some(Resume::Stop)),
+            "b" | "break" if arg.is_empty() => match session.breakpoints() {
+                lines if lines.is_empty() => writeln!(out, "No breakpoints.")?,
+                lines => {
+                    let lines: Vec<_> = lines.iter().map(u32::to_string).collect();
+                    writeln!(out, "Breakpoints at lines {}.", lines.join(", "))?
+                }
+            },
+            "b" | "break" => match arg.parse() {
+                Ok(line) if session.set_breakpoint(line) => {
+                    writeln!(out, "Breakpoint set at line {line}.")?
+                }
+                Ok(line) => writeln!(out, "Already a breakpoint at line {line}.")?,
+                Err(_) => writeln!(out, "Expected a line number.")?,
+            },
+            "d" | "delete" => match arg.parse() {
+                Ok(line) if session.clear_breakpoint(line) => {
+                    writeln!(out, "Breakpoint removed from line {line}.")?
+                }
+                Ok(line) => writeln!(out, "No breakpoint at line {line}.")?,
+                Err(_) => writeln!(out, "Expected a line number.")?,
+            },
+            "bt" | "backtrace" => {
+                for (i, frame) in session.frames().iter().enumerate() {
+                    let marker = if i == self.frame { '>' } else { ' ' };
+                    match frame.line {
+                        Some(line) => writeln!(out, "{marker} #{i} {} at line {line}", frame.name)?,
+                        None => writeln!(out, "{marker} #{i} {}", frame.name)?,
+                    }
+                }
+            }
+            "frame" => match arg.parse() {
+                Ok(frame) if frame < session.frame_count() => {
+                    self.frame = frame;
+                    writeln!(out, "Selected frame #{frame}.")?
+                }
+                _ => writeln!(out, "Expected a frame number from `backtrace`.")?,
+            },
+            "l" | "locals" => {
+                let locals = session.locals(self.frame);
+                if locals.is_empty() {
+                    writeln!(out, "No locals.")?;
+                }
+                for var in locals {
+                    writeln!(out, "{} = {}", var.name, var.value)?;
+                }
+            }
+            "g" | "globals" => {
+                for var in session.globals() {
+                    writeln!(out, "{} = {}", var.name, var.value)?;
+                }
+            }
+            "p" | "print" if arg.is_empty() => writeln!(out, "Expected an expression.")?,
+            "p" | "print" => match session.evaluate(self.frame, arg) {
+                Ok(value) => writeln!(out, "{value}")?,
+                Err(err) => writeln!(out, "{err}")?,
+            },
+            "h" | "help" => writeln!(out, "{HELP}")?,
+            _ => writeln!(out, "Unknown command `{cmd}`. Try `help`.")?,
+        }
+        Ok(None)
+    }
+}
+
+impl<R: BufRead, W: Write> DebugFrontend for Prompt<R, W> {
+    fn paused(&mut self, session: &mut Session<'_>, reason: PauseReason) -> Resume {
+        // Without a working terminal there is nobody to ask, so just run on.
+        self.interact(session, reason).unwrap_or(Resume::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::vm::{VirtualMachine, debugger::Debugger};
+
+    /// Output shared with the test after the prompt moves into the VM.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run `source` under a prompt fed `commands`. Returns the transcript and
+    /// the program's output.
+    fn session(source: &str, commands: &str) -> (String, String) {
+        let transcript = Shared::default();
+        let prompt = Prompt::new(source, Cursor::new(commands.to_owned()), transcript.clone());
+        let mut vm = VirtualMachine::default().with_debugger(Debugger::new(prompt));
+        let mut out = Vec::new();
+        crate::run_with(source.to_owned(), &mut vm, &mut out, &mut io::sink()).unwrap();
+        let transcript = String::from_utf8(transcript.0.take()).unwrap();
+        (transcript, String::from_utf8(out).unwrap())
+    }
+
+    const PROGRAM: &str = "\
+fun greet(name) {
+  var greeting = \"hi \" + name;
+  return greeting;
+}
+print greet(\"bob\");
+";
+
+    #[test]
+    fn drives_a_session() {
+        let commands = "\
+break 3
+b
+continue
+bt
+locals
+print greeting + \"!\"
+frame 1
+locals
+next
+quit
+";
+        let (transcript, out) = session(PROGRAM, commands);
+        let expected = "\
+Paused on entry at line 1
+   1 | fun greet(name) {
+(lox) Breakpoint set at line 3.
+(lox) Breakpoints at lines 3.
+(lox) Hit breakpoint at line 3
+   3 |   return greeting;
+(lox) > #0 greet at line 3
+  #1 <script> at line 5
+(lox) name = bob
+greeting = hi bob
+(lox) hi bob!
+(lox) Selected frame #1.
+(lox) No locals.
+(lox) Stepped at line 5
+   5 | print greet(\"bob\");
+(lox) ";
+        assert_eq!(transcript, expected);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn runs_on_when_input_ends() {
+        let (transcript, out) = session(PROGRAM, "bogus\nprint\n");
+        assert!(transcript.contains("Unknown command `bogus`. Try `help`."));
+        assert!(transcript.contains("Expected an expression."));
+        assert_eq!(out, "hi bob\n");
+    }
+}
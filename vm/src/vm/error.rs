@@ -1,4 +1,4 @@
-use report::error::RuntimeError;
+use report::error::{HaltError, RuntimeError};
 use thiserror::Error;
 
 use crate::enconding::DecodeError;
@@ -10,5 +10,9 @@ pub enum VirtualMachineError {
     #[error(transparent)]
     Runtime(#[from] RuntimeError),
     #[error(transparent)]
+    Halt(#[from] HaltError),
+    #[error(transparent)]
     Other(#[from] anyhow::Error),
+    #[error("execution stopped by the debugger")]
+    Stopped,
 }
@@ -2,7 +2,11 @@ use std::io::Cursor;
 
 use intrusive_collections::UnsafeRef;
 
-use crate::{chunk::Chunk, object::function::LoxFunction};
+use crate::{
+    chunk::Chunk,
+    object::closure::LoxClosure,
+    storage::gc::{Trace, Tracer},
+};
 
 pub struct CallFrame {
     pub pc: Cursor<FrameSource>,
@@ -11,14 +15,23 @@ pub struct CallFrame {
 
 pub enum FrameSource {
     TopLevel(Chunk),
-    Function(UnsafeRef<LoxFunction>),
+    Closure(UnsafeRef<LoxClosure>),
 }
 
 impl AsRef<[u8]> for FrameSource {
     fn as_ref(&self) -> &[u8] {
         match self {
             FrameSource::TopLevel(chunk) => chunk.as_ref(),
-            FrameSource::Function(func) => func.chunk.as_ref(),
+            FrameSource::Closure(closure) => closure.function.chunk.as_ref(),
+        }
+    }
+}
+
+impl Trace for CallFrame {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self.pc.get_ref() {
+            FrameSource::TopLevel(chunk) => chunk.trace(tracer),
+            FrameSource::Closure(closure) => tracer.mark(closure),
         }
     }
 }
@@ -35,14 +48,22 @@ impl CallFrame {
         Self::new(FrameSource::TopLevel(chunk), stack_start)
     }
 
-    pub fn function(func: UnsafeRef<LoxFunction>, stack_start: usize) -> Self {
-        Self::new(FrameSource::Function(func), stack_start)
+    pub fn closure(closure: UnsafeRef<LoxClosure>, stack_start: usize) -> Self {
+        Self::new(FrameSource::Closure(closure), stack_start)
     }
 
     pub fn chunk(&self) -> &Chunk {
         match self.pc.get_ref() {
             FrameSource::TopLevel(chunk) => chunk,
-            FrameSource::Function(func) => &func.chunk,
+            FrameSource::Closure(closure) => &closure.function.chunk,
+        }
+    }
+
+    /// The closure being executed, or `None` for top-level code.
+    pub fn callee(&self) -> Option<&UnsafeRef<LoxClosure>> {
+        match self.pc.get_ref() {
+            FrameSource::TopLevel(_) => None,
+            FrameSource::Closure(closure) => Some(closure),
         }
     }
 }
@@ -0,0 +1,337 @@
+//! Opcode, function and line profiling for the VM.
+//!
+//! A [`Profiler`] sees every instruction the VM runs along with the call
+//! stack it runs in. Calls are noticed by the stack growing or shrinking
+//! between two instructions, so natives count towards their caller.
+
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use lasso::Spur;
+use rustc_hash::FxHashMap;
+
+use crate::enconding::OpCode;
+use crate::storage::Storage;
+use crate::vm::frame::CallFrame;
+
+/// A Lox function as the profiler tells them apart: by name and the line
+/// its code starts on. `name` is `None` for top-level code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FnId {
+    name: Option<Spur>,
+    line: Option<u32>,
+}
+
+impl FnId {
+    fn of(frame: &CallFrame) -> Self {
+        Self {
+            name: frame.callee().map(|closure| closure.function.name),
+            line: frame.chunk().get_line(0).map(|info| info.line),
+        }
+    }
+
+    fn label(&self, storage: &Storage) -> String {
+        match (self.name, self.line) {
+            (None, _) => "<script>".to_owned(),
+            (Some(name), Some(line)) => format!("{} (line {line})", storage.resolve(name)),
+            (Some(name), None) => storage.resolve(name).to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct FnStats {
+    calls: u64,
+    /// Time spent in the function and its callees. Recursive calls are only
+    /// counted once, at the outermost call.
+    total: Duration,
+    /// Time spent in the function itself.
+    own: Duration,
+    instructions: u64,
+}
+
+/// A call the profiled program is in.
+struct Active {
+    id: FnId,
+    started: Instant,
+    /// Time spent in callees so far.
+    children: Duration,
+    instructions: u64,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    started: Option<Instant>,
+    instructions: u64,
+    ops: FxHashMap<&'static str, u64>,
+    functions: FxHashMap<FnId, FnStats>,
+    lines: FxHashMap<u32, u64>,
+    /// Instructions run in each distinct call stack, outermost call first.
+    stacks: FxHashMap<Vec<FnId>, u64>,
+    calls: Vec<Active>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `op`, about to run at `offset` in the innermost of `frames`.
+    pub(super) fn record(&mut self, frames: &[CallFrame], offset: u64, op: OpCode) {
+        let now = Instant::now();
+        self.started.get_or_insert(now);
+        self.sync(frames, now);
+
+        self.instructions += 1;
+        *self.ops.entry(op.mnemonic()).or_default() += 1;
+        if let Some(call) = self.calls.last_mut() {
+            call.instructions += 1;
+        }
+        let frame = frames.last().expect("always has top level call frame");
+        if let Some(info) = frame.chunk().get_line(offset) {
+            *self.lines.entry(info.line).or_default() += 1;
+        }
+    }
+
+    /// Bring the calls in progress in line with `frames`. At most one call
+    /// starts or returns between two instructions, but a failed run drops
+    /// every frame at once.
+    fn sync(&mut self, frames: &[CallFrame], now: Instant) {
+        while self.calls.len() > frames.len() {
+            self.leave(now);
+        }
+        while let Some(frame) = frames.get(self.calls.len()) {
+            let id = FnId::of(frame);
+            self.functions.entry(id).or_default().calls += 1;
+            self.calls.push(Active {
+                id,
+                started: now,
+                children: Duration::ZERO,
+                instructions: 0,
+            });
+        }
+    }
+
+    fn leave(&mut self, now: Instant) {
+        let Some(call) = self.calls.pop() else {
+            return;
+        };
+        let elapsed = now - call.started;
+        let recursive = self.calls.iter().any(|outer| outer.id == call.id);
+        let stats = self.functions.entry(call.id).or_default();
+        if !recursive {
+            stats.total += elapsed;
+        }
+        stats.own += elapsed.saturating_sub(call.children);
+        stats.instructions += call.instructions;
+
+        if call.instructions > 0 {
+            let stack = self.calls.iter().map(|outer| outer.id).chain([call.id]);
+            *self.stacks.entry(stack.collect()).or_default() += call.instructions;
+        }
+        if let Some(caller) = self.calls.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+
+    /// End every call still in progress and sum everything up.
+    pub fn finish(mut self, storage: &Storage) -> Profile {
+        let now = Instant::now();
+        while !self.calls.is_empty() {
+            self.leave(now);
+        }
+
+        let mut ops: Vec<_> = self.ops.into_iter().collect();
+        ops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let mut functions: Vec<_> = self
+            .functions
+            .into_iter()
+            .map(|(id, stats)| FunctionProfile {
+                name: id.label(storage),
+                calls: stats.calls,
+                total: stats.total,
+                own: stats.own,
+                instructions: stats.instructions,
+            })
+            .collect();
+        functions.sort_by(|a, b| b.own.cmp(&a.own).then_with(|| a.name.cmp(&b.name)));
+        let mut lines: Vec<_> = self.lines.into_iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut stacks: Vec<_> = self
+            .stacks
+            .into_iter()
+            .map(|(stack, weight)| {
+                let names: Vec<_> = stack.iter().map(|id| id.label(storage)).collect();
+                (names.join(";"), weight)
+            })
+            .collect();
+        stacks.sort();
+
+        Profile {
+            elapsed: self.started.map_or(Duration::ZERO, |started| now - started),
+            instructions: self.instructions,
+            ops,
+            functions,
+            lines,
+            stacks,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub total: Duration,
+    pub own: Duration,
+    pub instructions: u64,
+}
+
+/// What a [`Profiler`] saw, hottest first.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub elapsed: Duration,
+    pub instructions: u64,
+    /// Executions per opcode mnemonic.
+    pub ops: Vec<(&'static str, u64)>,
+    /// By time spent in the function itself.
+    pub functions: Vec<FunctionProfile>,
+    /// Instructions run per source line.
+    pub lines: Vec<(u32, u64)>,
+    /// Instructions run per call stack, as `outer;inner` paths.
+    pub stacks: Vec<(String, u64)>,
+}
+
+impl Profile {
+    /// Lines of the report's hot line table.
+    const HOT_LINES: usize = 20;
+
+    /// Write the call stacks in the folded format flamegraph tools read,
+    /// weighted by instructions run.
+    pub fn write_folded(&self, out: &mut dyn Write) -> io::Result<()> {
+        for (stack, weight) in &self.stacks {
+            writeln!(out, "{stack} {weight}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let share = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+        writeln!(
+            f,
+            "== profile: {} instructions in {:.3?} ==",
+            self.instructions, self.elapsed
+        )?;
+
+        writeln!(f, "\n{:<24} {:>12} {:>7}", "opcode", "count", "%")?;
+        for (op, count) in &self.ops {
+            writeln!(f, "{op:<24} {count:>12} {:>7.2}", share(*count))?;
+        }
+
+        writeln!(
+            f,
+            "\n{:<24} {:>10} {:>12} {:>12} {:>12}",
+            "function", "calls", "total", "self", "instructions"
+        )?;
+        for func in &self.functions {
+            writeln!(
+                f,
+                "{:<24} {:>10} {:>12} {:>12} {:>12}",
+                func.name,
+                func.calls,
+                format!("{:.3?}", func.total),
+                format!("{:.3?}", func.own),
+                func.instructions
+            )?;
+        }
+
+        writeln!(f, "\n{:<24} {:>12} {:>7}", "line", "instructions", "%")?;
+        for (line, count) in self.lines.iter().take(Self::HOT_LINES) {
+            writeln!(f, "{line:<24} {count:>12} {:>7.2}", share(*count))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VirtualMachine;
+
+    fn profile(source: &str) -> Profile {
+        let mut vm = VirtualMachine::default().with_profiler(Profiler::new());
+        crate::run_with(source.to_owned(), &mut vm, &mut io::sink(), &mut io::sink()).unwrap();
+        vm.take_profile().unwrap()
+    }
+
+    const FIB: &str = "\
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+print fib(5);
+";
+
+    #[test]
+    fn counts_calls_and_instructions() {
+        let profile = profile(FIB);
+        let fib = profile
+            .functions
+            .iter()
+            .find(|func| func.name == "fib (line 2)")
+            .unwrap();
+        assert_eq!(fib.calls, 15);
+        let script = profile
+            .functions
+            .iter()
+            .find(|func| func.name == "<script>")
+            .unwrap();
+        assert_eq!(script.calls, 1);
+        assert!(script.total >= fib.total);
+
+        let counted: u64 = profile.ops.iter().map(|(_, count)| count).sum();
+        assert_eq!(counted, profile.instructions);
+        assert_eq!(fib.instructions + script.instructions, profile.instructions);
+        let calls = profile.ops.iter().find(|(op, _)| *op == "OP_CALL").unwrap();
+        assert_eq!(calls.1, 15);
+        assert_eq!(profile.lines.first().map(|&(line, _)| line), Some(2));
+    }
+
+    #[test]
+    fn folds_stacks_by_instructions() {
+        let profile = profile(FIB);
+        let stacks: Vec<_> = profile
+            .stacks
+            .iter()
+            .map(|(stack, _)| stack.as_str())
+            .collect();
+        let fib = "fib (line 2)";
+        assert_eq!(stacks[0], "<script>");
+        assert!(stacks.contains(&format!("<script>;{fib};{fib};{fib};{fib}").as_str()));
+        let weight: u64 = profile.stacks.iter().map(|(_, weight)| weight).sum();
+        assert_eq!(weight, profile.instructions);
+
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        assert!(folded.lines().all(|line| line.rsplit_once(' ').is_some()));
+    }
+
+    #[test]
+    fn failed_runs_still_add_up() {
+        let mut vm = VirtualMachine::default().with_profiler(Profiler::new());
+        let source = "fun f() { return nil + 1; }\nf();\n".to_owned();
+        assert!(crate::run_with(source, &mut vm, &mut io::sink(), &mut io::sink()).is_err());
+        let profile = vm.take_profile().unwrap();
+        let calls: Vec<_> = profile
+            .functions
+            .iter()
+            .map(|func| (func.name.as_str(), func.calls))
+            .collect();
+        assert!(calls.contains(&("f (line 1)", 1)));
+        assert!(profile.to_string().contains("OP_ADD"));
+    }
+}
@@ -38,6 +38,36 @@ impl Stack {
         self.inner.truncate(new_len);
     }
 
+    pub fn truncate(&mut self, len: usize) {
+        debug_assert!(
+            len <= self.inner.len(),
+            "compiler bug, truncating past the top"
+        );
+        self.inner.truncate(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// The topmost `n` values, oldest first.
+    pub fn top_n(&self, n: usize) -> &[Value] {
+        let start = self
+            .inner
+            .len()
+            .checked_sub(n)
+            .expect("compiler bug, peeking more values than stack holds");
+        &self.inner[start..]
+    }
+
     pub fn top(&self) -> &Value {
         self.inner
             .last()